@@ -0,0 +1,486 @@
+// The arith module evaluates shell arithmetic for `$(( ))`, `(( ))`, `let`
+// and integer variables. Expressions use 64-bit integers and the C operator
+// set with bash's precedence. They are parsed into a small tree first so that
+// `&&`, `||` and `?:` only evaluate (and assign) the side they need.
+use crate::shell::{Shell, Value};
+
+// variables may hold expressions themselves; this bounds how deep that goes
+const MAX_DEPTH: usize = 64;
+
+const OPERATORS: [&str; 37] = [
+    "**=", "<<=", ">>=", "**", "++", "--", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=", "<<",
+    ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&", "|", "^",
+    "!", "~", "?", ":", "=",
+];
+
+const ASSIGNMENTS: [&str; 12] = [
+    "=", "+=", "-=", "*=", "/=", "%=", "**=", "<<=", ">>=", "&=", "^=", "|=",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    // a variable, with the raw text of its subscript for `a[i]`
+    Name(String, Option<String>),
+    Operator(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+#[derive(Debug, Clone)]
+struct Place {
+    name: String,
+    subscript: Option<String>,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(i64),
+    Variable(Place),
+    Unary(&'static str, Box<Expr>),
+    // `++x`/`--x` when `prefix`, otherwise `x++`/`x--`
+    Step(Place, i64, bool),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Assign(Place, &'static str, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+}
+
+/// Evaluates an arithmetic expression. Errors name the expression, like
+/// `1/0: division by zero`.
+pub fn evaluate(expression: &str, shell: &mut Shell) -> Result<i64, String> {
+    evaluate_at(expression, shell, 0).map_err(|e| format!("{}: {}", expression.trim(), e))
+}
+
+fn evaluate_at(expression: &str, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err(String::from("expression recursion level exceeded"));
+    }
+    let tokens = tokenize(expression)?;
+    if tokens.is_empty() {
+        return Ok(0);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.comma()?;
+    if let Some(token) = parser.tokens.get(parser.pos) {
+        return Err(format!("syntax error near '{}'", describe(token)));
+    }
+    eval(&expr, shell, depth)
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Number(n) => n.to_string(),
+        Token::Name(name, _) => name.clone(),
+        Token::Operator(op) => op.to_string(),
+        Token::Open => String::from("("),
+        Token::Close => String::from(")"),
+        Token::Comma => String::from(","),
+    }
+}
+
+fn tokenize(expression: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_ascii_alphanumeric() || matches!(chars[i], '#' | '_' | '@'))
+            {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(parse_number(&text)?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let name: String = chars[start..i].iter().collect();
+            let mut subscript = None;
+            if chars.get(i) == Some(&'[') {
+                let mut depth = 0;
+                let open = i;
+                loop {
+                    match chars.get(i) {
+                        Some('[') => depth += 1,
+                        Some(']') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        Some(_) => {}
+                        None => return Err(format!("missing ']' after '{}'", name)),
+                    }
+                    i += 1;
+                }
+                subscript = Some(chars[open + 1..i].iter().collect());
+                i += 1;
+            }
+            tokens.push(Token::Name(name, subscript));
+        } else if c == '(' {
+            tokens.push(Token::Open);
+            i += 1;
+        } else if c == ')' {
+            tokens.push(Token::Close);
+            i += 1;
+        } else if c == ',' {
+            tokens.push(Token::Comma);
+            i += 1;
+        } else {
+            let rest: String = chars[i..].iter().take(3).collect();
+            let Some(&op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) else {
+                return Err(format!("syntax error near '{}'", c));
+            };
+            // `++`/`--` only step a variable next to them, so `5--3` is
+            // five minus minus three
+            let op = match op {
+                "++" | "--" => {
+                    let after_name = matches!(tokens.last(), Some(Token::Name(..)));
+                    let before_name = chars[i + 2..]
+                        .iter()
+                        .find(|c| !c.is_whitespace())
+                        .is_some_and(|c| c.is_ascii_alphabetic() || *c == '_');
+                    if after_name || before_name {
+                        op
+                    } else {
+                        &op[..1]
+                    }
+                }
+                _ => op,
+            };
+            tokens.push(Token::Operator(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// parses decimal, octal (`017`), hexadecimal (`0x1f`) and `base#digits`
+// numbers
+fn parse_number(text: &str) -> Result<i64, String> {
+    let (base, digits) = if let Some((base, digits)) = text.split_once('#') {
+        let base: u32 = base
+            .parse()
+            .ok()
+            .filter(|base| (2..=64).contains(base))
+            .ok_or_else(|| format!("{}: invalid arithmetic base", text))?;
+        (base, digits)
+    } else if let Some(digits) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        (16, digits)
+    } else if text.len() > 1 && text.starts_with('0') {
+        (8, &text[1..])
+    } else {
+        (10, text)
+    };
+    if digits.is_empty() {
+        return Err(format!("{}: invalid number", text));
+    }
+
+    let mut value: i64 = 0;
+    for c in digits.chars() {
+        // like bash, bases above 36 use lowercase, uppercase, `@` and `_`
+        let digit = match c {
+            '0'..='9' => c as u32 - '0' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 10,
+            'A'..='Z' if base <= 36 => c as u32 - 'A' as u32 + 10,
+            'A'..='Z' => c as u32 - 'A' as u32 + 36,
+            '@' => 62,
+            '_' => 63,
+            _ => u32::MAX,
+        };
+        if digit >= base {
+            return Err(format!("{}: value too great for base", text));
+        }
+        value = value.wrapping_mul(base as i64).wrapping_add(digit as i64);
+    }
+    Ok(value)
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        "**" => 11,
+        _ => return None,
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_operator(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Operator(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn unexpected(&self) -> String {
+        match self.peek() {
+            Some(token) => format!("syntax error near '{}'", describe(token)),
+            None => String::from("syntax error: operand expected"),
+        }
+    }
+
+    fn comma(&mut self) -> Result<Expr, String> {
+        let mut expr = self.assignment()?;
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            expr = Expr::Comma(Box::new(expr), Box::new(self.assignment()?));
+        }
+        Ok(expr)
+    }
+
+    fn assignment(&mut self) -> Result<Expr, String> {
+        if let (Some(Token::Name(name, subscript)), Some(Token::Operator(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if ASSIGNMENTS.contains(op) {
+                let place = Place {
+                    name: name.clone(),
+                    subscript: subscript.clone(),
+                };
+                let op = *op;
+                self.pos += 2;
+                let value = self.assignment()?;
+                return Ok(Expr::Assign(place, op, Box::new(value)));
+            }
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expr, String> {
+        let condition = self.binary(1)?;
+        if self.peek_operator() != Some("?") {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let then = self.assignment()?;
+        if self.peek_operator() != Some(":") {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        let otherwise = self.assignment()?;
+        Ok(Expr::Conditional(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    // precedence climbing over the binary operators; `**` groups to the right
+    fn binary(&mut self, min: u8) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while let Some(op) = self.peek_operator() {
+            let Some(prec) = precedence(op).filter(|prec| *prec >= min) else {
+                break;
+            };
+            self.pos += 1;
+            let right = if op == "**" {
+                self.binary(prec)?
+            } else {
+                self.binary(prec + 1)?
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        match self.peek_operator() {
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                let Some(Token::Name(name, subscript)) = self.peek().cloned() else {
+                    return Err(self.unexpected());
+                };
+                self.pos += 1;
+                let place = Place { name, subscript };
+                Ok(Expr::Step(place, if op == "++" { 1 } else { -1 }, true))
+            }
+            Some(op @ ("-" | "+" | "!" | "~")) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.unary()?)))
+            }
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, String> {
+        let expr = self.primary()?;
+        if let Expr::Variable(place) = &expr {
+            if let Some(op @ ("++" | "--")) = self.peek_operator() {
+                self.pos += 1;
+                return Ok(Expr::Step(
+                    place.clone(),
+                    if op == "++" { 1 } else { -1 },
+                    false,
+                ));
+            }
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.peek().cloned() {
+            Some(Token::Number(n)) => {
+                self.pos += 1;
+                Ok(Expr::Number(n))
+            }
+            Some(Token::Name(name, subscript)) => {
+                self.pos += 1;
+                Ok(Expr::Variable(Place { name, subscript }))
+            }
+            Some(Token::Open) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(String::from("missing ')'"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+}
+
+// the index or key of `name[subscript]`: associative arrays use the text as
+// it is, indexed arrays evaluate it
+fn key(place: &Place, shell: &mut Shell, depth: usize) -> Result<Option<String>, String> {
+    let Some(subscript) = &place.subscript else {
+        return Ok(None);
+    };
+    if matches!(shell.var(&place.name), Some(Value::Assoc(_))) {
+        return Ok(Some(subscript.trim().to_string()));
+    }
+    evaluate_at(subscript, shell, depth + 1).map(|index| Some(index.to_string()))
+}
+
+fn load(place: &Place, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    let value = match key(place, shell, depth)? {
+        Some(key) => shell.get_element(&place.name, &key)?,
+        None => shell.get_env(&place.name).cloned(),
+    };
+    let value = value.unwrap_or_default();
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    // a variable holding an expression is evaluated in turn
+    match parse_number(value) {
+        Ok(n) => Ok(n),
+        Err(_) => evaluate_at(value, shell, depth + 1),
+    }
+}
+
+fn store(place: &Place, value: i64, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    match key(place, shell, depth)? {
+        Some(key) => shell.set_element(&place.name, &key, value.to_string(), false)?,
+        None => shell.assign_var(&place.name, value.to_string(), false)?,
+    }
+    Ok(value)
+}
+
+fn eval(expr: &Expr, shell: &mut Shell, depth: usize) -> Result<i64, String> {
+    Ok(match expr {
+        Expr::Number(n) => *n,
+        Expr::Variable(place) => load(place, shell, depth)?,
+        Expr::Unary(op, operand) => {
+            let value = eval(operand, shell, depth)?;
+            match *op {
+                "-" => value.wrapping_neg(),
+                "!" => i64::from(value == 0),
+                "~" => !value,
+                _ => value,
+            }
+        }
+        Expr::Step(place, step, prefix) => {
+            let old = load(place, shell, depth)?;
+            let new = store(place, old.wrapping_add(*step), shell, depth)?;
+            if *prefix {
+                new
+            } else {
+                old
+            }
+        }
+        Expr::Binary("&&", left, right) => {
+            i64::from(eval(left, shell, depth)? != 0 && eval(right, shell, depth)? != 0)
+        }
+        Expr::Binary("||", left, right) => {
+            i64::from(eval(left, shell, depth)? != 0 || eval(right, shell, depth)? != 0)
+        }
+        Expr::Binary(op, left, right) => {
+            let left = eval(left, shell, depth)?;
+            let right = eval(right, shell, depth)?;
+            apply(op, left, right)?
+        }
+        Expr::Conditional(condition, then, otherwise) => {
+            if eval(condition, shell, depth)? != 0 {
+                eval(then, shell, depth)?
+            } else {
+                eval(otherwise, shell, depth)?
+            }
+        }
+        Expr::Assign(place, op, value) => {
+            let value = eval(value, shell, depth)?;
+            let value = match op.strip_suffix('=').filter(|op| !op.is_empty()) {
+                Some(op) => apply(op, load(place, shell, depth)?, value)?,
+                None => value,
+            };
+            store(place, value, shell, depth)?
+        }
+        Expr::Comma(first, second) => {
+            eval(first, shell, depth)?;
+            eval(second, shell, depth)?
+        }
+    })
+}
+
+fn apply(op: &str, left: i64, right: i64) -> Result<i64, String> {
+    Ok(match op {
+        "+" => left.wrapping_add(right),
+        "-" => left.wrapping_sub(right),
+        "*" => left.wrapping_mul(right),
+        "/" | "%" if right == 0 => return Err(String::from("division by zero")),
+        "/" => left.wrapping_div(right),
+        "%" => left.wrapping_rem(right),
+        "**" if right < 0 => return Err(String::from("exponent less than 0")),
+        "**" => left.wrapping_pow(u32::try_from(right).unwrap_or(u32::MAX)),
+        "<<" => left.wrapping_shl(right as u32),
+        ">>" => left.wrapping_shr(right as u32),
+        "&" => left & right,
+        "|" => left | right,
+        "^" => left ^ right,
+        "==" => i64::from(left == right),
+        "!=" => i64::from(left != right),
+        "<" => i64::from(left < right),
+        ">" => i64::from(left > right),
+        "<=" => i64::from(left <= right),
+        ">=" => i64::from(left >= right),
+        _ => return Err(format!("unknown operator '{}'", op)),
+    })
+}
@@ -1,102 +1,267 @@
 use std::{
     env, fs,
-    path::{Path, PathBuf},
-    process::{Command, Stdio},
+    path::{Component, Path, PathBuf},
+    process::Command,
 };
 
 use colored::*;
 use log::error;
 
 use crate::{
+    arith::evaluate,
+    conditions::test,
+    config::{config, save, Kind},
+    dirstack::{dirs, is_stack_reference, popd, pushd, stack_entry},
+    exec::{run_command, run_hook, run_script, Flow, Io},
+    explore::{disk_usage, find, tree},
+    fileops::{copy, make_directories, move_files, remove, touch},
+    frecency::{best_match, list_matches, pick, purge, record_visit},
     help::handle_help,
+    ls::list_directory,
+    parser::{is_name, split_assignment},
     shell::Shell,
     system::system_info,
-    utils::{expand_env_vars, tokenize_command},
+    textops::{cat, head, tail, word_count},
+    trash::handle_trash,
+    utils::failure,
+    variables::{declare, double_quote, readonly, set, unalias, unset},
 };
 
-pub fn execute_command(command: &str, shell: &mut Shell) -> String {
+pub fn execute_command(command: &str, shell: &mut Shell) {
+    run_hook("preexec", &[command.to_string()], shell);
     shell.start_command_timer();
-    let result = execute_command_internal(command, shell);
-    if let Some(duration) = shell.end_command_timer() {
-        println!("\n{}\n", format!("Command took {}s", duration).green());
-    }
-    result
+    shell.interactive = true;
+    run_script(command, shell, &Io::terminal());
+    shell.interactive = false;
+    shell.end_command_timer();
 }
 
-/// This function is used for processing and executing user commands
-/// Key steps include
-/// 1. Expand environment variables in the command
-/// 2. Tokenize the command
-/// 3. Check for and expand aliases
-/// 4. Match against built-in commands
-/// 5. If not a built in command, exectute as an external command
-pub fn execute_command_internal(command: &str, shell: &mut Shell) -> String {
-    let command = expand_env_vars(command, shell);
-
-    let mut parts: Vec<String> = tokenize_command(&command);
+/// Runs a built-in command with already expanded arguments and returns its
+/// output, or its error message for stderr, or None if `parts[0]` is not a
+/// builtin. Builtins that fail set `shell.last_status`.
+pub fn run_builtin(parts: &[String], shell: &mut Shell, io: &Io) -> Option<Result<String, String>> {
+    let output = match parts[0].as_str() {
+        "systeminfo" => Ok(toggle_system_info(&parts[1..], shell)),
+        "echo" => Ok(handle_echo(&parts[1..])),
+        "pwd" => Ok(print_directory(&parts[1..], shell)),
+        "cd" => {
+            let result = change_directory(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "z" | "zi" => {
+            let result = handle_jump(&parts[0], &parts[1..], shell);
+            with_status(result, shell)
+        }
+        "ls" => {
+            let result = list_directory(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "tree" => {
+            let result = tree(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "du" => {
+            let result = disk_usage(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "find" => {
+            let result = find(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "cat" => {
+            let result = cat(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "head" => {
+            let result = head(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "tail" => {
+            let result = tail(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "wc" => {
+            let result = word_count(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "mkdir" => {
+            let result = make_directories(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "rm" => {
+            let result = remove(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "cp" => {
+            let result = copy(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "mv" => {
+            let result = move_files(&parts[1..], io);
+            with_status(result, shell)
+        }
+        "trash" => {
+            let result = handle_trash(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "touch" => {
+            let result = touch(&parts[1..]);
+            with_status(result, shell)
+        }
+        "alias" => {
+            let result = handle_alias(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "config" => {
+            let result = config(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "unalias" => {
+            let result = unalias(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "unset" => {
+            let result = unset(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "declare" | "typeset" => {
+            let result = declare(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "readonly" => {
+            let result = readonly(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "set" => {
+            let result = set(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "export" => {
+            let result = handle_export(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "env" => {
+            let result = handle_env(&parts[1..], shell, io);
+            with_status(result, shell)
+        }
+        "info" => Ok(system_info()),
+        "help" => Ok(handle_help(&parts[1..])),
+        "set-default" => Ok(set_as_default_shell()),
+        "remove-default" => Ok(remove_default_shell()),
+        "dirs" => {
+            let result = dirs(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "pushd" => {
+            let result = pushd(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "popd" => {
+            let result = popd(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "jobs" => Ok(handle_jobs(shell)),
+        "history" => Ok(handle_history(shell)),
+        "return" | "exit" | "break" | "continue" => {
+            let result = handle_flow(&parts[0], &parts[1..], shell);
+            with_status(result, shell)
+        }
+        "let" => {
+            let result = handle_let(&parts[1..], shell);
+            with_status(result, shell)
+        }
+        "test" | "[" => handle_test(&parts[0], &parts[1..], shell),
+        ":" | "true" => Ok(String::new()),
+        "false" => {
+            shell.last_status = 1;
+            Ok(String::new())
+        }
+        _ => return None,
+    };
+    Some(output)
+}
 
-    if parts.is_empty() {
-        return String::from("No command entered");
+// Records the exit status of a builtin that can fail
+fn with_status(result: Result<String, String>, shell: &mut Shell) -> Result<String, String> {
+    if result.is_err() {
+        shell.last_status = 1;
     }
+    result
+}
 
-    if let Some(alias_command) = shell.get_alias(&parts[0]) {
-        parts = tokenize_command(alias_command);
+// `let EXPR...` evaluates each expression and, like `(( ))`, fails when the
+// last one is zero
+fn handle_let(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    if args.is_empty() {
+        return Err(failure("let: expression expected"));
     }
-
-    match parts[0].as_str() {
-        "systeminfo" => toggle_system_info(&parts[1..], shell),
-        "echo" => handle_echo(&parts[1..], shell),
-        "pwd" => env::current_dir()
-            .map(|path| path.to_string_lossy().into_owned())
-            .unwrap_or_else(|_| String::from("Failed to get current directory")),
-        "cd" => change_directory(parts.get(1).map(|s| s.as_str()).as_ref()),
-        "ls" => list_directory(parts.get(1).map(|s| s.as_str()).as_ref()),
-        "mkdir" => create_directory(parts.get(1).map(|s| s.as_str()).as_ref()),
-        "rm" => remove_file_or_directory(parts.get(1).map(|s| s.as_str()).as_ref()),
-        "touch" => create_file(parts.get(1).map(|s| s.as_str()).as_ref()),
-        "alias" => handle_alias(&parts[1..], shell),
-        "export" => handle_export(&parts[1..], shell),
-        "env" => shell
-            .env_vars
-            .iter()
-            .map(|(k, v)| format!("{}={}", k, v))
-            .collect::<Vec<_>>()
-            .join("\n"),
-        "info" => system_info(),
-        "help" => handle_help(&parts[1..]),
-        "set-default" => set_as_default_shell(),
-        "remove-default" => remove_default_shell(),
-        "pushd" => handle_pushd(&parts[1..], shell),
-        "popd" => handle_popd(shell),
-        "jobs" => handle_jobs(shell),
-        "history" => handle_history(shell),
-
-        _ => execute_external_command(&parts),
+    let mut last = 0;
+    for arg in args {
+        last = evaluate(arg, shell).map_err(|e| failure(&format!("let: {}", e)))?;
+    }
+    if last == 0 {
+        shell.last_status = 1;
     }
+    Ok(String::new())
 }
 
-fn execute_external_command(parts: &[String]) -> String {
-    let mut command = Command::new(&parts[0]);
-    command
-        .args(&parts[1..])
-        .stdin(Stdio::inherit())
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit());
-
-    match command.output() {
-        Ok(output) => {
-            if output.status.success() {
-                String::from_utf8_lossy(&output.stdout).into_owned()
-            } else {
-                String::from_utf8_lossy(&output.stderr).into_owned()
-            }
+// `test EXPR` and `[ EXPR ]` only set the status: 1 when the expression is
+// false and 2 when it is malformed
+fn handle_test(name: &str, args: &[String], shell: &mut Shell) -> Result<String, String> {
+    match test(name, args, shell) {
+        Ok(true) => Ok(String::new()),
+        Ok(false) => {
+            shell.last_status = 1;
+            Ok(String::new())
+        }
+        Err(message) => {
+            shell.last_status = 2;
+            Err(failure(&format!("{}: {}", name, message)))
         }
-        Err(e) => format!("Failed to execute command: {}", e),
     }
 }
 
+// `return [n]`, `break [n]` and `continue [n]` ask the enclosing function or
+// loop to stop early
+fn handle_flow(name: &str, args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let number = match args.first() {
+        Some(arg) => arg.parse::<i32>().map_err(|_| {
+            format!(
+                "{}{}: numeric argument required{}",
+                "[".red(),
+                name,
+                "]".red()
+            )
+        })?,
+        None if name == "return" || name == "exit" => shell.last_status,
+        None => 1,
+    };
+
+    shell.flow = match name {
+        "return" => {
+            shell.last_status = number;
+            Flow::Return
+        }
+        "exit" => {
+            shell.last_status = number;
+            Flow::Exit
+        }
+        _ if number < 1 => {
+            return Err(format!(
+                "{}{}: loop count out of range{}",
+                "[".red(),
+                name,
+                "]".red()
+            ));
+        }
+        "break" => Flow::Break(number as usize),
+        _ => Flow::Continue(number as usize),
+    };
+    Ok(String::new())
+}
+
 fn toggle_system_info(args: &[String], shell: &mut Shell) -> String {
-    match args.get(0).map(|s| s.as_str()) {
+    match args.first().map(|s| s.as_str()) {
         Some("on") => {
             shell.set_show_system_info(true);
             format!("{}System info display enabled{}", "[".green(), "]".green())
@@ -126,7 +291,7 @@ fn set_as_default_shell() -> String {
     let shells = fs::read_to_string("/etc/shells").unwrap_or_default();
     if !shells.contains(&*shell_path_str) {
         let status = Command::new("sudo")
-            .args(&[
+            .args([
                 "sh",
                 "-c",
                 &format!("echo '{}' >> /etc/shells", shell_path_str),
@@ -142,7 +307,7 @@ fn set_as_default_shell() -> String {
         }
     }
 
-    let status = Command::new("chsh").args(&["-s", &shell_path_str]).status();
+    let status = Command::new("chsh").args(["-s", &shell_path_str]).status();
 
     match status {
         Ok(_) => format!("{}Batcave set as default shell. Please log out and back in for changes to take effect{}", 
@@ -154,7 +319,7 @@ fn set_as_default_shell() -> String {
 
 fn remove_default_shell() -> String {
     // Change shell back to bash
-    let status = Command::new("chsh").args(&["-s", "/bin/bash"]).status();
+    let status = Command::new("chsh").args(["-s", "/bin/bash"]).status();
 
     match status {
         Ok(_) => format!("{}Default shell reset to bash. Please log out and back in for changes to take effect{}", 
@@ -180,67 +345,162 @@ fn handle_history(shell: &Shell) -> String {
                 .history
                 .iter()
                 .enumerate()
-                .map(|(i, cmd)| format!("{:5} {}", (i + 1).to_string().green(), cmd))
+                .map(|(i, entry)| format!("{:5} {}", (i + 1).to_string().green(), entry.command))
                 .collect::<Vec<_>>()
                 .join("\n")
         )
     }
 }
 
-fn change_directory(path: Option<&&str>) -> String {
-    match path {
-        Some(path) => {
-            if let Err(e) = env::set_current_dir(path) {
-                error!("Failed to change directory: {}", e);
-                format!(
-                    "{}Failed to change directory: {}{}",
+// `pwd` prints the logical directory ($PWD, which keeps symlinks) and
+// `pwd -P` the physical one
+fn print_directory(args: &[String], shell: &Shell) -> String {
+    let physical = args.iter().any(|arg| arg == "-P");
+    let dir = if physical {
+        env::current_dir().ok()
+    } else {
+        Some(shell.current_dir())
+    };
+    dir.map(|path| format!("{}\n", path.display()))
+        .unwrap_or_else(|| String::from("Failed to get current directory"))
+}
+
+/// Runs `cd`: `cd` goes home, `cd -` goes back to $OLDPWD, relative names
+/// are also looked up in $CDPATH, and `-P` resolves symlinks instead of
+/// keeping them in $PWD. `pushd`, `popd` and `z` change directory through it
+/// too, so they all update $PWD and $OLDPWD. Only changes made by commands
+/// typed at the prompt are recorded in the frecency database.
+pub fn change_directory(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let mut physical = false;
+    let mut target = None;
+    for arg in args {
+        match arg.as_str() {
+            "-L" if target.is_none() => physical = false,
+            "-P" if target.is_none() => physical = true,
+            _ if target.is_none() => target = Some(arg.as_str()),
+            _ => return Err(format!("{}cd: too many arguments{}", "[".red(), "]".red())),
+        }
+    }
+
+    let (target, mut show) = match target {
+        None => match shell.get_env("HOME") {
+            Some(home) => (home.clone(), false),
+            None => return Err(format!("{}cd: HOME not set{}", "[".red(), "]".red())),
+        },
+        Some("-") => match shell.get_env("OLDPWD") {
+            Some(old) => (old.clone(), true),
+            None => return Err(format!("{}cd: OLDPWD not set{}", "[".red(), "]".red())),
+        },
+        Some(entry) if is_stack_reference(entry) => match stack_entry(entry, shell) {
+            Some(dir) => (dir, false),
+            None => {
+                return Err(format!(
+                    "{}cd: {}: directory stack index out of range{}",
                     "[".red(),
-                    e,
+                    entry,
                     "]".red()
-                )
-            } else {
-                format!(
-                    "{}Changed to directory: {}{}",
-                    "[".green(),
-                    path,
-                    "]".green()
-                )
+                ))
+            }
+        },
+        Some(dir) => (dir.to_string(), false),
+    };
+
+    let mut path = PathBuf::from(&target);
+    let relative = !matches!(
+        path.components().next(),
+        Some(Component::RootDir | Component::CurDir | Component::ParentDir)
+    );
+    if relative {
+        let cdpath = shell.get_env("CDPATH").cloned().unwrap_or_default();
+        for base in cdpath.split(':').filter(|base| !base.is_empty()) {
+            let candidate = Path::new(base).join(&target);
+            if candidate.is_dir() {
+                // like bash, say where we ended up when CDPATH was used
+                show = base != ".";
+                path = candidate;
+                break;
             }
-        }
-        None => {
-            error!("cd: missing argument");
-            format!("{}cd: missing argument{}", "[".red(), "]".red())
         }
     }
-}
 
-fn handle_pushd(args: &[String], shell: &mut Shell) -> String {
-    if let Some(dir) = args.get(0) {
-        let current = env::current_dir()
-            .map(|p| p.to_string_lossy().into_owned())
-            .unwrap_or_default();
-        shell.push_dir(current);
-        change_directory(Some(&dir.as_str()))
+    let old = shell.current_dir();
+    let logical = normalize(&old.join(&path));
+    let changed = if physical {
+        env::set_current_dir(&path)
     } else {
-        format!(
-            "{}pushd: missing directory argument{}",
-            "[".red(),
-            "]".red()
-        )
+        // fall back to the physical path if `..` can't be resolved lexically
+        env::set_current_dir(&logical).or_else(|_| env::set_current_dir(&path))
+    };
+    if let Err(e) = changed {
+        error!("Failed to change directory: {}", e);
+        return Err(format!("{}cd: {}: {}{}", "[".red(), target, e, "]".red()));
     }
-}
 
-fn handle_popd(shell: &mut Shell) -> String {
-    if let Some(dir) = shell.pop_dir() {
-        change_directory(Some(&dir.as_str()))
+    let physical_dir = env::current_dir().unwrap_or_else(|_| logical.clone());
+    let new = if physical || !same_file(&logical, &physical_dir) {
+        physical_dir
     } else {
-        format!("{}popd: directory stack empty{}", "[".red(), "]".red())
+        logical
+    };
+    shell.set_pwd(&old, &new);
+    // cds from the rc file, hooks and functions would skew the ranking
+    if shell.interactive {
+        record_visit(&new, shell);
+    }
+
+    Ok(if show {
+        format!("{}\n", new.display())
+    } else {
+        String::new()
+    })
+}
+
+// `z FRAGMENT...` jumps to the best ranked directory matching the fragments
+// and `zi` lets the user pick one; `z -l` lists the candidates and
+// `z --purge` forgets directories that no longer exist
+fn handle_jump(name: &str, args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let target = match (name, args.first().map(String::as_str)) {
+        ("z", Some("--purge")) => return purge(shell),
+        ("z", Some("-l")) => return list_matches(&args[1..], shell),
+        ("z", None) => return list_matches(args, shell),
+        // like zoxide, a real directory wins over the database
+        ("z", Some(dir)) if args.len() == 1 && (dir == "-" || Path::new(dir).is_dir()) => {
+            return change_directory(args, shell)
+        }
+        ("z", _) => best_match(args, shell)?,
+        (_, _) => match pick(args, shell)? {
+            Some(path) => path,
+            None => return Ok(String::new()),
+        },
+    };
+    change_directory(&[target.to_string_lossy().into_owned()], shell)
+}
+
+// resolves `.` and `..` in an absolute path without following symlinks
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
     }
 }
 
 fn handle_jobs(_shell: &mut Shell) -> String {
     let processes = std::process::Command::new("ps")
-        .args(&["aux"])
+        .args(["aux"])
         .output()
         .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
         .unwrap_or_else(|e| {
@@ -259,154 +519,169 @@ fn handle_jobs(_shell: &mut Shell) -> String {
     )
 }
 
-fn list_directory(path: Option<&&str>) -> String {
-    let path = path.map(Path::new).unwrap_or_else(|| Path::new("."));
-    match fs::read_dir(path) {
-        Ok(entries) => entries
-            .filter_map(Result::ok)
-            .map(|entry| {
-                let name = entry.file_name().to_string_lossy().into_owned();
-                if entry.file_type().map(|ft| ft.is_dir()).unwrap_or(false) {
-                    format!("{}", name.blue())
-                } else {
-                    name
-                }
-            })
-            .collect::<Vec<_>>()
-            .join("  "),
-        Err(e) => {
-            error!("Failed to list directory: {}", e);
-            format!("{}Failed to list directory: {}{}", "[".red(), e, "]".red())
-        }
-    }
-}
-
-fn create_directory(path: Option<&&str>) -> String {
-    match path {
-        Some(path) => {
-            if let Err(e) = fs::create_dir(path) {
-                error!("Failed to create directory: {}", e);
-                format!(
-                    "{}Failed to create directory: {}{}",
-                    "[".red(),
-                    e,
-                    "]".red()
-                )
-            } else {
-                format!("{}Directory created: {}{}", "[".green(), path, "]".green())
-            }
-        }
-        None => {
-            error!("mkdir: missing argument");
-            format!("{}mkdir: missing argument{}", "[".red(), "]".red())
-        }
-    }
-}
-
-fn remove_file_or_directory(path: Option<&&str>) -> String {
-    match path {
-        Some(path) => {
-            let path = Path::new(path);
-            if path.is_dir() {
-                if let Err(e) = fs::remove_dir_all(path) {
-                    error!("Failed to remove directory: {}", e);
-                    format!(
-                        "{}Failed to remove directory: {}{}",
-                        "[".red(),
-                        e,
-                        "]".red()
-                    )
-                } else {
-                    format!(
-                        "{}Directory removed: {}{}",
-                        "[".green(),
-                        path.display(),
-                        "]".green()
-                    )
-                }
-            } else {
-                if let Err(e) = fs::remove_file(path) {
-                    error!("Failed to remove file: {}", e);
-                    format!("{}Failed to remove file: {}{}", "[".red(), e, "]".red())
-                } else {
-                    format!(
-                        "{}File removed: {}{}",
-                        "[".green(),
-                        path.display(),
-                        "]".green()
-                    )
-                }
-            }
-        }
-        None => {
-            error!("rm: missing argument");
-            format!("{}rm: missing argument{}", "[".red(), "]".red())
-        }
-    }
+fn handle_echo(args: &[String]) -> String {
+    format!("{}\n", args.join(" "))
 }
 
-fn create_file(path: Option<&&str>) -> String {
-    match path {
-        Some(path) => {
-            if let Err(e) = fs::File::create(path) {
-                error!("Failed to create file: {}", e);
-                format!("Failed to create file: {}", e)
-            } else {
-                format!("File created: {}", path)
-            }
-        }
-        None => {
-            error!("touch: missing argument");
-            String::from("touch: missing argument")
-        }
-    }
-}
-
-fn handle_echo(args: &[String], shell: &Shell) -> String {
-    args.iter()
-        .map(|arg| expand_env_vars(arg, shell))
-        .collect::<Vec<_>>()
-        .join(" ")
-}
-
-fn handle_alias(args: &[String], shell: &mut Shell) -> String {
-    if args.is_empty() {
-        return shell
+// `alias name=value` defines an alias and `alias --save` also writes it to
+// ~/.batcaverc, for a new definition or one that already exists
+fn handle_alias(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let persist = args.first().is_some_and(|arg| arg == "--save");
+    let args = if persist { &args[1..] } else { args };
+    if args.is_empty() && !persist {
+        return Ok(shell
             .aliases
             .iter()
             .map(|(name, command)| format!("{}='{}'", name, command))
             .collect::<Vec<_>>()
-            .join("\n");
+            .join("\n"));
     }
 
     let alias_str = args.join(" ");
+    if persist && !args.is_empty() && !alias_str.contains('=') {
+        let name = alias_str.trim();
+        let command = shell
+            .get_alias(name)
+            .cloned()
+            .ok_or_else(|| failure(&format!("alias: {}: not found", name)))?;
+        save(Kind::Alias, name, Some(&command)).map_err(|e| failure(&format!("alias: {}", e)))?;
+        return Ok(format!("Saved alias: {}='{}'", name, command));
+    }
     if let Some(equals_pos) = alias_str.find('=') {
         let name = alias_str[..equals_pos].trim().to_string();
+        // a trailing blank is kept: it makes the word after the alias an
+        // alias too
         let command = alias_str[equals_pos + 1..]
-            .trim()
+            .trim_start()
             .trim_matches('"')
             .trim_matches('\'')
             .to_string();
         shell.add_alias(name.clone(), command.clone());
-        format!("Added alias: {}='{}'", name, command)
+        if persist {
+            save(Kind::Alias, &name, Some(&command))
+                .map_err(|e| failure(&format!("alias: {}", e)))?;
+            return Ok(format!("Saved alias: {}='{}'", name, command));
+        }
+        Ok(format!("Added alias: {}='{}'", name, command))
     } else {
-        "Usage: alias name=command".to_string()
+        Ok("Usage: alias [--save] name=command".to_string())
     }
 }
 
-fn handle_export(args: &[String], shell: &mut Shell) -> String {
-    if args.is_empty() {
-        return "Usage: export NAME=value".to_string();
+// `export NAME=value` sets and exports a variable, `export NAME` exports an
+// existing one and `export -n NAME` keeps it local again. `--save` records
+// the change in ~/.batcaverc. Without names the exported variables are
+// listed in a form that can be read back.
+fn handle_export(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let unexport = args.iter().any(|arg| arg == "-n");
+    let persist = args.iter().any(|arg| arg == "--save");
+    let names: Vec<&String> = args
+        .iter()
+        .filter(|arg| !matches!(arg.as_str(), "-n" | "-p" | "--save"))
+        .collect();
+    if names.is_empty() {
+        return Ok(list_exports(shell));
     }
 
-    for arg in args {
-        if let Some(equals_pos) = arg.find('=') {
-            let name = arg[..equals_pos].to_string();
-            let value = arg[equals_pos + 1..].to_string();
-            shell.set_env(name, value);
+    let mut errors = Vec::new();
+    for arg in names {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (arg.as_str(), None),
+        };
+        if !is_name(name) {
+            errors.push(failure(&format!(
+                "export: '{}': not a valid identifier",
+                arg
+            )));
+            continue;
         }
+        if let Some(value) = value {
+            if let Err(message) = shell.assign_var(name, value.to_string(), false) {
+                errors.push(failure(&format!("export: {}", message)));
+                continue;
+            }
+        }
+        if unexport {
+            shell.exported.remove(name);
+        } else {
+            shell.export(name);
+        }
+        if persist {
+            let value = shell.get_env(name).cloned().unwrap_or_default();
+            let value = (!unexport).then_some(value.as_str());
+            if let Err(e) = save(Kind::Export, name, value) {
+                errors.push(failure(&format!("export: {}", e)));
+            }
+        }
+    }
+    if errors.is_empty() {
+        Ok(String::new())
+    } else {
+        Err(errors.join("\n"))
     }
-    String::new()
+}
+
+fn list_exports(shell: &Shell) -> String {
+    let mut names: Vec<&String> = shell.exported.iter().collect();
+    names.sort();
+    names
+        .into_iter()
+        .map(|name| match shell.get_env(name) {
+            Some(value) => format!("export {}={}", name, double_quote(value)),
+            None => format!("export {}", name),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// `env [-i] [-u NAME] [NAME=value]... [command]` runs a command with a
+// changed environment, or prints the environment it would get
+fn handle_env(args: &[String], shell: &mut Shell, io: &Io) -> Result<String, String> {
+    let saved_vars = shell.vars.clone();
+    let saved_exported = shell.exported.clone();
+
+    let mut rest = args;
+    while let Some(arg) = rest.first() {
+        match arg.as_str() {
+            "-i" | "-" => {
+                shell.exported.clear();
+                rest = &rest[1..];
+            }
+            "-u" => {
+                let Some(name) = rest.get(1) else {
+                    shell.vars = saved_vars;
+                    shell.exported = saved_exported;
+                    return Err(failure("env: option requires an argument -- 'u'"));
+                };
+                shell.unset_env(name);
+                rest = &rest[2..];
+            }
+            _ => match split_assignment(arg) {
+                Some((name, value)) => {
+                    shell.set_env(name.to_string(), value.to_string());
+                    shell.export(name);
+                    rest = &rest[1..];
+                }
+                None => break,
+            },
+        }
+    }
+
+    let result = if rest.is_empty() {
+        Ok(shell
+            .exported_vars()
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    } else {
+        shell.last_status = run_command(rest, shell, io);
+        Ok(String::new())
+    };
+    shell.vars = saved_vars;
+    shell.exported = saved_exported;
+    result
 }
 
 // TODO: find a way to use this function
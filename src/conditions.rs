@@ -0,0 +1,314 @@
+// The conditions module evaluates the expressions of the `test` and `[`
+// builtins and of `[[ ... ]]` commands: file tests, string and integer
+// comparisons, pattern and regex matching, joined with `!`, parentheses and
+// `-a`/`-o` or `&&`/`||`.
+use std::{
+    ffi::CString,
+    fs::{self, Metadata},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    path::Path,
+};
+
+use regex::Regex;
+
+use crate::{
+    arith::evaluate,
+    expand::{expand_pattern, expand_regex, expand_single, glob_match},
+    shell::Shell,
+};
+
+const UNARY: [&str; 17] = [
+    "-e", "-f", "-d", "-r", "-w", "-x", "-s", "-L", "-h", "-p", "-S", "-b", "-c", "-u", "-g", "-z",
+    "-n",
+];
+
+const BINARY: [&str; 14] = [
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// Evaluates the arguments of `test`, or of `[` when `name` is "[", which
+/// must end with `]`. Errors are for malformed expressions, which `test`
+/// reports with status 2.
+pub fn test(name: &str, args: &[String], shell: &mut Shell) -> Result<bool, String> {
+    let args = if name == "[" {
+        match args.split_last() {
+            Some((last, rest)) if last == "]" => rest,
+            _ => return Err(String::from("missing ']'")),
+        }
+    } else {
+        args
+    };
+    if args.is_empty() {
+        return Ok(false);
+    }
+    Evaluator::new(args, shell, false).run()
+}
+
+/// Evaluates the words between `[[` and `]]`. They are expanded here, one
+/// operand at a time, so `&&` and `||` skip the expansions they do not need.
+pub fn conditional(words: &[String], shell: &mut Shell) -> Result<bool, String> {
+    if words.is_empty() {
+        return Err(String::from("expression expected after `[['"));
+    }
+    Evaluator::new(words, shell, true).run()
+}
+
+// A recursive descent parser that evaluates as it goes. `extended` selects
+// the `[[` flavour: operands get expanded, `==` matches patterns, integers
+// are arithmetic expressions and `&&`/`||` replace `-a`/`-o`.
+struct Evaluator<'a> {
+    words: &'a [String],
+    pos: usize,
+    shell: &'a mut Shell,
+    extended: bool,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(words: &'a [String], shell: &'a mut Shell, extended: bool) -> Self {
+        Evaluator {
+            words,
+            pos: 0,
+            shell,
+            extended,
+        }
+    }
+
+    fn run(mut self) -> Result<bool, String> {
+        let result = self.or(false)?;
+        match self.peek(0) {
+            None => Ok(result),
+            Some(word) if self.extended => Err(format!("syntax error near `{}'", word)),
+            Some(_) => Err(String::from("too many arguments")),
+        }
+    }
+
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.words.get(self.pos + offset).map(String::as_str)
+    }
+
+    fn and_operator(&self) -> &'static str {
+        if self.extended {
+            "&&"
+        } else {
+            "-a"
+        }
+    }
+
+    fn or_operator(&self) -> &'static str {
+        if self.extended {
+            "||"
+        } else {
+            "-o"
+        }
+    }
+
+    // a word that starts a binary expression, as in `! = x`, is an operand
+    // rather than an operator
+    fn starts_binary(&self) -> bool {
+        self.peek(1).is_some_and(|op| self.is_binary(op)) && self.peek(2).is_some()
+    }
+
+    fn is_binary(&self, op: &str) -> bool {
+        BINARY.contains(&op) || (self.extended && op == "=~")
+    }
+
+    // `skip` is set on the side of `&&` or `||` that cannot change the
+    // result: it is parsed but neither expanded nor evaluated
+    fn or(&mut self, skip: bool) -> Result<bool, String> {
+        let mut result = self.and(skip)?;
+        while self.peek(0) == Some(self.or_operator()) {
+            self.pos += 1;
+            let right = self.and(skip || result)?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self, skip: bool) -> Result<bool, String> {
+        let mut result = self.not(skip)?;
+        while self.peek(0) == Some(self.and_operator()) {
+            self.pos += 1;
+            let right = self.not(skip || !result)?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self, skip: bool) -> Result<bool, String> {
+        if self.peek(0) == Some("!") && self.peek(1).is_some() && !self.starts_binary() {
+            self.pos += 1;
+            return Ok(!self.not(skip)?);
+        }
+        self.primary(skip)
+    }
+
+    fn primary(&mut self, skip: bool) -> Result<bool, String> {
+        let Some(word) = self.peek(0) else {
+            return Err(String::from("argument expected"));
+        };
+
+        if word == "(" && self.peek(1).is_some() && !self.starts_binary() {
+            self.pos += 1;
+            let result = self.or(skip)?;
+            if self.peek(0) != Some(")") {
+                return Err(String::from("')' expected"));
+            }
+            self.pos += 1;
+            return Ok(result);
+        }
+
+        if self.starts_binary() {
+            let op = self.peek(1).unwrap_or_default();
+            let right = self.peek(2).unwrap_or_default();
+            self.pos += 3;
+            if skip {
+                return Ok(false);
+            }
+            let left = self.operand(word);
+            return self.binary(&left, op, right);
+        }
+
+        if (UNARY.contains(&word) || (self.extended && word == "-v")) && self.peek(1).is_some() {
+            let operand = self.peek(1).unwrap_or_default();
+            self.pos += 2;
+            if skip {
+                return Ok(false);
+            }
+            let operand = self.operand(operand);
+            return Ok(unary(word, &operand, self.shell));
+        }
+
+        self.pos += 1;
+        if skip {
+            return Ok(false);
+        }
+        Ok(!self.operand(word).is_empty())
+    }
+
+    fn operand(&mut self, word: &str) -> String {
+        if self.extended {
+            expand_single(word, self.shell)
+        } else {
+            word.to_string()
+        }
+    }
+
+    fn binary(&mut self, left: &str, op: &str, right: &str) -> Result<bool, String> {
+        match op {
+            "=" | "==" | "!=" if self.extended => {
+                let pattern = expand_pattern(right, self.shell);
+                Ok(glob_match(&pattern, left) == (op != "!="))
+            }
+            "=~" => {
+                let pattern = expand_regex(right, self.shell);
+                self.regex_match(left, &pattern)
+            }
+            "-eq" | "-ne" | "-lt" | "-le" | "-gt" | "-ge" => {
+                let right = self.operand(right);
+                let (left, right) = (self.integer(left)?, self.integer(&right)?);
+                Ok(match op {
+                    "-eq" => left == right,
+                    "-ne" => left != right,
+                    "-lt" => left < right,
+                    "-le" => left <= right,
+                    "-gt" => left > right,
+                    _ => left >= right,
+                })
+            }
+            _ => {
+                let right = self.operand(right);
+                Ok(compare(left, op, &right))
+            }
+        }
+    }
+
+    // `test` takes plain integers, `[[` arithmetic expressions
+    fn integer(&mut self, text: &str) -> Result<i64, String> {
+        if self.extended {
+            evaluate(text, self.shell)
+        } else {
+            text.trim()
+                .parse()
+                .map_err(|_| format!("{}: integer expression expected", text))
+        }
+    }
+
+    // stores the whole match and the groups in BASH_REMATCH, which is
+    // emptied when there is no match
+    fn regex_match(&mut self, text: &str, pattern: &str) -> Result<bool, String> {
+        let regex =
+            Regex::new(pattern).map_err(|_| format!("{}: invalid regular expression", pattern))?;
+        let groups: Vec<(Option<String>, String)> = match regex.captures(text) {
+            Some(captures) => captures
+                .iter()
+                .map(|group| (None, group.map_or("", |m| m.as_str()).to_string()))
+                .collect(),
+            None => Vec::new(),
+        };
+        let matched = !groups.is_empty();
+        self.shell.assign_array("BASH_REMATCH", groups, false)?;
+        Ok(matched)
+    }
+}
+
+fn unary(op: &str, operand: &str, shell: &Shell) -> bool {
+    let path = Path::new(operand);
+    let metadata = || fs::metadata(path).ok();
+    let file_type = |check: fn(&Metadata) -> bool| metadata().is_some_and(|m| check(&m));
+    match op {
+        "-z" => operand.is_empty(),
+        "-n" => !operand.is_empty(),
+        "-v" => shell.var(operand).is_some(),
+        "-e" => metadata().is_some(),
+        "-f" => file_type(Metadata::is_file),
+        "-d" => file_type(Metadata::is_dir),
+        "-s" => file_type(|m| m.len() > 0),
+        "-p" => file_type(|m| m.file_type().is_fifo()),
+        "-S" => file_type(|m| m.file_type().is_socket()),
+        "-b" => file_type(|m| m.file_type().is_block_device()),
+        "-c" => file_type(|m| m.file_type().is_char_device()),
+        "-u" => file_type(|m| m.mode() & libc::S_ISUID != 0),
+        "-g" => file_type(|m| m.mode() & libc::S_ISGID != 0),
+        "-L" | "-h" => fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink()),
+        "-r" => accessible(operand, libc::R_OK),
+        "-w" => accessible(operand, libc::W_OK),
+        _ => accessible(operand, libc::X_OK),
+    }
+}
+
+// asks the kernel, so permissions are checked for the user running the shell
+fn accessible(path: &str, mode: libc::c_int) -> bool {
+    match CString::new(Path::new(path).as_os_str().as_bytes()) {
+        // SAFETY: the string is NUL-terminated and outlives the call
+        Ok(path) => unsafe { libc::access(path.as_ptr(), mode) == 0 },
+        Err(_) => false,
+    }
+}
+
+fn compare(left: &str, op: &str, right: &str) -> bool {
+    match op {
+        "=" | "==" => left == right,
+        "!=" => left != right,
+        "<" => left < right,
+        ">" => left > right,
+        "-nt" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left > right,
+            (left, _) => left.is_some(),
+        },
+        "-ot" => match (modified(left), modified(right)) {
+            (Some(left), Some(right)) => left < right,
+            (_, right) => right.is_some(),
+        },
+        _ => match (fs::metadata(left), fs::metadata(right)) {
+            (Ok(left), Ok(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+    }
+}
+
+fn modified(path: &str) -> Option<std::time::SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
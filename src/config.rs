@@ -0,0 +1,291 @@
+// The config module edits ~/.batcaverc for `alias --save`, `export --save`
+// and the `config` builtin. Only the lines defining the alias or variable
+// that changed are touched, so comments and ordering are kept. The file is
+// replaced atomically, and the previous version is kept as .batcaverc.bak.
+use std::{
+    env,
+    fs::{self, File},
+    io::Write,
+    path::PathBuf,
+};
+
+use crate::{
+    exec::{run_command, run_script, Flow, Input, Io, Output},
+    parser::{is_name, tokenize, unquote, Token},
+    shell::Shell,
+    utils::failure,
+    variables::{double_quote, single_quote},
+};
+
+/// The kinds of definitions the rc file can be edited for.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Alias,
+    Export,
+}
+
+impl Kind {
+    fn keyword(self) -> &'static str {
+        match self {
+            Kind::Alias => "alias",
+            Kind::Export => "export",
+        }
+    }
+
+    fn line(self, name: &str, value: &str) -> String {
+        match self {
+            Kind::Alias => format!("alias {}={}", name, single_quote(value)),
+            Kind::Export => format!("export {}={}", name, double_quote(value)),
+        }
+    }
+}
+
+/// The path of the rc file, ~/.batcaverc.
+pub fn config_path() -> Option<PathBuf> {
+    env::var("HOME")
+        .ok()
+        .map(|home| PathBuf::from(home).join(".batcaverc"))
+}
+
+/// Writes a definition to the rc file, replacing the one already there, or
+/// removes it when `value` is None.
+pub fn save(kind: Kind, name: &str, value: Option<&str>) -> Result<(), String> {
+    let mut rc = RcFile::open()?;
+    match value {
+        Some(value) => rc.set(kind, name, value),
+        None => rc.remove(kind, name),
+    }
+    rc.save()
+}
+
+// the lines of the rc file
+struct RcFile {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl RcFile {
+    // a missing file is read as an empty one
+    fn open() -> Result<Self, String> {
+        let path = config_path().ok_or("HOME is not set")?;
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        Ok(RcFile {
+            path,
+            lines: content.lines().map(String::from).collect(),
+        })
+    }
+
+    // the line that defines `name` last, which is the one that takes effect
+    fn find(&self, kind: Kind, name: &str) -> Option<usize> {
+        self.lines
+            .iter()
+            .rposition(|line| defined_name(line, kind).as_deref() == Some(name))
+    }
+
+    fn get(&self, kind: Kind, name: &str) -> Option<String> {
+        let tokens = tokenize(&self.lines[self.find(kind, name)?]).ok()?;
+        match tokens.get(1) {
+            Some(Token::Word(word)) => Some(unquote(word.split_once('=')?.1)),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, kind: Kind, name: &str, value: &str) {
+        let line = kind.line(name, value);
+        match self.find(kind, name) {
+            Some(index) => self.lines[index] = line,
+            None => self.lines.push(line),
+        }
+    }
+
+    fn remove(&mut self, kind: Kind, name: &str) {
+        self.lines
+            .retain(|line| defined_name(line, kind).as_deref() != Some(name));
+    }
+
+    // the names defined by `kind`, in the order they first appear
+    fn names(&self, kind: Kind) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+        for name in self
+            .lines
+            .iter()
+            .filter_map(|line| defined_name(line, kind))
+        {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
+    // writes a temporary file next to the rc file and renames it over the
+    // original, so a failed write never leaves a truncated config behind.
+    // A symlinked rc file is followed, so the link stays in place and its
+    // target is replaced.
+    fn save(&self) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("{}: {}", self.path.display(), e);
+        let target = match fs::canonicalize(&self.path) {
+            Ok(target) => target,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self.path.clone(),
+            Err(e) => return Err(error(e)),
+        };
+        let sibling = |suffix: &str| {
+            let mut name = target.file_name().unwrap_or_default().to_os_string();
+            name.push(suffix);
+            target.with_file_name(name)
+        };
+        let temporary = sibling(".tmp");
+        let backup = sibling(".bak");
+
+        let mut content = self.lines.join("\n");
+        content.push('\n');
+        let mut file = File::create(&temporary).map_err(error)?;
+        file.write_all(content.as_bytes()).map_err(error)?;
+        file.sync_all().map_err(error)?;
+
+        if let Ok(metadata) = fs::metadata(&target) {
+            let _ = fs::set_permissions(&temporary, metadata.permissions());
+            fs::copy(&target, &backup).map_err(error)?;
+        }
+        fs::rename(&temporary, &target).map_err(error)
+    }
+}
+
+// the alias or variable a line such as `alias ll='ls -la'` or `export EDITOR`
+// defines. Comments and other commands define nothing.
+fn defined_name(line: &str, kind: Kind) -> Option<String> {
+    let rest = line.trim_start().strip_prefix(kind.keyword())?;
+    if !rest.starts_with([' ', '\t']) {
+        return None;
+    }
+    let rest = rest.trim_start();
+    let end = rest.find(['=', ' ', '\t', ';']).unwrap_or(rest.len());
+    let name = &rest[..end];
+    (!name.is_empty() && !name.starts_with('-')).then(|| name.to_string())
+}
+
+// `NAME` stands for an exported variable and `alias.NAME` for an alias
+fn parse_key(key: &str) -> Result<(Kind, &str), String> {
+    match key.strip_prefix("alias.") {
+        Some(name) if !name.is_empty() => Ok((Kind::Alias, name)),
+        Some(_) => Err(failure(&format!("config: '{}': missing alias name", key))),
+        None if is_name(key) => Ok((Kind::Export, key)),
+        None => Err(failure(&format!(
+            "config: '{}': not a valid variable name",
+            key
+        ))),
+    }
+}
+
+/// The `config` builtin: `path`, `get KEY`, `set KEY VALUE`, `unset KEY`
+/// and `edit`. Changes made with `set` and `unset` also apply to the
+/// running shell.
+pub fn config(args: &[String], shell: &mut Shell, io: &Io) -> Result<String, String> {
+    let usage = || failure("Usage: config path | get KEY | set KEY VALUE | unset KEY | edit");
+    let fail = |message: String| failure(&format!("config: {}", message));
+    let Some(command) = args.first() else {
+        return Err(usage());
+    };
+
+    match (command.as_str(), &args[1..]) {
+        ("path", []) => config_path()
+            .map(|path| path.display().to_string())
+            .ok_or_else(|| fail(String::from("HOME is not set"))),
+        ("get", [key]) => {
+            let (kind, name) = parse_key(key)?;
+            let rc = RcFile::open().map_err(fail)?;
+            rc.get(kind, name)
+                .ok_or_else(|| fail(format!("{}: not set", key)))
+        }
+        ("set", [key, value @ ..]) if !value.is_empty() => {
+            let (kind, name) = parse_key(key)?;
+            let value = value.join(" ");
+            match kind {
+                Kind::Alias => shell.add_alias(name.to_string(), value.clone()),
+                Kind::Export => {
+                    shell.assign_var(name, value.clone(), false).map_err(fail)?;
+                    shell.export(name);
+                }
+            }
+            save(kind, name, Some(&value)).map_err(fail)?;
+            Ok(String::new())
+        }
+        ("unset", [key]) => {
+            let (kind, name) = parse_key(key)?;
+            match kind {
+                Kind::Alias => {
+                    shell.aliases.remove(name);
+                }
+                Kind::Export if shell.is_readonly(name) => {
+                    return Err(fail(format!("{}: readonly variable", name)));
+                }
+                Kind::Export => shell.unset_env(name),
+            }
+            save(kind, name, None).map_err(fail)?;
+            Ok(String::new())
+        }
+        ("edit", []) => edit(shell, io),
+        _ => Err(usage()),
+    }
+}
+
+// opens the rc file in $VISUAL or $EDITOR, then applies the aliases and
+// exports that changed. The rest of the file is not run again, so it can't
+// append to PATH twice or repeat other commands.
+fn edit(shell: &mut Shell, io: &Io) -> Result<String, String> {
+    let fail = |message: String| failure(&format!("config: {}", message));
+    let path = config_path().ok_or_else(|| failure("config: HOME is not set"))?;
+    let before = RcFile::open().map_err(fail)?;
+    let editor = shell
+        .get_env("VISUAL")
+        .or_else(|| shell.get_env("EDITOR"))
+        .filter(|editor| !editor.trim().is_empty())
+        .cloned()
+        .unwrap_or_else(|| String::from("vi"));
+
+    let mut argv: Vec<String> = editor.split_whitespace().map(String::from).collect();
+    argv.push(path.display().to_string());
+    if run_command(&argv, shell, io) != 0 {
+        return Err(failure(&format!("config: {} failed", editor)));
+    }
+    let after = RcFile::open().map_err(fail)?;
+    apply_changes(&before, &after, shell);
+    Ok(String::new())
+}
+
+// runs the alias and export lines that are new or differ from `before`, and
+// drops the definitions whose lines were removed
+fn apply_changes(before: &RcFile, after: &RcFile, shell: &mut Shell) {
+    let io = Io {
+        stdin: Input::Inherit,
+        stdout: Output::Null,
+        stderr: Output::Terminal,
+    };
+    for kind in [Kind::Alias, Kind::Export] {
+        for name in before.names(kind) {
+            if after.find(kind, &name).is_some() {
+                continue;
+            }
+            match kind {
+                Kind::Alias => {
+                    shell.aliases.remove(&name);
+                }
+                Kind::Export if shell.is_readonly(&name) => {}
+                Kind::Export => shell.unset_env(&name),
+            }
+        }
+        for name in after.names(kind) {
+            let line = after.find(kind, &name).map(|index| &after.lines[index]);
+            let old = before.find(kind, &name).map(|index| &before.lines[index]);
+            if let Some(line) = line.filter(|&line| Some(line) != old) {
+                run_script(line, shell, &io);
+            }
+        }
+    }
+    shell.flow = Flow::Normal;
+    shell.last_status = 0;
+}
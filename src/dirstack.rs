@@ -0,0 +1,216 @@
+// The dirstack module implements the bash directory stack: `dirs`, `pushd`
+// and `popd`. Like in bash the stack as listed always starts with the
+// current directory, so entry 0 is where the shell is and entry N is the
+// Nth directory saved by `pushd`. The list is also kept in $DIRSTACK.
+use std::path::Path;
+
+use crate::{commands::change_directory, shell::Shell, utils::failure};
+
+// the listed stack: the current directory followed by the saved ones
+fn entries(shell: &Shell) -> Vec<String> {
+    let mut entries = vec![shell.current_dir().to_string_lossy().into_owned()];
+    entries.extend(shell.dir_stack().iter().cloned());
+    entries
+}
+
+// turns `+N` (counting from the left of `dirs`) or `-N` (from the right)
+// into an index of the listed stack
+fn stack_index(arg: &str, len: usize) -> Option<usize> {
+    let (from_right, digits) = match arg.split_at_checked(1)? {
+        ("+", digits) => (false, digits),
+        ("-", digits) => (true, digits),
+        _ => return None,
+    };
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let n: usize = digits.parse().ok()?;
+    if n >= len {
+        return None;
+    }
+    Some(if from_right { len - 1 - n } else { n })
+}
+
+/// Returns true for `+N` and `-N` stack references.
+pub fn is_stack_reference(arg: &str) -> bool {
+    arg.len() > 1
+        && (arg.starts_with('+') || arg.starts_with('-'))
+        && arg[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Looks up a `+N`/`-N` entry of the listed stack, for `cd -N`.
+pub fn stack_entry(arg: &str, shell: &Shell) -> Option<String> {
+    let mut entries = entries(shell);
+    stack_index(arg, entries.len()).map(|i| entries.swap_remove(i))
+}
+
+fn tilde(dir: &str, shell: &Shell) -> String {
+    let Some(home) = shell.get_env("HOME").filter(|home| !home.is_empty()) else {
+        return dir.to_string();
+    };
+    match Path::new(dir).strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => String::from("~"),
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => dir.to_string(),
+    }
+}
+
+/// Runs `dirs`: `-c` clears the stack, `-l` shows full paths instead of
+/// `~`, `-p` prints one entry per line and `-v` numbers them. `+N`/`-N`
+/// print a single entry.
+pub fn dirs(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let mut long = false;
+    let mut per_line = false;
+    let mut numbered = false;
+    let mut only = None;
+
+    for arg in args {
+        if is_stack_reference(arg) {
+            only = Some(arg.as_str());
+            continue;
+        }
+        match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() => {
+                for flag in flags.chars() {
+                    match flag {
+                        'c' => {
+                            shell.set_dir_stack(Vec::new());
+                            return Ok(String::new());
+                        }
+                        'l' => long = true,
+                        'p' => per_line = true,
+                        'v' => numbered = true,
+                        _ => {
+                            return Err(failure(&format!(
+                                "dirs: -{}: invalid option\nUsage: dirs [-clpv] [+N] [-N]",
+                                flag
+                            )))
+                        }
+                    }
+                }
+            }
+            _ => return Err(failure(&format!("dirs: {}: invalid argument", arg))),
+        }
+    }
+
+    let entries = entries(shell);
+    let show = |dir: &str| {
+        if long {
+            dir.to_string()
+        } else {
+            tilde(dir, shell)
+        }
+    };
+
+    if let Some(arg) = only {
+        return match stack_index(arg, entries.len()) {
+            Some(i) => Ok(show(&entries[i])),
+            None => Err(failure(&format!(
+                "dirs: {}: directory stack index out of range",
+                arg
+            ))),
+        };
+    }
+
+    Ok(if numbered {
+        entries
+            .iter()
+            .enumerate()
+            .map(|(i, dir)| format!("{:>2}  {}", i, show(dir)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    } else {
+        let shown: Vec<String> = entries.iter().map(|dir| show(dir)).collect();
+        shown.join(if per_line { "\n" } else { " " })
+    })
+}
+
+// changes to `dir` for pushd and installs `stack` as the saved directories.
+// The stack only changes when the directory change worked.
+fn switch_to(dir: &str, stack: Vec<String>, shell: &mut Shell) -> Result<String, String> {
+    change_directory(&[dir.to_string()], shell).map_err(|e| e.replacen("cd:", "pushd:", 1))?;
+    shell.set_dir_stack(stack);
+    dirs(&[], shell)
+}
+
+/// Runs `pushd`: `pushd DIR` saves the current directory and changes to
+/// DIR, bare `pushd` swaps the top two entries and `pushd +N`/`-N` rotates
+/// the stack so that entry comes first. `pushd -n DIR` only adds DIR to the
+/// stack.
+pub fn pushd(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let current = shell.current_dir().to_string_lossy().into_owned();
+    let mut stack = shell.dir_stack().to_vec();
+
+    match args {
+        [] => {
+            if stack.is_empty() {
+                return Err(failure("pushd: no other directory"));
+            }
+            let top = std::mem::replace(&mut stack[0], current);
+            switch_to(&top, stack, shell)
+        }
+        [arg] if is_stack_reference(arg) => {
+            let mut entries = entries(shell);
+            let Some(i) = stack_index(arg, entries.len()) else {
+                return Err(failure(&format!(
+                    "pushd: {}: directory stack index out of range",
+                    arg
+                )));
+            };
+            entries.rotate_left(i);
+            let top = entries.remove(0);
+            switch_to(&top, entries, shell)
+        }
+        [flag, dir] if flag == "-n" => {
+            stack.insert(0, dir.clone());
+            shell.set_dir_stack(stack);
+            dirs(&[], shell)
+        }
+        [dir] => {
+            stack.insert(0, current);
+            change_directory(std::slice::from_ref(dir), shell)
+                .map_err(|e| e.replacen("cd:", "pushd:", 1))?;
+            shell.set_dir_stack(stack);
+            dirs(&[], shell)
+        }
+        _ => Err(failure("pushd: too many arguments")),
+    }
+}
+
+/// Runs `popd`: removes the top entry and changes to the next one, or with
+/// `+N`/`-N` removes that entry. `-n` leaves the current directory alone.
+pub fn popd(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let no_cd = args.first().is_some_and(|arg| arg == "-n");
+    let args = if no_cd { &args[1..] } else { args };
+    let mut stack = shell.dir_stack().to_vec();
+    if stack.is_empty() {
+        return Err(failure("popd: directory stack empty"));
+    }
+
+    let index = match args {
+        [] => 0,
+        [arg] if is_stack_reference(arg) => match stack_index(arg, stack.len() + 1) {
+            Some(i) => i,
+            None => {
+                return Err(failure(&format!(
+                    "popd: {}: directory stack index out of range",
+                    arg
+                )))
+            }
+        },
+        [arg] => return Err(failure(&format!("popd: {}: invalid argument", arg))),
+        _ => return Err(failure("popd: too many arguments")),
+    };
+
+    if index == 0 && !no_cd {
+        // drop the current directory and go to the one below it
+        let top = stack.remove(0);
+        change_directory(&[top], shell).map_err(|e| e.replacen("cd:", "popd:", 1))?;
+        shell.set_dir_stack(stack);
+    } else {
+        // with -n the current directory stays, so `popd -n` drops entry 1
+        stack.remove(index.max(1) - 1);
+        shell.set_dir_stack(stack);
+    }
+    dirs(&[], shell)
+}
@@ -0,0 +1,1327 @@
+// The exec module walks the command tree produced by the parser. It wires up
+// pipelines and redirections, runs builtins in-process, calls shell functions
+// and spawns external programs.
+use std::{
+    cell::RefCell,
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    os::{
+        fd::{AsRawFd, IntoRawFd, OwnedFd, RawFd},
+        unix::{
+            fs::OpenOptionsExt,
+            process::{CommandExt, ExitStatusExt},
+        },
+    },
+    process::{Command as Process, ExitStatus, Stdio},
+    rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+use colored::*;
+use log::error;
+
+use crate::{
+    arith::evaluate,
+    commands::run_builtin,
+    conditions::conditional,
+    expand::{expand_heredoc, expand_pattern, expand_single, expand_word, glob_match},
+    parser::{
+        parse, split_assignment, split_subscript, tokenize, unquote, Command, ParseError, Redirect,
+        SimpleCommand, Token,
+    },
+    shell::{Shell, Value},
+    utils::{strip_ansi, terminal_width},
+};
+
+/// Control flow requested by `break`, `continue`, `return` or `exit`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Flow {
+    Normal,
+    Break(usize),
+    Continue(usize),
+    Return,
+    Exit,
+}
+
+/// A running `<(...)` or `>(...)` command. The shell keeps its end of the
+/// pipe open until the command that was given the `/dev/fd` path has run.
+#[derive(Clone, Copy)]
+pub struct Substitution {
+    pid: libc::pid_t,
+    fd: Option<RawFd>,
+}
+
+// A copy of the shell running in a child process, such as a pipeline stage.
+// What it writes into a `$(...)` capture comes back through temporary files,
+// since the child cannot reach the shell's buffers.
+struct Forked {
+    pid: libc::pid_t,
+    captures: Vec<Captured>,
+}
+
+// the file a forked child writes captured output to, and the buffer it goes to
+type Captured = (File, Rc<RefCell<Vec<u8>>>);
+
+impl Forked {
+    // waits for the child to exit and collects the output it captured
+    fn wait(self) -> i32 {
+        let mut status = 0;
+        // SAFETY: waits for a child this shell forked
+        unsafe { libc::waitpid(self.pid, &mut status, 0) };
+        for (file, buffer) in self.captures {
+            read_back(file, &buffer);
+        }
+        exit_code(ExitStatus::from_raw(status))
+    }
+}
+
+// an unnamed temporary file. Captured output and here-documents go through
+// one instead of a pipe, so the shell never needs a helper thread to drain or
+// feed it, which would not survive a fork.
+fn scratch_file() -> io::Result<File> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let name = format!(
+        ".batcave-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = env::temp_dir().join(name);
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
+
+// appends what was written to a scratch file to a capture buffer
+fn read_back(mut file: File, buffer: &RefCell<Vec<u8>>) {
+    let read = file
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| file.read_to_end(&mut buffer.borrow_mut()));
+    if let Err(e) = read {
+        error!("Failed to read captured output: {}", e);
+    }
+}
+
+/// Where a command reads its standard input from.
+#[derive(Clone)]
+pub enum Input {
+    Inherit,
+    File(Rc<File>),
+    Bytes(Rc<RefCell<Vec<u8>>>),
+}
+
+/// Where a command writes its output to.
+#[derive(Clone)]
+pub enum Output {
+    Terminal,
+    Null,
+    File(Rc<File>),
+    Capture(Rc<RefCell<Vec<u8>>>),
+}
+
+impl Input {
+    /// Opens the input for a builtin that reads its standard input, such as
+    /// `cat` or `wc`. A here-document can only be read once.
+    pub fn reader(&self) -> io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Input::Inherit => Box::new(io::stdin()),
+            Input::File(file) => Box::new(file.try_clone()?),
+            Input::Bytes(bytes) => {
+                Box::new(io::Cursor::new(std::mem::take(&mut *bytes.borrow_mut())))
+            }
+        })
+    }
+}
+
+impl Output {
+    fn same_as(&self, other: &Output) -> bool {
+        match (self, other) {
+            (Output::File(a), Output::File(b)) => Rc::ptr_eq(a, b),
+            (Output::Capture(a), Output::Capture(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Writes the output of a builtin. Colors are only kept on the terminal.
+    pub fn write(&self, text: &str, stderr: bool) {
+        if text.is_empty() {
+            return;
+        }
+        let newline = if text.ends_with('\n') { "" } else { "\n" };
+        let plain = || format!("{}{}", strip_ansi(text), newline);
+
+        match self {
+            Output::Terminal if stderr => eprint!("{}{}", text, newline),
+            Output::Terminal => print!("{}{}", text, newline),
+            Output::Null => {}
+            Output::File(file) => {
+                if let Err(e) = (&**file).write_all(plain().as_bytes()) {
+                    error!("Failed to write output: {}", e);
+                }
+            }
+            Output::Capture(buffer) => buffer.borrow_mut().extend(plain().as_bytes()),
+        }
+    }
+
+    /// Writes bytes exactly as given, for builtins like `cat` whose output
+    /// has to reach files and pipes unchanged.
+    pub fn write_bytes(&self, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Output::Terminal => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(bytes)?;
+                stdout.flush()
+            }
+            Output::Null => Ok(()),
+            Output::File(file) => (&**file).write_all(bytes),
+            Output::Capture(buffer) => {
+                buffer.borrow_mut().extend_from_slice(bytes);
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The standard streams a command runs with.
+#[derive(Clone)]
+pub struct Io {
+    pub stdin: Input,
+    pub stdout: Output,
+    pub stderr: Output,
+}
+
+impl Io {
+    pub fn terminal() -> Self {
+        Io {
+            stdin: Input::Inherit,
+            stdout: Output::Terminal,
+            stderr: Output::Terminal,
+        }
+    }
+
+    /// The terminal width when stdout is the terminal, for builtins that lay
+    /// out their output in columns.
+    pub fn terminal_width(&self) -> Option<usize> {
+        match self.stdout {
+            Output::Terminal => terminal_width(),
+            _ => None,
+        }
+    }
+
+    /// Ends a builtin that failed for some of its operands: the errors go
+    /// to stderr first, like ls reports them, then the output it still has
+    /// goes to stdout, so neither ends up in the other's redirection.
+    pub fn finish(&self, output: String, errors: Vec<String>) -> Result<String, String> {
+        if errors.is_empty() {
+            return Ok(output);
+        }
+        self.stderr.write(&errors.join("\n"), true);
+        self.stdout.write(&output, false);
+        Err(String::new())
+    }
+
+    // reports an error on this command's stderr
+    fn error(&self, message: &str) {
+        self.stderr
+            .write(&format!("batcave: {}", message).red().to_string(), true);
+    }
+}
+
+/// Parses and runs a script, reporting syntax errors. Returns the exit status.
+pub fn run_script(source: &str, shell: &mut Shell, io: &Io) -> i32 {
+    let Some(command) = parse_or_report(source, shell, io) else {
+        return 2;
+    };
+    let status = execute(&command, shell, io);
+    // a stray `break` or `return` ends the script, not the session. `exit`
+    // is left for the caller to act on.
+    if shell.flow != Flow::Exit {
+        shell.flow = Flow::Normal;
+    }
+    status
+}
+
+// parses a command line, reporting a syntax error with status 2
+fn parse_or_report(source: &str, shell: &mut Shell, io: &Io) -> Option<Command> {
+    let message = match parse(source) {
+        Ok(command) => return Some(command),
+        Err(ParseError::Incomplete) => String::from("syntax error: unexpected end of input"),
+        Err(ParseError::Invalid(message)) => message,
+    };
+    io.error(&message);
+    shell.last_status = 2;
+    None
+}
+
+/// Runs a command and returns what it wrote to stdout, for `$(...)`.
+pub fn capture_output(source: &str, shell: &mut Shell) -> String {
+    let buffer = Rc::new(RefCell::new(Vec::new()));
+    let io = Io {
+        stdin: Input::Inherit,
+        stdout: Output::Capture(buffer.clone()),
+        stderr: Output::Terminal,
+    };
+    let interactive = std::mem::replace(&mut shell.interactive, false);
+    run_script(source, shell, &io);
+    shell.interactive = interactive;
+    // like a subshell, `$(exit 1)` only ends the substituted command
+    shell.flow = Flow::Normal;
+    let output = String::from_utf8_lossy(&buffer.borrow()).into_owned();
+    output
+}
+
+/// Starts a process substitution: forks a copy of the shell that runs
+/// `source` writing into a pipe for `<(...)` (`input` set), or reading from
+/// one for `>(...)`. Returns the `/dev/fd` path of the shell's end.
+pub fn process_substitution(source: &str, input: bool, shell: &mut Shell) -> String {
+    let (reader, writer) = match io::pipe() {
+        Ok(pipe) => pipe,
+        Err(e) => {
+            shell.expansion_error = Some(format!("failed to create pipe: {}", e));
+            return String::new();
+        }
+    };
+    let (ours, theirs): (OwnedFd, OwnedFd) = if input {
+        (reader.into(), writer.into())
+    } else {
+        (writer.into(), reader.into())
+    };
+
+    let pipe = Rc::new(File::from(theirs));
+    let io = if input {
+        Io {
+            stdout: Output::File(pipe),
+            ..Io::terminal()
+        }
+    } else {
+        Io {
+            stdin: Input::File(pipe),
+            ..Io::terminal()
+        }
+    };
+    let forked = fork_shell(shell, &io, &[ours.as_raw_fd()], |shell, io| {
+        close_substitutions(shell);
+        run_script(source, shell, io)
+    });
+    drop(io);
+
+    match forked {
+        Ok(child) => {
+            // the fd stays close-on-exec: only the command given the path
+            // inherits it, see `substitution_fds`
+            let fd = ours.into_raw_fd();
+            shell.substitutions.push(Substitution {
+                pid: child.pid,
+                fd: Some(fd),
+            });
+            format!("/dev/fd/{}", fd)
+        }
+        Err(e) => {
+            shell.expansion_error = Some(format!("fork failed: {}", e));
+            String::new()
+        }
+    }
+}
+
+// forks a copy of the shell that calls `run` with `io` and exits with the
+// status it returns. `close` lists descriptors only the parent needs, such as
+// the read end of the pipe a pipeline stage writes into: a child that kept
+// them open would never see its reader go away.
+fn fork_shell(
+    shell: &mut Shell,
+    io: &Io,
+    close: &[RawFd],
+    run: impl FnOnce(&mut Shell, &Io) -> i32,
+) -> io::Result<Forked> {
+    let mut child_io = io.clone();
+    let mut captures = Vec::new();
+    if let Output::Capture(buffer) = &io.stdout {
+        let file = scratch_file()?;
+        child_io.stdout = Output::File(Rc::new(file.try_clone()?));
+        if io.stderr.same_as(&io.stdout) {
+            child_io.stderr = child_io.stdout.clone();
+        }
+        captures.push((file, buffer.clone()));
+    }
+    if let Output::Capture(buffer) = &io.stderr {
+        if !io.stderr.same_as(&io.stdout) {
+            let file = scratch_file()?;
+            child_io.stderr = Output::File(Rc::new(file.try_clone()?));
+            captures.push((file, buffer.clone()));
+        }
+    }
+
+    // anything still buffered would be written by both processes
+    let _ = io::stdout().flush();
+    // SAFETY: the child only runs `run` and exits without returning into
+    // the caller. Running Rust code after fork is only sound because the
+    // shell is single-threaded: captures and here-documents use scratch
+    // files rather than helper threads, and sysinfo is built without its
+    // thread pool, so no other thread can hold a lock, such as the
+    // allocator's, that the child would wait on forever.
+    match unsafe { libc::fork() } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => {
+            drop(captures);
+            for &fd in close {
+                // SAFETY: the descriptors belong to the parent's copies
+                unsafe { libc::close(fd) };
+            }
+            shell.interactive = false;
+            let status = run(shell, &child_io);
+            let _ = io::stdout().flush();
+            // SAFETY: _exit skips the parent's atexit handlers and buffers
+            unsafe { libc::_exit(status) }
+        }
+        pid => {
+            drop(child_io);
+            Ok(Forked { pid, captures })
+        }
+    }
+}
+
+// closes the shell's ends of all process substitutions in the child running
+// another one, which leaves them to the parent
+fn close_substitutions(shell: &mut Shell) {
+    for substitution in shell.substitutions.drain(..) {
+        if let Some(fd) = substitution.fd {
+            // SAFETY: the fd belongs to the parent's copy of the list
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+// closes the shell's ends of the process substitutions started since `first`,
+// once the command using them has run, then waits for them and stores their
+// statuses in PROCSUB_STATUS
+fn finish_substitutions(shell: &mut Shell, first: usize) {
+    if shell.substitutions.len() <= first {
+        return;
+    }
+    for substitution in &mut shell.substitutions[first..] {
+        if let Some(fd) = substitution.fd.take() {
+            // SAFETY: the fd was taken from the list, so it is closed once
+            unsafe { libc::close(fd) };
+        }
+    }
+    let statuses: Vec<(Option<String>, String)> = shell
+        .substitutions
+        .drain(first..)
+        .map(|substitution| {
+            let mut status = 0;
+            // SAFETY: waits for a child this shell forked
+            unsafe { libc::waitpid(substitution.pid, &mut status, 0) };
+            (None, exit_code(ExitStatus::from_raw(status)).to_string())
+        })
+        .collect();
+    let _ = shell.assign_array("PROCSUB_STATUS", statuses, false);
+}
+
+/// Runs the `name` hook: a function called `name` and/or each command in the
+/// `name_commands` array, with `args` as positional parameters. The exit
+/// status of the last user command is preserved.
+pub fn run_hook(name: &str, args: &[String], shell: &mut Shell) {
+    if shell.hooks_running.iter().any(|hook| hook == name) {
+        return;
+    }
+    let commands = shell.var(&format!("{}_commands", name)).map(Value::values);
+    if !shell.functions.contains_key(name) && commands.is_none() {
+        return;
+    }
+
+    shell.hooks_running.push(name.to_string());
+    let status = shell.last_status;
+    let saved = std::mem::replace(&mut shell.positional, args.to_vec());
+    let interactive = std::mem::replace(&mut shell.interactive, false);
+    let io = Io::terminal();
+
+    if let Some(body) = shell.functions.get(name).cloned() {
+        execute(&body, shell, &io);
+        shell.flow = Flow::Normal;
+    }
+    for command in commands.unwrap_or_default() {
+        run_script(&command, shell, &io);
+        shell.flow = Flow::Normal;
+    }
+
+    shell.positional = saved;
+    shell.interactive = interactive;
+    shell.last_status = status;
+    shell.hooks_running.retain(|hook| hook != name);
+}
+
+/// Executes a command tree and returns its exit status, which is also
+/// recorded in `shell.last_status`.
+pub fn execute(command: &Command, shell: &mut Shell, io: &Io) -> i32 {
+    let status = match command {
+        Command::Simple(simple) => {
+            let first = shell.substitutions.len();
+            let status = execute_simple(simple, shell, io);
+            finish_substitutions(shell, first);
+            status
+        }
+        Command::Pipeline(commands) => execute_pipeline(commands, shell, io),
+        Command::Not(command) => i32::from(execute(command, shell, io) == 0),
+        Command::And(left, right) => {
+            let status = execute(left, shell, io);
+            if status == 0 && shell.flow == Flow::Normal {
+                execute(right, shell, io)
+            } else {
+                status
+            }
+        }
+        Command::Or(left, right) => {
+            let status = execute(left, shell, io);
+            if status != 0 && shell.flow == Flow::Normal {
+                execute(right, shell, io)
+            } else {
+                status
+            }
+        }
+        Command::Sequence(commands) => {
+            let mut status = 0;
+            for command in commands {
+                status = execute(command, shell, io);
+                if shell.flow != Flow::Normal {
+                    break;
+                }
+            }
+            status
+        }
+        Command::Brace(body) => execute(body, shell, io),
+        Command::Subshell(body) => execute_subshell(body, shell, io),
+        Command::Redirected { command, redirects } => {
+            let first = shell.substitutions.len();
+            let status = execute_redirected(command, redirects, shell, io);
+            finish_substitutions(shell, first);
+            status
+        }
+        Command::Time(pipeline) => execute_timed(pipeline, shell, io),
+        Command::If {
+            branches,
+            otherwise,
+        } => execute_if(branches, otherwise.as_deref(), shell, io),
+        Command::Loop {
+            condition,
+            body,
+            until,
+        } => execute_loop(condition, body, *until, shell, io),
+        Command::For {
+            variable,
+            words,
+            body,
+        } => execute_for(variable, words.as_deref(), body, shell, io),
+        Command::ArithFor {
+            init,
+            condition,
+            step,
+            body,
+        } => execute_arith_for([init, condition, step], body, shell, io),
+        Command::Arith(expression) => match arithmetic(expression, shell, io) {
+            Some(value) => i32::from(value == 0),
+            None => 1,
+        },
+        Command::Conditional(words) => {
+            let result = conditional(words, shell);
+            if expansion_failed(shell, io) {
+                return 1;
+            }
+            match result {
+                Ok(true) => 0,
+                Ok(false) => 1,
+                Err(message) => {
+                    io.error(&format!("[[: {}", message));
+                    2
+                }
+            }
+        }
+        Command::Case { word, arms } => {
+            let word = expand_single(word, shell);
+            if expansion_failed(shell, io) {
+                return 1;
+            }
+            let arm = arms.iter().find(|arm| {
+                arm.patterns
+                    .iter()
+                    .any(|pattern| glob_match(&expand_pattern(pattern, shell), &word))
+            });
+            match arm {
+                Some(arm) => execute(&arm.body, shell, io),
+                None => 0,
+            }
+        }
+        Command::Function { name, body } => {
+            shell.functions.insert(name.clone(), body.clone());
+            0
+        }
+    };
+
+    shell.last_status = status;
+    status
+}
+
+fn execute_redirected(
+    command: &Command,
+    redirects: &[Redirect],
+    shell: &mut Shell,
+    io: &Io,
+) -> i32 {
+    shell.expansion_error = None;
+    let io = match apply_redirects(redirects, shell, io) {
+        Ok(io) => io,
+        Err(message) => {
+            io.error(&message);
+            return 1;
+        }
+    };
+    if expansion_failed(shell, &io) {
+        return 1;
+    }
+    execute(command, shell, &io)
+}
+
+// runs `( ... )` in a forked copy of the shell, so the variables, aliases,
+// functions and working directory it changes are dropped when it ends, and
+// `exit` only ends the subshell
+fn execute_subshell(body: &Command, shell: &mut Shell, io: &Io) -> i32 {
+    match fork_shell(shell, io, &[], |shell, io| execute(body, shell, io)) {
+        Ok(child) => child.wait(),
+        Err(e) => {
+            io.error(&format!("fork failed: {}", e));
+            1
+        }
+    }
+}
+
+// runs a pipeline and reports its real, user and system time on stderr,
+// in the same format as bash
+fn execute_timed(pipeline: &Command, shell: &mut Shell, io: &Io) -> i32 {
+    let start = Instant::now();
+    let (user_before, sys_before) = cpu_times();
+    let status = execute(pipeline, shell, io);
+    let (user_after, sys_after) = cpu_times();
+
+    io.stderr.write(
+        &format!(
+            "\nreal\t{}\nuser\t{}\nsys\t{}",
+            format_time(start.elapsed()),
+            format_time(user_after.saturating_sub(user_before)),
+            format_time(sys_after.saturating_sub(sys_before))
+        ),
+        true,
+    );
+    status
+}
+
+// user and system CPU time used so far by the shell and its finished children
+fn cpu_times() -> (Duration, Duration) {
+    let timeval = |tv: libc::timeval| {
+        Duration::from_secs(tv.tv_sec as u64) + Duration::from_micros(tv.tv_usec as u64)
+    };
+    let mut user = Duration::ZERO;
+    let mut sys = Duration::ZERO;
+
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        // SAFETY: getrusage only writes into the zeroed struct it is given
+        let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+        if unsafe { libc::getrusage(who, &mut usage) } == 0 {
+            user += timeval(usage.ru_utime);
+            sys += timeval(usage.ru_stime);
+        }
+    }
+    (user, sys)
+}
+
+fn format_time(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    format!("{}m{:.3}s", (secs / 60.0) as u64, secs % 60.0)
+}
+
+fn execute_if(
+    branches: &[(Command, Command)],
+    otherwise: Option<&Command>,
+    shell: &mut Shell,
+    io: &Io,
+) -> i32 {
+    for (condition, body) in branches {
+        let status = execute(condition, shell, io);
+        if shell.flow != Flow::Normal {
+            return status;
+        }
+        if status == 0 {
+            return execute(body, shell, io);
+        }
+    }
+    otherwise.map_or(0, |body| execute(body, shell, io))
+}
+
+// applies a `break` or `continue` aimed at the loop being run; returns true
+// if the loop should stop
+fn loop_should_stop(shell: &mut Shell) -> bool {
+    match shell.flow {
+        Flow::Normal => false,
+        Flow::Break(levels) => {
+            shell.flow = if levels > 1 {
+                Flow::Break(levels - 1)
+            } else {
+                Flow::Normal
+            };
+            true
+        }
+        Flow::Continue(levels) if levels > 1 => {
+            shell.flow = Flow::Continue(levels - 1);
+            true
+        }
+        Flow::Continue(_) => {
+            shell.flow = Flow::Normal;
+            false
+        }
+        Flow::Return | Flow::Exit => true,
+    }
+}
+
+fn execute_loop(
+    condition: &Command,
+    body: &Command,
+    until: bool,
+    shell: &mut Shell,
+    io: &Io,
+) -> i32 {
+    let mut status = 0;
+    loop {
+        let result = execute(condition, shell, io);
+        if shell.flow != Flow::Normal || (result == 0) == until {
+            break;
+        }
+        status = execute(body, shell, io);
+        if loop_should_stop(shell) {
+            break;
+        }
+    }
+    status
+}
+
+// evaluates the expression of `(( ))` or of an arithmetic for loop. Errors
+// are reported and give None.
+fn arithmetic(expression: &str, shell: &mut Shell, io: &Io) -> Option<i64> {
+    let expression = expand_single(expression, shell);
+    if expansion_failed(shell, io) {
+        return None;
+    }
+    evaluate(&expression, shell)
+        .map_err(|message| io.error(&message))
+        .ok()
+}
+
+// runs `for ((init; condition; step))`. An empty condition is always true.
+fn execute_arith_for(
+    [init, condition, step]: [&String; 3],
+    body: &Command,
+    shell: &mut Shell,
+    io: &Io,
+) -> i32 {
+    if arithmetic(init, shell, io).is_none() {
+        return 1;
+    }
+    let mut status = 0;
+    loop {
+        if !condition.trim().is_empty() {
+            match arithmetic(condition, shell, io) {
+                Some(0) => break,
+                Some(_) => {}
+                None => return 1,
+            }
+        }
+        status = execute(body, shell, io);
+        if loop_should_stop(shell) {
+            break;
+        }
+        if arithmetic(step, shell, io).is_none() {
+            return 1;
+        }
+    }
+    status
+}
+
+// reports an error raised while expanding words, such as a division by zero
+// in `$(( ))`. The command the words were for is not run.
+fn expansion_failed(shell: &mut Shell, io: &Io) -> bool {
+    match shell.expansion_error.take() {
+        Some(message) => {
+            io.error(&message);
+            true
+        }
+        None => false,
+    }
+}
+
+fn execute_for(
+    variable: &str,
+    words: Option<&[String]>,
+    body: &Command,
+    shell: &mut Shell,
+    io: &Io,
+) -> i32 {
+    let items = match words {
+        Some(words) => words
+            .iter()
+            .flat_map(|word| expand_word(word, shell))
+            .collect(),
+        None => shell.positional.clone(),
+    };
+    if expansion_failed(shell, io) {
+        return 1;
+    }
+
+    let mut status = 0;
+    for item in items {
+        if let Err(message) = shell.assign_var(variable, item, false) {
+            io.error(&message);
+            return 1;
+        }
+        status = execute(body, shell, io);
+        if loop_should_stop(shell) {
+            break;
+        }
+    }
+    status
+}
+
+// runs every stage but the last in a forked copy of the shell, connected to
+// the next stage by a pipe, so output streams through the pipeline as it is
+// written. The last stage runs in the shell itself.
+fn execute_pipeline(commands: &[Command], shell: &mut Shell, io: &Io) -> i32 {
+    let first = shell.substitutions.len();
+    let Some((last, stages)) = commands.split_last() else {
+        return 0;
+    };
+    let mut stdin = io.stdin.clone();
+    let mut forked: Vec<Forked> = Vec::new();
+
+    for command in stages {
+        let (reader, writer) = match io::pipe() {
+            Ok(pipe) => pipe,
+            Err(e) => {
+                io.error(&format!("failed to create pipe: {}", e));
+                drop(stdin);
+                for child in forked {
+                    child.wait();
+                }
+                return 1;
+            }
+        };
+        let stage = Io {
+            stdin,
+            stdout: Output::File(Rc::new(File::from(OwnedFd::from(writer)))),
+            stderr: io.stderr.clone(),
+        };
+        let close = [reader.as_raw_fd()];
+        match fork_shell(shell, &stage, &close, |shell, io| {
+            execute(command, shell, io)
+        }) {
+            Ok(child) => forked.push(child),
+            Err(e) => io.error(&format!("fork failed: {}", e)),
+        }
+        // dropping the stage closes our copies of its pipe ends, so the next
+        // stage sees end-of-file and this one stops once nobody is reading
+        drop(stage);
+        stdin = Input::File(Rc::new(File::from(OwnedFd::from(reader))));
+    }
+
+    let stage = Io {
+        stdin,
+        stdout: io.stdout.clone(),
+        stderr: io.stderr.clone(),
+    };
+    let status = execute(last, shell, &stage);
+    drop(stage);
+    // every stage is a subshell as far as `exit` is concerned
+    if shell.flow == Flow::Exit {
+        shell.flow = Flow::Normal;
+    }
+
+    for child in forked {
+        child.wait();
+    }
+    finish_substitutions(shell, first);
+    status
+}
+
+// applies the redirections of a command to a copy of its streams
+fn apply_redirects(redirects: &[Redirect], shell: &mut Shell, io: &Io) -> Result<Io, String> {
+    let mut io = io.clone();
+
+    for redirect in redirects {
+        // the delimiter of a here-document is not expanded
+        let target = match redirect.body {
+            Some(_) => redirect.target.clone(),
+            None => expand_single(&redirect.target, shell),
+        };
+        let fd = redirect
+            .fd
+            .unwrap_or(if redirect.op.starts_with('<') { 0 } else { 1 });
+        let open_error = |e: io::Error| format!("{}: {}", target, e);
+
+        match redirect.op {
+            ">" | ">|" | ">>" | "&>" => {
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .append(redirect.op == ">>")
+                    .truncate(redirect.op != ">>")
+                    .open(&target)
+                    .map_err(open_error)?;
+                let output = Output::File(Rc::new(file));
+                match (redirect.op, fd) {
+                    ("&>", _) => {
+                        io.stdout = output.clone();
+                        io.stderr = output;
+                    }
+                    (_, 1) => io.stdout = output,
+                    (_, 2) => io.stderr = output,
+                    _ => return Err(format!("{}: bad file descriptor", fd)),
+                }
+            }
+            "<" => {
+                let file = File::open(&target).map_err(open_error)?;
+                io.stdin = Input::File(Rc::new(file));
+            }
+            ">&" => match (fd, target.as_str()) {
+                (2, "1") => io.stderr = io.stdout.clone(),
+                (1, "2") => io.stdout = io.stderr.clone(),
+                (1, "1") | (2, "2") => {}
+                _ => return Err(format!("{}: unsupported redirection", target)),
+            },
+            // here-documents and here-strings are fed to the command from
+            // memory. A quoted delimiter leaves the body as it is.
+            "<<" | "<<-" | "<<<" => {
+                let text = match &redirect.body {
+                    Some(body) if unquote(&target) != target => body.clone(),
+                    Some(body) => expand_heredoc(body, shell),
+                    None => format!("{}\n", target),
+                };
+                if fd != 0 {
+                    return Err(format!("{}: bad file descriptor", fd));
+                }
+                io.stdin = Input::Bytes(Rc::new(RefCell::new(text.into_bytes())));
+            }
+            _ => return Err(format!("{}: unsupported redirection", redirect.op)),
+        }
+    }
+
+    Ok(io)
+}
+
+// replaces the command word with the text of its alias, keeping the
+// arguments as written. An alias whose value ends in a blank lets the next
+// word be an alias too. Quoted or escaped words such as `\ls` are never
+// aliases. Returns None when there is no alias, or else the command line and
+// the aliases it came from, which are not expanded again inside it.
+fn expand_aliases(words: &[String], shell: &Shell) -> Option<(String, Vec<String>)> {
+    let mut text = Vec::new();
+    let mut expanding = shell.expanding_aliases.clone();
+    let mut check = true;
+    for word in words {
+        let alias = if check {
+            alias_text(word, shell, &mut expanding)
+        } else {
+            None
+        };
+        match alias {
+            Some(value) => {
+                check = value.ends_with([' ', '\t']);
+                text.push(value);
+            }
+            None => {
+                text.push(word.clone());
+                check = false;
+            }
+        }
+    }
+    (expanding.len() > shell.expanding_aliases.len()).then(|| (text.join(" "), expanding))
+}
+
+// the text of an alias, with its first word substituted in turn unless it
+// names an alias already being expanded, as in `alias ls='ls -F'`. Returns
+// None if `word` is not an alias.
+fn alias_text(word: &str, shell: &Shell, expanding: &mut Vec<String>) -> Option<String> {
+    if expanding.iter().any(|name| name == word) {
+        return None;
+    }
+    let value = shell.get_alias(word)?.trim_start();
+    expanding.push(word.to_string());
+    let inner = match tokenize(value).unwrap_or_default().first() {
+        Some(Token::Word(first)) => value
+            .strip_prefix(first.as_str())
+            .and_then(|rest| Some(alias_text(first, shell, expanding)? + rest)),
+        _ => None,
+    };
+    Some(inner.unwrap_or_else(|| value.to_string()))
+}
+
+// the words of a command line, or None when it holds operators, as in
+// `alias up='cd ..; ls'`, and has to be parsed again
+fn alias_words(text: &str) -> Option<Vec<String>> {
+    tokenize(text)
+        .ok()?
+        .into_iter()
+        .map(|token| match token {
+            Token::Word(word) => Some(word),
+            _ => None,
+        })
+        .collect()
+}
+
+// runs a command line that an alias turned into several commands. The
+// redirections of the original command apply to all of them.
+fn execute_alias(
+    text: &str,
+    expanding: Vec<String>,
+    command: &SimpleCommand,
+    shell: &mut Shell,
+    io: &Io,
+) -> i32 {
+    let source: Vec<&str> = command
+        .assignments
+        .iter()
+        .map(String::as_str)
+        .chain([text])
+        .collect();
+    let Some(expanded) = parse_or_report(&source.join(" "), shell, io) else {
+        return 2;
+    };
+    let saved = std::mem::replace(&mut shell.expanding_aliases, expanding);
+    let status = execute_redirected(&expanded, &command.redirects, shell, io);
+    shell.expanding_aliases = saved;
+    status
+}
+
+fn execute_simple(command: &SimpleCommand, shell: &mut Shell, io: &Io) -> i32 {
+    let first = shell.substitutions.len();
+    let words = match expand_aliases(&command.words, shell) {
+        Some((text, expanding)) => match alias_words(&text) {
+            Some(words) => words,
+            None => return execute_alias(&text, expanding, command, shell, io),
+        },
+        None => command.words.clone(),
+    };
+
+    shell.expansion_error = None;
+    let mut argv: Vec<String> = Vec::new();
+    for word in &words {
+        // `declare a=(...)` gets the array literal as written, so its items
+        // are expanded like those of an assignment
+        let declaration = matches!(
+            argv.first().map(String::as_str),
+            Some("declare" | "typeset")
+        );
+        if declaration && is_array_assignment(word) {
+            argv.push(word.clone());
+        } else {
+            argv.extend(expand_word(word, shell));
+        }
+    }
+    if expansion_failed(shell, io) {
+        return 1;
+    }
+
+    let io = match apply_redirects(&command.redirects, shell, io) {
+        Ok(io) => io,
+        Err(message) => {
+            io.error(&message);
+            return 1;
+        }
+    };
+    if expansion_failed(shell, &io) {
+        return 1;
+    }
+
+    if argv.is_empty() {
+        for assignment in &command.assignments {
+            if let Err(message) = assign(assignment, shell) {
+                io.error(&message);
+                return 1;
+            }
+        }
+        return 0;
+    }
+
+    // assignments in front of a command only last for that command, and are
+    // exported to it
+    let saved: Vec<(String, Option<String>, bool)> = command
+        .assignments
+        .iter()
+        .filter_map(|assignment| split_assignment(assignment))
+        .map(|(name, _)| name.strip_suffix('+').unwrap_or(name))
+        // elements of arrays are just assigned, arrays can't be exported
+        .filter(|name| split_subscript(name).is_none())
+        .map(|name| {
+            (
+                name.to_string(),
+                shell.get_env(name).cloned(),
+                shell.is_exported(name),
+            )
+        })
+        .collect();
+    for assignment in &command.assignments {
+        if let Err(message) = assign(assignment, shell) {
+            io.error(&message);
+            return 1;
+        }
+    }
+    for (name, _, _) in &saved {
+        shell.export(name);
+    }
+
+    // the programs the command runs inherit the `/dev/fd` paths it was given
+    let inherited = shell.substitution_fds.clone();
+    let fds = shell.substitutions[first..].iter().filter_map(|s| s.fd);
+    shell.substitution_fds.extend(fds);
+    let status = run_command(&argv, shell, &io);
+    shell.substitution_fds = inherited;
+
+    for (name, value, exported) in saved {
+        match value {
+            Some(value) => shell.set_env(name.clone(), value),
+            None => {
+                shell.vars.remove(&name);
+            }
+        }
+        if !exported {
+            shell.exported.remove(&name);
+        }
+    }
+    status
+}
+
+// whether a word is a `name=(...)` array assignment
+fn is_array_assignment(word: &str) -> bool {
+    split_assignment(word).is_some_and(|(name, value)| {
+        split_subscript(name).is_none() && value.starts_with('(') && value.ends_with(')')
+    })
+}
+
+/// Expands the items between the parentheses of an array literal.
+/// `[key]=value` items are expanded without splitting, the others like
+/// command arguments.
+pub fn array_items(list: &str, shell: &mut Shell) -> Result<Vec<(Option<String>, String)>, String> {
+    let mut items = Vec::new();
+    for token in tokenize(list).unwrap_or_default() {
+        let Token::Word(word) = token else {
+            continue;
+        };
+        match word
+            .strip_prefix('[')
+            .and_then(|rest| rest.split_once("]="))
+        {
+            Some((key, item)) => {
+                items.push((Some(expand_single(key, shell)), expand_single(item, shell)))
+            }
+            None => items.extend(
+                expand_word(&word, shell)
+                    .into_iter()
+                    .map(|item| (None, item)),
+            ),
+        }
+    }
+    match shell.expansion_error.take() {
+        Some(message) => Err(message),
+        None => Ok(items),
+    }
+}
+
+// performs `name=value`, `name+=value`, `name[key]=value` and
+// `name=(a b c)` assignments
+fn assign(assignment: &str, shell: &mut Shell) -> Result<(), String> {
+    let Some((name, value)) = split_assignment(assignment) else {
+        return Ok(());
+    };
+    let (name, append) = match name.strip_suffix('+') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+
+    if let Some((name, subscript)) = split_subscript(name) {
+        let subscript = expand_single(subscript, shell);
+        let value = expand_single(value, shell);
+        if let Some(message) = shell.expansion_error.take() {
+            return Err(message);
+        }
+        return shell.set_element(name, &subscript, value, append);
+    }
+
+    if let Some(list) = value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+        let items = array_items(list, shell)?;
+        return shell.assign_array(name, items, append);
+    }
+
+    let value = expand_single(value, shell);
+    if let Some(message) = shell.expansion_error.take() {
+        return Err(message);
+    }
+    shell.assign_var(name, value, append)
+}
+
+/// Runs a function, builtin or external command with expanded arguments.
+pub fn run_command(argv: &[String], shell: &mut Shell, io: &Io) -> i32 {
+    // `command NAME ARGS` skips functions, and aliases since NAME is not the
+    // first word
+    if argv[0] == "command" {
+        return match argv.get(1) {
+            Some(option) if option.starts_with('-') && option.len() > 1 => {
+                io.error(&format!("command: {}: options are not supported", option));
+                shell.last_status = 2;
+                2
+            }
+            Some(_) => run_program(&argv[1..], shell, io),
+            None => 0,
+        };
+    }
+
+    if let Some(body) = shell.functions.get(&argv[0]).cloned() {
+        let saved = std::mem::replace(&mut shell.positional, argv[1..].to_vec());
+        let interactive = std::mem::replace(&mut shell.interactive, false);
+        let status = execute(&body, shell, io);
+        shell.positional = saved;
+        shell.interactive = interactive;
+        if shell.flow == Flow::Return {
+            shell.flow = Flow::Normal;
+            return shell.last_status;
+        }
+        return status;
+    }
+
+    run_program(argv, shell, io)
+}
+
+// runs a builtin or an external program
+fn run_program(argv: &[String], shell: &mut Shell, io: &Io) -> i32 {
+    let cwd = env::current_dir().ok();
+    // `return` and `exit` without a number keep the status of the command
+    // before them
+    if !matches!(argv[0].as_str(), "return" | "exit") {
+        shell.last_status = 0;
+    }
+    if let Some(result) = run_builtin(argv, shell, io) {
+        match result {
+            Ok(output) => io.stdout.write(&output, false),
+            Err(message) => io.stderr.write(&message, true),
+        }
+        let status = shell.last_status;
+        if env::current_dir().ok() != cwd {
+            run_hook("chpwd", &[], shell);
+        }
+        return status;
+    }
+
+    run_external(argv, shell, io)
+}
+
+// here-documents and here-strings are read from a scratch file
+fn stdin_for(input: &Input) -> io::Result<Stdio> {
+    Ok(match input {
+        Input::Inherit => Stdio::inherit(),
+        Input::File(file) => Stdio::from(file.try_clone()?),
+        Input::Bytes(bytes) => {
+            let mut file = scratch_file()?;
+            file.write_all(&std::mem::take(&mut *bytes.borrow_mut()))?;
+            file.seek(SeekFrom::Start(0))?;
+            Stdio::from(file)
+        }
+    })
+}
+
+// creates the Stdio for an output stream. Captured output goes to the
+// returned scratch file, to be read back once the command has exited.
+fn stdio_for(output: &Output) -> io::Result<(Stdio, Option<File>)> {
+    Ok(match output {
+        Output::Terminal => (Stdio::inherit(), None),
+        Output::Null => (Stdio::null(), None),
+        Output::File(file) => (Stdio::from(file.try_clone()?), None),
+        Output::Capture(_) => {
+            let file = scratch_file()?;
+            (Stdio::from(file.try_clone()?), Some(file))
+        }
+    })
+}
+
+fn run_external(argv: &[String], shell: &Shell, io: &Io) -> i32 {
+    let spawned = (|| -> io::Result<_> {
+        let stdin = stdin_for(&io.stdin)?;
+        let (stdout, stdout_capture) = stdio_for(&io.stdout)?;
+        let (stderr, stderr_capture) = match &stdout_capture {
+            // `2>&1` into a capture shares the capture file
+            Some(file) if io.stderr.same_as(&io.stdout) => (Stdio::from(file.try_clone()?), None),
+            _ => stdio_for(&io.stderr)?,
+        };
+
+        let mut process = Process::new(&argv[0]);
+        process
+            .args(&argv[1..])
+            .env_clear()
+            .envs(shell.exported_vars())
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(stderr);
+        let fds = shell.substitution_fds.clone();
+        if !fds.is_empty() {
+            // SAFETY: fcntl is async-signal-safe and only changes the flags
+            // of descriptors the shell owns
+            unsafe {
+                process.pre_exec(move || {
+                    for &fd in &fds {
+                        libc::fcntl(fd, libc::F_SETFD, 0);
+                    }
+                    Ok(())
+                });
+            }
+        }
+        let child = process.spawn()?;
+        Ok((child, stdout_capture, stderr_capture))
+    })();
+
+    let (mut child, stdout_capture, stderr_capture) = match spawned {
+        Ok(spawned) => spawned,
+        Err(e) => {
+            error!("Failed to execute {}: {}", argv[0], e);
+            // a name without a slash was looked up in PATH
+            let not_found = e.kind() == io::ErrorKind::NotFound;
+            if not_found && !argv[0].contains('/') {
+                io.error(&format!("{}: command not found", argv[0]));
+            } else {
+                io.error(&format!("{}: {}", argv[0], e));
+            }
+            return if not_found { 127 } else { 126 };
+        }
+    };
+
+    let waited = child.wait();
+    for (capture, output) in [(stdout_capture, &io.stdout), (stderr_capture, &io.stderr)] {
+        if let (Some(file), Output::Capture(buffer)) = (capture, output) {
+            read_back(file, buffer);
+        }
+    }
+
+    match waited {
+        Ok(status) => exit_code(status),
+        Err(e) => {
+            io.error(&format!("{}: {}", argv[0], e));
+            1
+        }
+    }
+}
+
+// Converts a process exit status to a shell status, using 128 + N for
+// processes killed by signal N
+fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1)
+}
@@ -0,0 +1,659 @@
+// The expand module turns the raw words produced by the parser into the
+// arguments a command is run with: tilde and parameter expansion, command
+// substitution, splitting of unquoted results into fields and quote removal.
+use std::{fs, path::Path};
+
+use crate::{
+    arith::evaluate,
+    exec::{capture_output, process_substitution},
+    parser::{is_name, split_subscript},
+    shell::{Shell, Value},
+};
+
+// the characters escaped in the patterns of quoted text, for globs and for
+// the regexes of `[[ =~ ]]`
+const GLOB_META: &str = "*?[]\\";
+const REGEX_META: &str = "\\.+*?()|[]{}^$";
+
+// Collects the fields a single word expands to. Alongside the text of each
+// field it keeps a pattern in which quoted characters are escaped, so only
+// unquoted `*`, `?` and `[` trigger pathname expansion.
+struct Fields {
+    fields: Vec<(String, String, bool)>,
+    current: String,
+    pattern: String,
+    meta: &'static str,
+    glob: bool,
+    started: bool,
+    // set by a quoted list expansion without items, like "$@" without
+    // arguments, which leaves no field behind unless the word has more text
+    vanished: bool,
+}
+
+impl Fields {
+    fn new(meta: &'static str) -> Self {
+        Fields {
+            fields: Vec::new(),
+            current: String::new(),
+            pattern: String::new(),
+            meta,
+            glob: false,
+            started: false,
+            vanished: false,
+        }
+    }
+
+    // adds quoted text, which is never treated as a pattern
+    fn push_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.push_char(c);
+        }
+        self.started = true;
+    }
+
+    fn push_char(&mut self, c: char) {
+        self.vanished = false;
+        self.current.push(c);
+        if self.meta.contains(c) {
+            self.pattern.push('\\');
+        }
+        self.pattern.push(c);
+        self.started = true;
+    }
+
+    // adds unquoted text, whose wildcards are expanded against the filesystem
+    fn push_glob_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.vanished = false;
+            self.current.push(c);
+            self.pattern.push(c);
+            self.glob |= matches!(c, '*' | '?' | '[');
+        }
+        self.started = true;
+    }
+
+    // unquoted expansion results are split on whitespace. Without splitting
+    // they still count as pattern text, as in `[[ $file == $pattern ]]`.
+    fn push_unquoted(&mut self, text: &str, split: bool) {
+        if !split {
+            self.push_glob_str(text);
+            return;
+        }
+        for (i, part) in text.split([' ', '\t', '\n']).enumerate() {
+            if i > 0 {
+                self.end_field();
+            }
+            if !part.is_empty() {
+                self.push_glob_str(part);
+            }
+        }
+    }
+
+    // "$@" keeps every item as its own field
+    fn push_list(&mut self, items: &[String]) {
+        if items.is_empty() && self.current.is_empty() {
+            self.vanished = true;
+        }
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 {
+                self.end_field();
+            }
+            self.push_str(item);
+        }
+    }
+
+    fn end_field(&mut self) {
+        if self.started && !self.vanished {
+            let pattern = std::mem::take(&mut self.pattern);
+            let text = std::mem::take(&mut self.current);
+            self.fields.push((text, pattern, self.glob));
+            self.glob = false;
+            self.started = false;
+        }
+        self.vanished = false;
+    }
+
+    // finishes the last field, replacing patterns with the paths they match.
+    // Patterns that match nothing are left as they are.
+    fn finish(mut self, glob: bool) -> Vec<String> {
+        self.end_field();
+        let mut fields = Vec::new();
+        for (text, pattern, is_glob) in self.fields {
+            let matches = if glob && is_glob {
+                expand_pathname(&pattern)
+            } else {
+                Vec::new()
+            };
+            if matches.is_empty() {
+                fields.push(text);
+            } else {
+                fields.extend(matches);
+            }
+        }
+        fields
+    }
+}
+
+/// Expands a word into zero or more fields.
+pub fn expand_word(word: &str, shell: &mut Shell) -> Vec<String> {
+    expand(word, shell, true)
+}
+
+/// Expands a word into exactly one string, without field splitting. Used for
+/// assignments, redirection targets and `case` words.
+pub fn expand_single(word: &str, shell: &mut Shell) -> String {
+    expand(word, shell, false).join(" ")
+}
+
+/// Expands a word into a pattern for `case` and `[[ == ]]`, in which quoted
+/// characters only match themselves.
+pub fn expand_pattern(word: &str, shell: &mut Shell) -> String {
+    expand_escaped(word, shell, GLOB_META)
+}
+
+/// Expands the regex of `[[ =~ ]]`, in which quoted characters only match
+/// themselves.
+pub fn expand_regex(word: &str, shell: &mut Shell) -> String {
+    expand_escaped(word, shell, REGEX_META)
+}
+
+fn expand_escaped(word: &str, shell: &mut Shell, meta: &'static str) -> String {
+    let mut fields = Fields::new(meta);
+    expand_into(word, shell, false, &mut fields);
+    fields.end_field();
+    let patterns: Vec<String> = fields
+        .fields
+        .into_iter()
+        .map(|(_, pattern, _)| pattern)
+        .collect();
+    patterns.join(" ")
+}
+
+fn expand(word: &str, shell: &mut Shell, split: bool) -> Vec<String> {
+    let mut fields = Fields::new(GLOB_META);
+    expand_into(word, shell, split, &mut fields);
+    fields.finish(split)
+}
+
+fn expand_into(word: &str, shell: &mut Shell, split: bool, fields: &mut Fields) {
+    let chars: Vec<char> = word.chars().collect();
+    let mut in_double = false;
+    let mut i = 0;
+
+    if chars.first() == Some(&'~') && matches!(chars.get(1), None | Some('/')) {
+        let home = shell.get_env("HOME").cloned().unwrap_or_default();
+        fields.push_str(&home);
+        i = 1;
+    }
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\'' if !in_double => {
+                fields.push_str("");
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    fields.push_char(chars[i]);
+                    i += 1;
+                }
+                i += 1;
+            }
+            '"' => {
+                fields.push_str("");
+                in_double = !in_double;
+                i += 1;
+            }
+            '\\' => {
+                i += 1;
+                match chars.get(i) {
+                    Some(&next) if !in_double || matches!(next, '$' | '`' | '"' | '\\') => {
+                        fields.push_char(next);
+                    }
+                    Some('\n') => {}
+                    Some(&next) => {
+                        fields.push_char('\\');
+                        fields.push_char(next);
+                    }
+                    None => fields.push_char('\\'),
+                }
+                i += 1;
+            }
+            '`' => {
+                i = expand_backticks(&chars, i, fields, in_double, split, shell);
+            }
+            '$' => {
+                i = expand_dollar(&chars, i, fields, in_double, split, shell);
+            }
+            '<' | '>' if !in_double && chars.get(i + 1) == Some(&'(') => {
+                let end = find_closing(&chars, i + 2, '(', ')').unwrap_or(chars.len());
+                let source: String = chars[i + 2..end].iter().collect();
+                let path = process_substitution(&source, c == '<', shell);
+                fields.push_str(&path);
+                i = end + 1;
+            }
+            _ if in_double => {
+                fields.push_char(c);
+                i += 1;
+            }
+            _ => {
+                fields.push_glob_str(c.encode_utf8(&mut [0; 4]));
+                i += 1;
+            }
+        }
+    }
+}
+
+/// Expands the body of a here-document whose delimiter is unquoted. It works
+/// like double-quoted text, except that quotes are plain characters.
+pub fn expand_heredoc(body: &str, shell: &mut Shell) -> String {
+    let chars: Vec<char> = body.chars().collect();
+    let mut fields = Fields::new(GLOB_META);
+    let mut i = 0;
+
+    while i < chars.len() {
+        match (chars[i], chars.get(i + 1)) {
+            ('\\', Some('\n')) => i += 2,
+            ('\\', Some(&next)) if matches!(next, '$' | '`' | '\\') => {
+                fields.push_char(next);
+                i += 2;
+            }
+            ('`', _) => i = expand_backticks(&chars, i, &mut fields, true, false, shell),
+            ('$', _) => i = expand_dollar(&chars, i, &mut fields, true, false, shell),
+            (c, _) => {
+                fields.push_char(c);
+                i += 1;
+            }
+        }
+    }
+
+    fields.finish(false).join(" ")
+}
+
+// runs the `...` command substitution starting at `start` and returns the
+// index after the closing backtick
+fn expand_backticks(
+    chars: &[char],
+    start: usize,
+    fields: &mut Fields,
+    quoted: bool,
+    split: bool,
+    shell: &mut Shell,
+) -> usize {
+    let mut end = start + 1;
+    while end < chars.len() && chars[end] != '`' {
+        end += if chars[end] == '\\' { 2 } else { 1 };
+    }
+    let source: String = chars[start + 1..end.min(chars.len())].iter().collect();
+    let source = source.replace("\\`", "`");
+    let output = command_substitution(&source, shell);
+    push_expansion(fields, &output, quoted, split);
+    end + 1
+}
+
+fn push_expansion(fields: &mut Fields, value: &str, quoted: bool, split: bool) {
+    if quoted {
+        fields.push_str(value);
+    } else {
+        fields.push_unquoted(value, split);
+    }
+}
+
+// finds the index of the bracket closing the one just before `start`,
+// skipping over quoted text
+fn find_closing(chars: &[char], start: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 1;
+    let mut i = start;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '\'' => {
+                i += 1;
+                while i < chars.len() && chars[i] != '\'' {
+                    i += 1;
+                }
+            }
+            c if c == open => depth += 1,
+            c if c == close => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// finds the end of `$(( ... ))` starting at `start`. `$((cmd) | other)` is
+// a command substitution instead.
+fn arithmetic_end(chars: &[char], start: usize) -> Option<usize> {
+    if chars.get(start + 2) != Some(&'(') {
+        return None;
+    }
+    let end = find_closing(chars, start + 2, '(', ')')?;
+    (end - 1 > start + 2 && chars[end - 1] == ')').then_some(end)
+}
+
+// expands the `$` construct starting at `start` and returns the index just
+// after it
+fn expand_dollar(
+    chars: &[char],
+    start: usize,
+    fields: &mut Fields,
+    quoted: bool,
+    split: bool,
+    shell: &mut Shell,
+) -> usize {
+    let next = chars.get(start + 1).copied();
+
+    match next {
+        Some('(') if arithmetic_end(chars, start).is_some() => {
+            let end = arithmetic_end(chars, start).unwrap_or(start);
+            // the expression is expanded first, so `$((x + $y))` works too
+            let source: String = chars[start + 3..end - 1].iter().collect();
+            let expression = expand_single(&source, shell);
+            let value = match evaluate(&expression, shell) {
+                Ok(value) => value.to_string(),
+                Err(message) => {
+                    shell.expansion_error.get_or_insert(message);
+                    String::new()
+                }
+            };
+            push_expansion(fields, &value, quoted, split);
+            end + 1
+        }
+        Some('(') => {
+            let Some(end) = find_closing(chars, start + 2, '(', ')') else {
+                fields.push_str(&chars[start..].iter().collect::<String>());
+                return chars.len();
+            };
+            let source: String = chars[start + 2..end].iter().collect();
+            let output = command_substitution(&source, shell);
+            push_expansion(fields, &output, quoted, split);
+            end + 1
+        }
+        Some('{') => {
+            let Some(end) = find_closing(chars, start + 2, '{', '}') else {
+                fields.push_str(&chars[start..].iter().collect::<String>());
+                return chars.len();
+            };
+            let inner: String = chars[start + 2..end].iter().collect();
+            if inner == "@" {
+                push_positional(fields, quoted, split, shell);
+            } else if let Some(name) = inner.strip_suffix("[@]").filter(|name| is_name(name)) {
+                // "${arr[@]}" gives one field per element
+                let items = shell.var(name).map(Value::values).unwrap_or_default();
+                push_items(fields, &items, quoted, split);
+            } else if let Some(name) = inner
+                .strip_prefix('!')
+                .and_then(|name| name.strip_suffix("[@]"))
+                .filter(|name| is_name(name))
+            {
+                let keys = shell.var(name).map(Value::keys).unwrap_or_default();
+                push_items(fields, &keys, quoted, split);
+            } else {
+                let value = expand_braced(&inner, shell);
+                push_expansion(fields, &value, quoted, split);
+            }
+            end + 1
+        }
+        Some('@') => {
+            push_positional(fields, quoted, split, shell);
+            start + 2
+        }
+        Some(c) if matches!(c, '?' | '#' | '$' | '*' | '!' | '-') || c.is_ascii_digit() => {
+            let value = shell.get_param(&c.to_string()).unwrap_or_default();
+            push_expansion(fields, &value, quoted, split);
+            start + 2
+        }
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut end = start + 1;
+            while end < chars.len() && (chars[end].is_ascii_alphanumeric() || chars[end] == '_') {
+                end += 1;
+            }
+            let name: String = chars[start + 1..end].iter().collect();
+            let value = shell.get_param(&name).unwrap_or_default();
+            push_expansion(fields, &value, quoted, split);
+            end
+        }
+        // a lone `$` stays, and still anchors a `[[ =~ ]]` regex when unquoted
+        _ => {
+            if quoted {
+                fields.push_char('$');
+            } else {
+                fields.push_glob_str("$");
+            }
+            start + 1
+        }
+    }
+}
+
+fn push_positional(fields: &mut Fields, quoted: bool, split: bool, shell: &Shell) {
+    let items = shell.positional.clone();
+    push_items(fields, &items, quoted, split);
+}
+
+// expands a list like "$@" does: one field per item when quoted
+fn push_items(fields: &mut Fields, items: &[String], quoted: bool, split: bool) {
+    if quoted {
+        fields.push_list(items);
+    } else {
+        for (i, item) in items.iter().enumerate() {
+            if i > 0 && split {
+                fields.end_field();
+            } else if i > 0 {
+                fields.push_char(' ');
+            }
+            fields.push_unquoted(item, split);
+        }
+    }
+}
+
+// handles `${name}`, `${#name}`, `${name[i]}`, `${#name[@]}`, `${!name[*]}`
+// and the `:-`, `-`, `:=`, `:+` operators
+fn expand_braced(inner: &str, shell: &mut Shell) -> String {
+    if let Some((prefix, name, subscript)) = split_subscript(inner)
+        .map(|(name, subscript)| ("", name, subscript))
+        .or_else(|| {
+            let (prefix, rest) = inner.split_at_checked(1)?;
+            let (name, subscript) = split_subscript(rest)?;
+            matches!(prefix, "#" | "!").then_some((prefix, name, subscript))
+        })
+    {
+        let value = shell.var(name).cloned();
+        return match (prefix, subscript) {
+            ("#", "@" | "*") => value.map_or(0, |value| value.values().len()).to_string(),
+            ("!", "@" | "*") => value
+                .map(|value| value.keys().join(" "))
+                .unwrap_or_default(),
+            (_, "*") => value
+                .map(|value| value.values().join(" "))
+                .unwrap_or_default(),
+            (_, subscript) => {
+                let subscript = expand_single(subscript, shell);
+                let item = match shell.get_element(name, &subscript) {
+                    Ok(item) => item.unwrap_or_default(),
+                    Err(message) => {
+                        shell.expansion_error.get_or_insert(message);
+                        String::new()
+                    }
+                };
+                match prefix {
+                    "#" => item.chars().count().to_string(),
+                    _ => item,
+                }
+            }
+        };
+    }
+
+    if let Some(name) = inner.strip_prefix('#') {
+        if !name.is_empty() {
+            return shell
+                .get_param(name)
+                .unwrap_or_default()
+                .chars()
+                .count()
+                .to_string();
+        }
+    }
+
+    let name_end = inner
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(inner.len())
+        .max(1.min(inner.len()));
+    let (name, rest) = inner.split_at(name_end);
+    let value = shell.get_param(name);
+
+    let (op, word) = if let Some(word) = rest.strip_prefix(":-") {
+        (":-", word)
+    } else if let Some(word) = rest.strip_prefix(":=") {
+        (":=", word)
+    } else if let Some(word) = rest.strip_prefix(":+") {
+        (":+", word)
+    } else if let Some(word) = rest.strip_prefix('-') {
+        ("-", word)
+    } else {
+        return value.unwrap_or_default();
+    };
+
+    let is_set = value.is_some();
+    let is_empty = value.as_deref().is_none_or(str::is_empty);
+    match op {
+        ":-" if is_empty => expand_single(word, shell),
+        "-" if !is_set => expand_single(word, shell),
+        ":=" if is_empty => {
+            let default = expand_single(word, shell);
+            let _ = shell.assign_var(name, default.clone(), false);
+            default
+        }
+        ":+" if !is_empty => expand_single(word, shell),
+        ":+" => String::new(),
+        _ => value.unwrap_or_default(),
+    }
+}
+
+fn command_substitution(source: &str, shell: &mut Shell) -> String {
+    let mut output = capture_output(source, shell);
+    while output.ends_with('\n') {
+        output.pop();
+    }
+    output
+}
+
+/// Expands a pathname pattern such as `src/*.rs` into the sorted list of
+/// matching paths. Hidden files only match a pattern that starts with `.`.
+pub fn expand_pathname(pattern: &str) -> Vec<String> {
+    let absolute = pattern.starts_with('/');
+    let mut paths = vec![if absolute {
+        String::from("/")
+    } else {
+        String::new()
+    }];
+
+    for component in pattern.split('/').filter(|component| !component.is_empty()) {
+        let has_glob = {
+            let mut escaped = false;
+            component.chars().any(|c| {
+                let special = !escaped && matches!(c, '*' | '?' | '[');
+                escaped = !escaped && c == '\\';
+                special
+            })
+        };
+
+        let mut next = Vec::new();
+        for base in &paths {
+            if !has_glob {
+                next.push(format!("{}{}/", base, unescape(component)));
+                continue;
+            }
+            let dir = if base.is_empty() { "." } else { base.as_str() };
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            let mut names: Vec<String> = entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| glob_match(component, name))
+                .collect();
+            names.sort();
+            next.extend(names.into_iter().map(|name| format!("{}{}/", base, name)));
+        }
+        paths = next;
+    }
+
+    let trailing_slash = pattern.ends_with('/');
+    paths
+        .into_iter()
+        .map(|mut path| {
+            if !trailing_slash && path.len() > 1 {
+                path.pop();
+            }
+            path
+        })
+        .filter(|path| Path::new(path).symlink_metadata().is_ok())
+        .collect()
+}
+
+fn unescape(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => plain.extend(chars.next()),
+            c => plain.push(c),
+        }
+    }
+    plain
+}
+
+/// Matches text against a shell pattern with `*`, `?` and `[...]` classes.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_from(&pattern, &text)
+}
+
+fn glob_match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') => (0..=text.len()).any(|i| glob_match_from(&pattern[1..], &text[i..])),
+        Some('?') => !text.is_empty() && glob_match_from(&pattern[1..], &text[1..]),
+        Some('[') => {
+            let Some(close) = pattern
+                .iter()
+                .skip(2)
+                .position(|&c| c == ']')
+                .map(|i| i + 2)
+            else {
+                return text.first() == Some(&'[') && glob_match_from(&pattern[1..], &text[1..]);
+            };
+            let Some(&c) = text.first() else {
+                return false;
+            };
+            let class = &pattern[1..close];
+            let (negate, class) = match class.first() {
+                Some('!') | Some('^') => (true, &class[1..]),
+                _ => (false, class),
+            };
+            let mut matched = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == '-' {
+                    matched |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    matched |= class[i] == c;
+                    i += 1;
+                }
+            }
+            matched != negate && glob_match_from(&pattern[close + 1..], &text[1..])
+        }
+        Some('\\') if pattern.len() > 1 => {
+            text.first() == Some(&pattern[1]) && glob_match_from(&pattern[2..], &text[1..])
+        }
+        Some(&p) => text.first() == Some(&p) && glob_match_from(&pattern[1..], &text[1..]),
+    }
+}
@@ -0,0 +1,822 @@
+// The explore module implements `tree`, `du` and `find`. They are native so
+// they behave the same on minimal systems without coreutils or findutils.
+use std::{
+    collections::HashSet,
+    fs::{self, Metadata},
+    io,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use log::error;
+
+use crate::{
+    exec::{run_command, Io},
+    expand::glob_match,
+    fileops::absolute_path,
+    ls::LsColors,
+    shell::Shell,
+    utils::{failure, human_size},
+};
+
+struct Entry {
+    name: String,
+    path: PathBuf,
+    metadata: Metadata,
+}
+
+// reads a directory sorted by name the way ls sorts it
+fn read_sorted(dir: &Path) -> io::Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.path().symlink_metadata().ok()?;
+            Some(Entry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                path: entry.path(),
+                metadata,
+            })
+        })
+        .collect();
+    entries.sort_by(|a, b| {
+        let key = |name: &str| name.trim_start_matches('.').to_lowercase();
+        key(&a.name)
+            .cmp(&key(&b.name))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    Ok(entries)
+}
+
+fn parse_number(command: &str, option: &str, value: Option<&String>) -> Result<usize, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| failure(&format!("{}: {} needs a number", command, option)))
+}
+
+struct IgnoreRule {
+    pattern: String,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+/// The .gitignore rules that apply while walking a tree. Each directory
+/// level adds the rules of its own .gitignore, and like in git the last
+/// matching rule decides.
+struct Ignores {
+    levels: Vec<(PathBuf, Vec<IgnoreRule>)>,
+}
+
+impl Ignores {
+    // starts with the .gitignore files from the enclosing repository root
+    // down to `root`, so running in a subdirectory honors them too
+    fn new(root: &Path) -> Self {
+        let mut ignores = Ignores { levels: Vec::new() };
+        let root = absolute_path(root);
+        let repo = root.ancestors().find(|dir| dir.join(".git").exists());
+        if let Some(repo) = repo {
+            let mut parents: Vec<&Path> = root
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(repo))
+                .collect();
+            parents.reverse();
+            for dir in parents {
+                ignores.enter(dir);
+            }
+            let exclude = fs::read_to_string(repo.join(".git/info/exclude")).unwrap_or_default();
+            ignores
+                .levels
+                .insert(0, (repo.to_path_buf(), parse_gitignore(&exclude)));
+        }
+        ignores
+    }
+
+    fn enter(&mut self, dir: &Path) {
+        let content = fs::read_to_string(dir.join(".gitignore")).unwrap_or_default();
+        self.levels
+            .push((absolute_path(dir), parse_gitignore(&content)));
+    }
+
+    fn leave(&mut self) {
+        self.levels.pop();
+    }
+
+    fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = absolute_path(path);
+        if path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        let mut ignored = false;
+        for (base, rules) in &self.levels {
+            let Ok(relative) = path.strip_prefix(base) else {
+                continue;
+            };
+            let relative = relative.to_string_lossy();
+            for rule in rules {
+                if rule.dir_only && !is_dir {
+                    continue;
+                }
+                let subject = if rule.anchored { &relative } else { &*name };
+                if glob_match(&rule.pattern, subject) {
+                    ignored = !rule.negated;
+                }
+            }
+        }
+        ignored
+    }
+}
+
+fn parse_gitignore(content: &str) -> Vec<IgnoreRule> {
+    content
+        .lines()
+        .map(str::trim_end)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let line = line.strip_prefix("**/").unwrap_or(line);
+            IgnoreRule {
+                anchored: line.contains('/'),
+                pattern: line.trim_start_matches('/').to_string(),
+                negated,
+                dir_only,
+            }
+        })
+        .collect()
+}
+
+struct TreeOptions {
+    all: bool,
+    dirs_only: bool,
+    max_depth: Option<usize>,
+    gitignore: bool,
+    excludes: Vec<String>,
+}
+
+struct Tree {
+    options: TreeOptions,
+    colors: LsColors,
+    ignores: Option<Ignores>,
+    lines: Vec<String>,
+    dirs: usize,
+    files: usize,
+}
+
+/// Runs `tree`: `-a` shows hidden files, `-d` only directories, `-L N`
+/// limits the depth, `-I PATTERN` skips matching names and `--gitignore`
+/// skips whatever git would ignore.
+pub fn tree(args: &[String], shell: &Shell, io: &Io) -> Result<String, String> {
+    let mut options = TreeOptions {
+        all: false,
+        dirs_only: false,
+        max_depth: None,
+        gitignore: false,
+        excludes: Vec::new(),
+    };
+    let mut roots = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-a" => options.all = true,
+            "-d" => options.dirs_only = true,
+            "-L" => options.max_depth = Some(parse_number("tree", "-L", args.next())?),
+            "-I" => match args.next() {
+                Some(pattern) => options
+                    .excludes
+                    .extend(pattern.split('|').map(String::from)),
+                None => return Err(failure("tree: -I needs a pattern")),
+            },
+            "--gitignore" => options.gitignore = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(failure(&format!("tree: invalid option '{}'", arg)))
+            }
+            _ => roots.push(arg.clone()),
+        }
+    }
+    if roots.is_empty() {
+        roots.push(String::from("."));
+    }
+
+    let mut tree = Tree {
+        options,
+        colors: LsColors::from_shell(shell),
+        ignores: None,
+        lines: Vec::new(),
+        dirs: 0,
+        files: 0,
+    };
+    let mut errors = Vec::new();
+
+    for root in &roots {
+        let path = Path::new(root);
+        match path.metadata() {
+            Ok(metadata) if metadata.is_dir() => {
+                tree.lines
+                    .push(tree.colors.paint_name(root, path, &metadata));
+                if tree.options.gitignore {
+                    let mut ignores = Ignores::new(path);
+                    ignores.enter(path);
+                    tree.ignores = Some(ignores);
+                }
+                tree.walk(path, "", 1);
+            }
+            Ok(_) => {
+                tree.lines.push(format!("{}  [not a directory]", root));
+                errors.push(failure(&format!("tree: {}: not a directory", root)));
+            }
+            Err(e) => {
+                tree.lines.push(format!("{}  [error opening dir]", root));
+                errors.push(failure(&format!("tree: {}: {}", root, e)));
+            }
+        }
+    }
+
+    let plural = |count: usize, one: &str, many: &str| {
+        format!("{} {}", count, if count == 1 { one } else { many })
+    };
+    let mut summary = plural(tree.dirs, "directory", "directories");
+    if !tree.options.dirs_only {
+        summary.push_str(&format!(", {}", plural(tree.files, "file", "files")));
+    }
+    tree.lines.push(String::new());
+    tree.lines.push(summary);
+    io.finish(tree.lines.join("\n"), errors)
+}
+
+impl Tree {
+    fn walk(&mut self, dir: &Path, prefix: &str, depth: usize) {
+        if self.options.max_depth.is_some_and(|max| depth > max) {
+            return;
+        }
+        let entries = match read_sorted(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("tree: {}: {}", dir.display(), e);
+                if let Some(last) = self.lines.last_mut() {
+                    last.push_str("  [error opening dir]");
+                }
+                return;
+            }
+        };
+
+        let entries: Vec<Entry> = entries
+            .into_iter()
+            .filter(|entry| self.options.all || !entry.name.starts_with('.'))
+            .filter(|entry| !self.options.dirs_only || entry.metadata.is_dir())
+            .filter(|entry| {
+                !self
+                    .options
+                    .excludes
+                    .iter()
+                    .any(|pattern| glob_match(pattern, &entry.name))
+            })
+            .filter(|entry| {
+                self.ignores
+                    .as_ref()
+                    .is_none_or(|ignores| !ignores.is_ignored(&entry.path, entry.metadata.is_dir()))
+            })
+            .collect();
+
+        for (i, entry) in entries.iter().enumerate() {
+            let last = i + 1 == entries.len();
+            let mut line = format!(
+                "{}{}{}",
+                prefix,
+                if last { "└── " } else { "├── " },
+                self.colors
+                    .paint_name(&entry.name, &entry.path, &entry.metadata)
+            );
+            if entry.metadata.is_symlink() {
+                if let Ok(target) = fs::read_link(&entry.path) {
+                    line.push_str(&format!(" -> {}", target.display()));
+                }
+            }
+            self.lines.push(line);
+
+            if entry.metadata.is_dir() {
+                self.dirs += 1;
+                let prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+                if let Some(ignores) = self.ignores.as_mut() {
+                    ignores.enter(&entry.path);
+                }
+                self.walk(&entry.path, &prefix, depth + 1);
+                if let Some(ignores) = self.ignores.as_mut() {
+                    ignores.leave();
+                }
+            } else {
+                self.files += 1;
+            }
+        }
+    }
+}
+
+struct DuOptions {
+    human: bool,
+    all: bool,
+    total: bool,
+    max_depth: Option<usize>,
+    sort: bool,
+    apparent: bool,
+}
+
+struct Du {
+    options: DuOptions,
+    seen: HashSet<(u64, u64)>,
+    rows: Vec<(u64, String)>,
+    errors: Vec<String>,
+}
+
+/// Runs `du`: `-h` human sizes, `-s` one total per argument, `-d N` the
+/// depth to report, `-a` files too, `-c` a grand total, `--sort` biggest
+/// first and `--apparent-size` file lengths instead of disk usage.
+pub fn disk_usage(args: &[String], io: &Io) -> Result<String, String> {
+    let mut options = DuOptions {
+        human: false,
+        all: false,
+        total: false,
+        max_depth: None,
+        sort: false,
+        apparent: false,
+    };
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--human-readable" => options.human = true,
+            "--summarize" => options.max_depth = Some(0),
+            "--all" => options.all = true,
+            "--total" => options.total = true,
+            "--sort" => options.sort = true,
+            "--apparent-size" => options.apparent = true,
+            "-d" | "--max-depth" => options.max_depth = Some(parse_number("du", arg, args.next())?),
+            _ if arg.starts_with("--max-depth=") => {
+                let value = arg["--max-depth=".len()..].to_string();
+                options.max_depth = Some(parse_number("du", "--max-depth", Some(&value))?)
+            }
+            _ if arg.starts_with('-') && arg.len() > 1 && !arg.starts_with("--") => {
+                for flag in arg[1..].chars() {
+                    match flag {
+                        'h' => options.human = true,
+                        's' => options.max_depth = Some(0),
+                        'a' => options.all = true,
+                        'c' => options.total = true,
+                        _ => return Err(failure(&format!("du: invalid option -- '{}'", flag))),
+                    }
+                }
+            }
+            _ if arg.starts_with("--") => {
+                return Err(failure(&format!("du: unrecognized option '{}'", arg)))
+            }
+            _ => paths.push(arg.clone()),
+        }
+    }
+    if paths.is_empty() {
+        paths.push(String::from("."));
+    }
+
+    let mut du = Du {
+        options,
+        seen: HashSet::new(),
+        rows: Vec::new(),
+        errors: Vec::new(),
+    };
+    let mut total = 0;
+    for path in &paths {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) => total += du.walk(Path::new(path), &metadata, 0),
+            Err(e) => du.error(&format!("cannot access '{}': {}", path, e)),
+        }
+    }
+
+    if du.options.sort {
+        du.rows.sort_by_key(|row| std::cmp::Reverse(row.0));
+    }
+    if du.options.total {
+        du.rows.push((total, String::from("total")));
+    }
+    let output = du
+        .rows
+        .iter()
+        .map(|(size, path)| format!("{}\t{}", du.format_size(*size), path))
+        .collect::<Vec<_>>()
+        .join("\n");
+    io.finish(output, du.errors)
+}
+
+impl Du {
+    fn error(&mut self, message: &str) {
+        error!("du: {}", message);
+        self.errors.push(failure(&format!("du: {}", message)));
+    }
+
+    // adds up the size of a path, recording a row for each directory (and
+    // file with -a) within the reported depth
+    fn walk(&mut self, path: &Path, metadata: &Metadata, depth: usize) -> u64 {
+        // hard links are only counted once
+        if metadata.nlink() > 1 && !self.seen.insert((metadata.dev(), metadata.ino())) {
+            return 0;
+        }
+        let mut size = if self.options.apparent {
+            metadata.len()
+        } else {
+            metadata.blocks() * 512
+        };
+        let reported = self.options.max_depth.is_none_or(|max| depth <= max);
+
+        if metadata.is_dir() {
+            match read_sorted(path) {
+                Ok(entries) => {
+                    for entry in entries {
+                        size += self.walk(&entry.path, &entry.metadata, depth + 1);
+                    }
+                }
+                Err(e) => self.error(&format!(
+                    "cannot read directory '{}': {}",
+                    path.display(),
+                    e
+                )),
+            }
+            if reported {
+                self.rows.push((size, path.display().to_string()));
+            }
+        } else if reported && (self.options.all || depth == 0) {
+            self.rows.push((size, path.display().to_string()));
+        }
+        size
+    }
+
+    fn format_size(&self, bytes: u64) -> String {
+        if self.options.human {
+            human_size(bytes)
+        } else {
+            bytes.div_ceil(1024).to_string()
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Compare {
+    Less,
+    Equal,
+    Greater,
+}
+
+impl Compare {
+    // splits a find number like `+5`, `-5` or `5`
+    fn parse(value: &str) -> (Compare, &str) {
+        match value.split_at_checked(1) {
+            Some(("+", rest)) => (Compare::Greater, rest),
+            Some(("-", rest)) => (Compare::Less, rest),
+            _ => (Compare::Equal, value),
+        }
+    }
+
+    fn matches(self, actual: u64, wanted: u64) -> bool {
+        match self {
+            Compare::Less => actual < wanted,
+            Compare::Equal => actual == wanted,
+            Compare::Greater => actual > wanted,
+        }
+    }
+}
+
+enum Test {
+    Name(String, bool),
+    Path(String),
+    Type(char),
+    // comparison, wanted count and the unit size in bytes
+    Size(Compare, u64, u64),
+    // comparison, wanted age and the unit length in seconds
+    Age(Compare, u64, u64),
+    Newer(SystemTime),
+    Empty,
+    Not(Box<Test>),
+    Print,
+    Exec(Vec<String>),
+    ExecBatch(Vec<String>),
+}
+
+struct Find<'a> {
+    // alternatives separated by -o, each a list of tests that must all pass
+    expression: Vec<Vec<Test>>,
+    has_action: bool,
+    min_depth: usize,
+    max_depth: Option<usize>,
+    now: SystemTime,
+    output: Vec<String>,
+    batch: Vec<String>,
+    errors: Vec<String>,
+    shell: &'a mut Shell,
+    io: &'a Io,
+}
+
+/// Runs `find PATH... [EXPRESSION]`. The expression is a list of tests that
+/// must all be true, optionally split into alternatives with `-o`:
+/// `-name`, `-iname`, `-path`, `-type`, `-size`, `-mtime`, `-mmin`,
+/// `-newer`, `-empty`, `!`/`-not`, `-print` and `-exec CMD {} ;` or `+`.
+/// `-maxdepth` and `-mindepth` limit how deep it goes.
+pub fn find(args: &[String], shell: &mut Shell, io: &Io) -> Result<String, String> {
+    let start = args
+        .iter()
+        .position(|arg| arg.starts_with('-') || arg == "!")
+        .unwrap_or(args.len());
+    let mut roots: Vec<String> = args[..start].to_vec();
+    if roots.is_empty() {
+        roots.push(String::from("."));
+    }
+
+    let mut find = Find {
+        expression: vec![Vec::new()],
+        has_action: false,
+        min_depth: 0,
+        max_depth: None,
+        now: SystemTime::now(),
+        output: Vec::new(),
+        batch: Vec::new(),
+        errors: Vec::new(),
+        shell,
+        io,
+    };
+    find.parse(&args[start..])?;
+
+    for root in &roots {
+        match fs::symlink_metadata(root) {
+            Ok(metadata) => find.walk(Path::new(root), &metadata, 0),
+            Err(e) => find.error(&format!("'{}': {}", root, e)),
+        }
+    }
+    find.run_batches();
+
+    // the commands run by -exec must not decide find's own status
+    find.shell.last_status = 0;
+    let output = find.output.join("\n");
+    let errors = std::mem::take(&mut find.errors);
+    io.finish(output, errors)
+}
+
+impl Find<'_> {
+    fn error(&mut self, message: &str) {
+        error!("find: {}", message);
+        self.errors.push(failure(&format!("find: {}", message)));
+    }
+
+    fn parse(&mut self, args: &[String]) -> Result<(), String> {
+        let mut args = args.iter();
+        let mut negate = false;
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| failure(&format!("find: missing argument to '{}'", name)))
+            };
+            let test = match arg.as_str() {
+                "!" | "-not" => {
+                    negate = !negate;
+                    continue;
+                }
+                "-o" | "-or" => {
+                    self.expression.push(Vec::new());
+                    continue;
+                }
+                "-a" | "-and" => continue,
+                "-maxdepth" => {
+                    self.max_depth = Some(parse_number("find", arg, Some(&value(arg)?))?);
+                    continue;
+                }
+                "-mindepth" => {
+                    self.min_depth = parse_number("find", arg, Some(&value(arg)?))?;
+                    continue;
+                }
+                "-name" => Test::Name(value(arg)?, false),
+                "-iname" => Test::Name(value(arg)?.to_lowercase(), true),
+                "-path" | "-wholename" => Test::Path(value(arg)?),
+                "-type" => match value(arg)?.as_str() {
+                    kind @ ("f" | "d" | "l" | "p" | "s" | "b" | "c") => {
+                        Test::Type(kind.chars().next().unwrap_or('f'))
+                    }
+                    kind => return Err(failure(&format!("find: unknown type '{}'", kind))),
+                },
+                "-size" => parse_size(&value(arg)?)?,
+                "-mtime" | "-mmin" => {
+                    let value = value(arg)?;
+                    let (compare, number) = Compare::parse(&value);
+                    let number = number.parse().map_err(|_| {
+                        failure(&format!("find: invalid argument '{}' to {}", value, arg))
+                    })?;
+                    Test::Age(compare, number, if arg == "-mtime" { 86400 } else { 60 })
+                }
+                "-newer" => {
+                    let file = value(arg)?;
+                    let modified = fs::metadata(&file)
+                        .and_then(|metadata| metadata.modified())
+                        .map_err(|e| failure(&format!("find: '{}': {}", file, e)))?;
+                    Test::Newer(modified)
+                }
+                "-empty" => Test::Empty,
+                "-print" => {
+                    self.has_action = true;
+                    Test::Print
+                }
+                "-exec" => {
+                    let mut command = Vec::new();
+                    let mut batch = false;
+                    for word in args.by_ref() {
+                        match word.as_str() {
+                            ";" => break,
+                            "+" if command.last().is_some_and(|last| last == "{}") => {
+                                batch = true;
+                                break;
+                            }
+                            _ => command.push(word.clone()),
+                        }
+                    }
+                    if command.is_empty() {
+                        return Err(failure("find: missing argument to '-exec'"));
+                    }
+                    self.has_action = true;
+                    if batch {
+                        Test::ExecBatch(command)
+                    } else {
+                        Test::Exec(command)
+                    }
+                }
+                _ => return Err(failure(&format!("find: unknown predicate '{}'", arg))),
+            };
+            let test = if std::mem::take(&mut negate) {
+                Test::Not(Box::new(test))
+            } else {
+                test
+            };
+            if let Some(alternative) = self.expression.last_mut() {
+                alternative.push(test);
+            }
+        }
+        Ok(())
+    }
+
+    fn walk(&mut self, path: &Path, metadata: &Metadata, depth: usize) {
+        if depth >= self.min_depth {
+            let path_text = path.display().to_string();
+            let mut matched = false;
+            let expression = std::mem::take(&mut self.expression);
+            for alternative in &expression {
+                if alternative
+                    .iter()
+                    .all(|test| self.evaluate(test, path, &path_text, metadata))
+                {
+                    matched = true;
+                    break;
+                }
+            }
+            self.expression = expression;
+            if matched && !self.has_action {
+                self.output.push(path_text);
+            }
+        }
+
+        if metadata.is_dir() && self.max_depth.is_none_or(|max| depth < max) {
+            match read_sorted(path) {
+                Ok(entries) => {
+                    for entry in entries {
+                        self.walk(&entry.path, &entry.metadata, depth + 1);
+                    }
+                }
+                Err(e) => self.error(&format!("'{}': {}", path.display(), e)),
+            }
+        }
+    }
+
+    fn evaluate(&mut self, test: &Test, path: &Path, path_text: &str, metadata: &Metadata) -> bool {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| path_text.to_string());
+        match test {
+            Test::Name(pattern, false) => glob_match(pattern, &name),
+            Test::Name(pattern, true) => glob_match(pattern, &name.to_lowercase()),
+            Test::Path(pattern) => glob_match(pattern, path_text),
+            Test::Type(kind) => {
+                let file_type = metadata.file_type();
+                match kind {
+                    'd' => file_type.is_dir(),
+                    'l' => file_type.is_symlink(),
+                    'p' => file_type.is_fifo(),
+                    's' => file_type.is_socket(),
+                    'b' => file_type.is_block_device(),
+                    'c' => file_type.is_char_device(),
+                    _ => file_type.is_file(),
+                }
+            }
+            // sizes are rounded up to whole units, like GNU find does
+            Test::Size(compare, wanted, unit) => {
+                compare.matches(metadata.len().div_ceil(*unit), *wanted)
+            }
+            Test::Age(compare, wanted, unit) => {
+                let age = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| self.now.duration_since(modified).ok())
+                    .map_or(0, |age| age.as_secs());
+                compare.matches(age / unit, *wanted)
+            }
+            Test::Newer(time) => metadata.modified().is_ok_and(|modified| modified > *time),
+            Test::Empty => {
+                if metadata.is_dir() {
+                    fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none())
+                } else {
+                    metadata.is_file() && metadata.len() == 0
+                }
+            }
+            Test::Not(test) => !self.evaluate(test, path, path_text, metadata),
+            Test::Print => {
+                self.output.push(path_text.to_string());
+                true
+            }
+            Test::Exec(command) => {
+                let argv: Vec<String> = command
+                    .iter()
+                    .map(|word| word.replace("{}", path_text))
+                    .collect();
+                self.run(&argv) == 0
+            }
+            Test::ExecBatch(_) => {
+                self.batch.push(path_text.to_string());
+                true
+            }
+        }
+    }
+
+    // runs a command for -exec, after flushing what was printed so far so
+    // the output stays in order
+    fn run(&mut self, argv: &[String]) -> i32 {
+        if !self.output.is_empty() {
+            let printed = std::mem::take(&mut self.output).join("\n");
+            self.io.stdout.write(&printed, false);
+        }
+        run_command(argv, self.shell, self.io)
+    }
+
+    // runs the `-exec ... {} +` commands once with all the collected paths
+    fn run_batches(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let paths = std::mem::take(&mut self.batch);
+        let commands: Vec<Vec<String>> = self
+            .expression
+            .iter()
+            .flatten()
+            .filter_map(|test| match test {
+                Test::ExecBatch(command) => Some(command.clone()),
+                _ => None,
+            })
+            .collect();
+        for command in commands {
+            let mut argv = command[..command.len() - 1].to_vec();
+            argv.extend(paths.iter().cloned());
+            if self.run(&argv) != 0 {
+                self.errors.push(failure(&format!(
+                    "find: '{}' returned a non-zero status",
+                    command[0]
+                )));
+            }
+        }
+    }
+}
+
+// parses `-size [+-]N[cwbkMG]`; without a suffix N counts 512-byte blocks
+fn parse_size(value: &str) -> Result<Test, String> {
+    let (compare, number) = Compare::parse(value);
+    let (digits, unit) = match number.char_indices().last() {
+        Some((i, suffix)) if suffix.is_ascii_alphabetic() => (&number[..i], Some(suffix)),
+        _ => (number, None),
+    };
+    let unit = match unit {
+        None | Some('b') => 512,
+        Some('c') => 1,
+        Some('w') => 2,
+        Some('k') => 1024,
+        Some('M') => 1024 * 1024,
+        Some('G') => 1024 * 1024 * 1024,
+        Some(other) => return Err(failure(&format!("find: invalid -size unit '{}'", other))),
+    };
+    let count = digits
+        .parse()
+        .map_err(|_| failure(&format!("find: invalid argument '{}' to -size", value)))?;
+    Ok(Test::Size(compare, count, unit))
+}
@@ -0,0 +1,1015 @@
+// The fileops module holds the builtins that change the filesystem, along
+// with the helpers they share for copying and moving whole trees.
+use std::{
+    env,
+    ffi::CString,
+    fs::{self, File},
+    io::{self, IsTerminal, Read, Write},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{symlink, PermissionsExt},
+    },
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+use colored::*;
+use log::error;
+
+use crate::{
+    exec::Io,
+    shell::Shell,
+    trash::move_to_trash,
+    utils::{confirm, failure, human_size, success},
+};
+
+struct RemoveOptions {
+    recursive: bool,
+    force: bool,
+    interactive: bool,
+    verbose: bool,
+    empty_dirs: bool,
+    preserve_root: bool,
+    trash: bool,
+}
+
+/// Runs `rm`. Directories need `-r`, `/` and `$HOME` are refused unless
+/// `--no-preserve-root` is given, and with `--trash` (or RM_TRASH="true")
+/// files are moved to the trash instead of being deleted.
+pub fn remove(args: &[String], shell: &Shell, io: &Io) -> Result<String, String> {
+    let mut options = RemoveOptions {
+        recursive: false,
+        force: false,
+        interactive: false,
+        verbose: false,
+        empty_dirs: false,
+        preserve_root: true,
+        trash: shell
+            .get_env("RM_TRASH")
+            .is_some_and(|value| value.eq_ignore_ascii_case("true")),
+    };
+    let mut paths = Vec::new();
+    let mut only_paths = false;
+
+    for arg in args {
+        if only_paths || arg == "-" || !arg.starts_with('-') {
+            paths.push(arg.as_str());
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_paths = true,
+            "--recursive" => options.recursive = true,
+            "--force" => options.force = true,
+            "--interactive" => options.interactive = true,
+            "--verbose" => options.verbose = true,
+            "--dir" => options.empty_dirs = true,
+            "--no-preserve-root" => options.preserve_root = false,
+            "--trash" => options.trash = true,
+            long if long.starts_with("--") => {
+                return Err(failure(&format!("rm: unrecognized option '{}'", long)));
+            }
+            flags => {
+                for flag in flags.chars().skip(1) {
+                    match flag {
+                        'r' | 'R' => options.recursive = true,
+                        // like GNU rm, the last of -f and -i wins
+                        'f' => {
+                            options.force = true;
+                            options.interactive = false;
+                        }
+                        'i' => {
+                            options.interactive = true;
+                            options.force = false;
+                        }
+                        'v' => options.verbose = true,
+                        'd' => options.empty_dirs = true,
+                        _ => return Err(failure(&format!("rm: invalid option -- '{}'", flag))),
+                    }
+                }
+            }
+        }
+    }
+
+    if paths.is_empty() {
+        return if options.force {
+            Ok(String::new())
+        } else {
+            Err(failure("rm: missing operand"))
+        };
+    }
+
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        match remove_one(Path::new(path), &options, shell) {
+            Ok(Some(message)) => output.push(message),
+            Ok(None) => {}
+            Err(message) => {
+                error!("{}", message);
+                errors.push(failure(&message));
+            }
+        }
+    }
+
+    io.finish(output.join("\n"), errors)
+}
+
+// removes a single operand, returning the message to show with -v
+fn remove_one(
+    path: &Path,
+    options: &RemoveOptions,
+    shell: &Shell,
+) -> Result<Option<String>, String> {
+    let name = path.display();
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == io::ErrorKind::NotFound && options.force => return Ok(None),
+        Err(e) => return Err(format!("rm: cannot remove '{}': {}", name, e)),
+    };
+
+    if options.preserve_root && is_protected(path, shell) {
+        return Err(format!(
+            "rm: it is dangerous to operate recursively on '{}'; use --no-preserve-root to override",
+            name
+        ));
+    }
+    let text = path.to_string_lossy();
+    let text = text.trim_end_matches('/');
+    if [".", ".."].contains(&text) || text.ends_with("/.") || text.ends_with("/..") {
+        return Err(format!(
+            "rm: refusing to remove '.' or '..' directory: skipping '{}'",
+            name
+        ));
+    }
+
+    let is_dir = metadata.is_dir();
+    if is_dir && !options.recursive {
+        let is_empty = fs::read_dir(path).is_ok_and(|mut entries| entries.next().is_none());
+        if !(options.empty_dirs && is_empty) {
+            return Err(format!("rm: cannot remove '{}': Is a directory", name));
+        }
+    }
+
+    if options.interactive {
+        let kind = if is_dir {
+            "directory"
+        } else if metadata.is_symlink() {
+            "symbolic link"
+        } else if metadata.len() == 0 {
+            "regular empty file"
+        } else {
+            "regular file"
+        };
+        if !confirm(&format!("rm: remove {} '{}'?", kind, name)) {
+            return Ok(None);
+        }
+    }
+
+    if options.trash {
+        move_to_trash(path, shell)
+            .map_err(|e| format!("rm: cannot move '{}' to trash: {}", name, e))?;
+        return Ok(options
+            .verbose
+            .then(|| success(&format!("Moved to trash: {}", name))));
+    }
+
+    let result = if is_dir {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    };
+    result.map_err(|e| format!("rm: cannot remove '{}': {}", name, e))?;
+    Ok(options
+        .verbose
+        .then(|| success(&format!("Removed: {}", name))))
+}
+
+/// The root directory and the home directory are never removed or trashed by
+/// accident.
+pub fn is_protected(path: &Path, shell: &Shell) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    path == Path::new("/")
+        || shell
+            .get_env("HOME")
+            .and_then(|home| Path::new(home).canonicalize().ok())
+            .is_some_and(|home| home == path)
+}
+
+/// Runs `mkdir`. `-p` creates missing parents and accepts directories that
+/// already exist, `-m MODE` sets the mode of the new directories.
+pub fn make_directories(args: &[String], io: &Io) -> Result<String, String> {
+    let mut parents = false;
+    let mut verbose = false;
+    let mut mode = None;
+    let mut paths = Vec::new();
+    let mut only_paths = false;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if only_paths || arg == "-" || !arg.starts_with('-') {
+            paths.push(arg.as_str());
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_paths = true,
+            "--parents" => parents = true,
+            "--verbose" => verbose = true,
+            long if long.starts_with("--mode=") => mode = Some(long["--mode=".len()..].to_string()),
+            long if long.starts_with("--") => {
+                return Err(failure(&format!("mkdir: unrecognized option '{}'", long)));
+            }
+            flags => {
+                for (i, flag) in flags.char_indices().skip(1) {
+                    match flag {
+                        'p' => parents = true,
+                        'v' => verbose = true,
+                        'm' => {
+                            // the mode is either the rest of this argument or the next one
+                            let rest = &flags[i + 1..];
+                            mode = if rest.is_empty() {
+                                args.next().cloned()
+                            } else {
+                                Some(rest.to_string())
+                            };
+                            if mode.is_none() {
+                                return Err(failure("mkdir: option requires an argument -- 'm'"));
+                            }
+                            break;
+                        }
+                        _ => return Err(failure(&format!("mkdir: invalid option -- '{}'", flag))),
+                    }
+                }
+            }
+        }
+    }
+
+    let mode = match mode {
+        Some(spec) => Some(
+            parse_mode(&spec, 0o777)
+                .ok_or_else(|| failure(&format!("mkdir: invalid mode '{}'", spec)))?,
+        ),
+        None => None,
+    };
+    if paths.is_empty() {
+        return Err(failure("mkdir: missing operand"));
+    }
+
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        match make_directory(Path::new(path), parents, mode) {
+            Ok(created) if verbose => output.extend(
+                created
+                    .iter()
+                    .map(|dir| success(&format!("Directory created: {}", dir.display()))),
+            ),
+            Ok(_) => {}
+            Err(e) => {
+                let message = format!("mkdir: cannot create directory '{}': {}", path, e);
+                error!("{}", message);
+                errors.push(failure(&message));
+            }
+        }
+    }
+
+    io.finish(output.join("\n"), errors)
+}
+
+// creates a directory (and with `parents` any missing ancestors), returning
+// the directories that were created
+fn make_directory(path: &Path, parents: bool, mode: Option<u32>) -> io::Result<Vec<PathBuf>> {
+    let mut missing = vec![path.to_path_buf()];
+    if parents {
+        if path.is_dir() {
+            return Ok(Vec::new());
+        }
+        if path.symlink_metadata().is_ok() {
+            return Err(io::Error::from_raw_os_error(libc::EEXIST));
+        }
+        missing = path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .take_while(|ancestor| ancestor.symlink_metadata().is_err())
+            .map(Path::to_path_buf)
+            .collect();
+        missing.reverse();
+    }
+
+    for dir in &missing {
+        fs::create_dir(dir)?;
+    }
+    // the mode is set explicitly so the umask doesn't apply to it
+    if let Some(mode) = mode {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(missing)
+}
+
+// parses an octal (`755`) or symbolic (`u=rwx,go+rx`) mode. Symbolic modes
+// are applied to `base`.
+fn parse_mode(spec: &str, base: u32) -> Option<u32> {
+    if !spec.is_empty() && spec.chars().all(|c| c.is_digit(8)) {
+        return u32::from_str_radix(spec, 8)
+            .ok()
+            .filter(|&mode| mode <= 0o7777);
+    }
+
+    let mut mode = base;
+    for clause in spec.split(',') {
+        let op_index = clause.find(['+', '-', '='])?;
+        let (who, rest) = clause.split_at(op_index);
+        let mut mask = 0;
+        for c in who.chars() {
+            mask |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                'a' => 0o7777,
+                _ => return None,
+            };
+        }
+        if who.is_empty() {
+            mask = 0o7777;
+        }
+
+        let op = rest.chars().next()?;
+        let mut bits = 0;
+        for c in rest[1..].chars() {
+            bits |= match c {
+                'r' => 0o444,
+                'w' => 0o222,
+                'x' => 0o111,
+                'X' if mode & 0o111 != 0 => 0o111,
+                'X' => 0,
+                's' => 0o6000,
+                't' => 0o1000,
+                _ => return None,
+            };
+        }
+        let bits = bits & mask;
+        mode = match op {
+            '+' => mode | bits,
+            '-' => mode & !bits,
+            _ => (mode & !(mask & 0o777)) | bits,
+        };
+    }
+    Some(mode)
+}
+
+/// Runs `touch`: creates missing files and updates the access and
+/// modification times of existing ones without touching their contents.
+pub fn touch(args: &[String]) -> Result<String, String> {
+    let mut access = false;
+    let mut modification = false;
+    let mut no_create = false;
+    let mut times = None;
+    let mut paths = Vec::new();
+    let mut only_paths = false;
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        if only_paths || arg == "-" || !arg.starts_with('-') {
+            paths.push(arg.as_str());
+            continue;
+        }
+        // -d and -r take a value, either attached or as the next argument
+        let mut value_for = |flag: char, rest: &str| -> Result<String, String> {
+            if rest.is_empty() {
+                args.next().cloned().ok_or_else(|| {
+                    failure(&format!("touch: option requires an argument -- '{}'", flag))
+                })
+            } else {
+                Ok(rest.to_string())
+            }
+        };
+
+        match arg.as_str() {
+            "--" => only_paths = true,
+            "--no-create" => no_create = true,
+            long if long.starts_with("--date=") => {
+                let date = parse_date(&long["--date=".len()..])?;
+                times = Some((date, date));
+            }
+            long if long.starts_with("--reference=") => {
+                times = Some(reference_times(&long["--reference=".len()..])?);
+            }
+            long if long.starts_with("--") => {
+                return Err(failure(&format!("touch: unrecognized option '{}'", long)));
+            }
+            flags => {
+                for (i, flag) in flags.char_indices().skip(1) {
+                    match flag {
+                        'a' => access = true,
+                        'm' => modification = true,
+                        'c' => no_create = true,
+                        'd' => {
+                            let date = parse_date(&value_for('d', &flags[i + 1..])?)?;
+                            times = Some((date, date));
+                            break;
+                        }
+                        'r' => {
+                            times = Some(reference_times(&value_for('r', &flags[i + 1..])?)?);
+                            break;
+                        }
+                        _ => return Err(failure(&format!("touch: invalid option -- '{}'", flag))),
+                    }
+                }
+            }
+        }
+    }
+
+    if paths.is_empty() {
+        return Err(failure("touch: missing file operand"));
+    }
+    // without -a or -m both times are changed
+    if !access && !modification {
+        access = true;
+        modification = true;
+    }
+
+    let mut errors = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        let result = if path.symlink_metadata().is_err() {
+            if no_create {
+                continue;
+            }
+            File::options()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .map(|_| ())
+        } else {
+            Ok(())
+        };
+
+        let result = result.and_then(|_| set_file_times(path, times, access, modification));
+        if let Err(e) = result {
+            let message = format!("touch: cannot touch '{}': {}", path.display(), e);
+            error!("{}", message);
+            errors.push(failure(&message));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(String::new())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+// sets the access and/or modification time of a file, leaving the other one
+// alone. `times` holds the new access and modification times; None means now.
+fn set_file_times(
+    path: &Path,
+    times: Option<(SystemTime, SystemTime)>,
+    access: bool,
+    modification: bool,
+) -> io::Result<()> {
+    let timespec = |set: bool, time: Option<SystemTime>| match (set, time) {
+        (false, _) => libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_OMIT,
+        },
+        (true, None) => libc::timespec {
+            tv_sec: 0,
+            tv_nsec: libc::UTIME_NOW,
+        },
+        (true, Some(time)) => {
+            let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            libc::timespec {
+                tv_sec: since_epoch.as_secs() as libc::time_t,
+                tv_nsec: since_epoch.subsec_nanos() as libc::c_long,
+            }
+        }
+    };
+    let times = [
+        timespec(access, times.map(|(accessed, _)| accessed)),
+        timespec(modification, times.map(|(_, modified)| modified)),
+    ];
+    let path = CString::new(path.as_os_str().as_bytes())?;
+
+    // SAFETY: the path is a valid C string and `times` holds two timespecs
+    let result = unsafe { libc::utimensat(libc::AT_FDCWD, path.as_ptr(), times.as_ptr(), 0) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+// parses the argument of `touch -d`: `now`, `today`, `yesterday`,
+// `@SECONDS` or a date such as `2024-05-01` or `2024-05-01 13:45[:30]`
+fn parse_date(text: &str) -> Result<SystemTime, String> {
+    let invalid = || failure(&format!("touch: invalid date format '{}'", text));
+    let text = text.trim();
+    let today = Local::now().date_naive();
+
+    let date_time = match text {
+        "now" => return Ok(SystemTime::now()),
+        "today" => today.and_hms_opt(0, 0, 0),
+        "yesterday" => today.pred_opt().and_then(|day| day.and_hms_opt(0, 0, 0)),
+        "tomorrow" => today.succ_opt().and_then(|day| day.and_hms_opt(0, 0, 0)),
+        _ if text.starts_with('@') => {
+            let seconds: i64 = text[1..].parse().map_err(|_| invalid())?;
+            return DateTime::from_timestamp(seconds, 0)
+                .map(SystemTime::from)
+                .ok_or_else(invalid);
+        }
+        _ => [
+            "%Y-%m-%d %H:%M:%S",
+            "%Y-%m-%dT%H:%M:%S",
+            "%Y-%m-%d %H:%M",
+            "%Y-%m-%dT%H:%M",
+        ]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(text, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        }),
+    };
+
+    date_time
+        .and_then(|date_time| Local.from_local_datetime(&date_time).earliest())
+        .map(SystemTime::from)
+        .ok_or_else(invalid)
+}
+
+// the access and modification times of the file given to `touch -r`
+fn reference_times(path: &str) -> Result<(SystemTime, SystemTime), String> {
+    fs::metadata(path)
+        .and_then(|metadata| Ok((metadata.accessed()?, metadata.modified()?)))
+        .map_err(|e| {
+            failure(&format!(
+                "touch: failed to get attributes of '{}': {}",
+                path, e
+            ))
+        })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Verb {
+    Copy,
+    Move,
+}
+
+impl Verb {
+    fn name(self) -> &'static str {
+        match self {
+            Verb::Copy => "cp",
+            Verb::Move => "mv",
+        }
+    }
+}
+
+struct TransferOptions {
+    recursive: bool,
+    interactive: bool,
+    no_clobber: bool,
+    update: bool,
+    verbose: bool,
+    preserve: bool,
+    // Some(true) for -L and Some(false) for -P
+    dereference: Option<bool>,
+}
+
+/// The symlinks `copy_tree` copies the target of instead of the link itself.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Follow {
+    Never,
+    // only the path given, as cp does for its operands
+    Top,
+    All,
+}
+
+/// Runs `cp`. Directories need `-r`; `-p` keeps modification times as well
+/// as permissions, and large copies show a progress bar on the terminal.
+/// Symlinks given as operands are followed, unless `-P` is given or the copy
+/// is recursive without `-L`; links inside a recursive copy stay links.
+pub fn copy(args: &[String], io: &Io) -> Result<String, String> {
+    transfer(Verb::Copy, args, io)
+}
+
+/// Runs `mv`, which falls back to copying when moving across filesystems.
+pub fn move_files(args: &[String], io: &Io) -> Result<String, String> {
+    transfer(Verb::Move, args, io)
+}
+
+fn transfer(verb: Verb, args: &[String], io: &Io) -> Result<String, String> {
+    let name = verb.name();
+    let mut options = TransferOptions {
+        recursive: verb == Verb::Move,
+        interactive: false,
+        no_clobber: false,
+        update: false,
+        verbose: false,
+        preserve: verb == Verb::Move,
+        dereference: None,
+    };
+    let mut operands = Vec::new();
+    let mut only_operands = false;
+
+    for arg in args {
+        if only_operands || arg == "-" || !arg.starts_with('-') {
+            operands.push(PathBuf::from(arg));
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_operands = true,
+            "--recursive" => options.recursive = true,
+            "--interactive" => options.interactive = true,
+            "--no-clobber" => options.no_clobber = true,
+            "--update" => options.update = true,
+            "--verbose" => options.verbose = true,
+            "--preserve" => options.preserve = true,
+            "--dereference" if verb == Verb::Copy => options.dereference = Some(true),
+            "--no-dereference" if verb == Verb::Copy => options.dereference = Some(false),
+            "--archive" => {
+                options.recursive = true;
+                options.preserve = true;
+            }
+            "--force" => {
+                options.interactive = false;
+                options.no_clobber = false;
+            }
+            long if long.starts_with("--") => {
+                return Err(failure(&format!(
+                    "{}: unrecognized option '{}'",
+                    name, long
+                )));
+            }
+            flags => {
+                for flag in flags.chars().skip(1) {
+                    match (verb, flag) {
+                        (Verb::Copy, 'r' | 'R') => options.recursive = true,
+                        (Verb::Copy, 'p') => options.preserve = true,
+                        (Verb::Copy, 'L') => options.dereference = Some(true),
+                        (Verb::Copy, 'P') => options.dereference = Some(false),
+                        (Verb::Copy, 'a') => {
+                            options.recursive = true;
+                            options.preserve = true;
+                        }
+                        // the last of -f, -i and -n wins
+                        (_, 'f') => {
+                            options.interactive = false;
+                            options.no_clobber = false;
+                        }
+                        (_, 'i') => {
+                            options.interactive = true;
+                            options.no_clobber = false;
+                        }
+                        (_, 'n') => {
+                            options.no_clobber = true;
+                            options.interactive = false;
+                        }
+                        (_, 'u') => options.update = true,
+                        (_, 'v') => options.verbose = true,
+                        _ => {
+                            return Err(failure(&format!(
+                                "{}: invalid option -- '{}'",
+                                name, flag
+                            )));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let Some(destination) = operands.pop() else {
+        return Err(failure(&format!("{}: missing file operand", name)));
+    };
+    if operands.is_empty() {
+        return Err(failure(&format!(
+            "{}: missing destination file operand after '{}'",
+            name,
+            destination.display()
+        )));
+    }
+    let into_dir = destination.is_dir();
+    if operands.len() > 1 && !into_dir {
+        return Err(failure(&format!(
+            "{}: target '{}' is not a directory",
+            name,
+            destination.display()
+        )));
+    }
+
+    let mut progress = match verb {
+        Verb::Copy => Progress::new(operands.iter().map(|path| tree_size(path)).sum()),
+        Verb::Move => Progress::hidden(),
+    };
+    let mut output = Vec::new();
+    let mut errors = Vec::new();
+    for source in &operands {
+        let target = match (into_dir, source.file_name()) {
+            (true, Some(file_name)) => destination.join(file_name),
+            _ => destination.clone(),
+        };
+        match transfer_one(verb, source, &target, &options, &mut progress) {
+            Ok(Some(message)) => output.push(message),
+            Ok(None) => {}
+            Err(message) => {
+                error!("{}", message);
+                errors.push(failure(&message));
+            }
+        }
+    }
+    progress.finish();
+
+    io.finish(output.join("\n"), errors)
+}
+
+// copies or moves one source, returning the message to show with -v
+fn transfer_one(
+    verb: Verb,
+    source: &Path,
+    target: &Path,
+    options: &TransferOptions,
+    progress: &mut Progress,
+) -> Result<Option<String>, String> {
+    let name = verb.name();
+    let follow = match (verb, options.dereference) {
+        (Verb::Move, _) | (_, Some(false)) => Follow::Never,
+        (_, Some(true)) => Follow::All,
+        (_, None) if options.recursive => Follow::Never,
+        (_, None) => Follow::Top,
+    };
+    let metadata = if follow == Follow::Never {
+        source.symlink_metadata()
+    } else {
+        source.metadata()
+    }
+    .map_err(|e| format!("{}: cannot stat '{}': {}", name, source.display(), e))?;
+
+    if metadata.is_dir() && !options.recursive {
+        return Err(format!(
+            "{}: -r not specified; omitting directory '{}'",
+            name,
+            source.display()
+        ));
+    }
+    let source_abs = absolute_path(source);
+    let target_abs = absolute_path(target);
+    if source
+        .canonicalize()
+        .ok()
+        .is_some_and(|path| target.canonicalize().ok() == Some(path))
+    {
+        return Err(format!(
+            "{}: '{}' and '{}' are the same file",
+            name,
+            source.display(),
+            target.display()
+        ));
+    }
+    if metadata.is_dir() && target_abs.starts_with(&source_abs) {
+        return Err(format!(
+            "{}: cannot {} a directory, '{}', into itself, '{}'",
+            name,
+            if verb == Verb::Copy { "copy" } else { "move" },
+            source.display(),
+            target.display()
+        ));
+    }
+
+    if let Ok(existing) = target.symlink_metadata() {
+        if options.no_clobber {
+            return Ok(None);
+        }
+        let newer = match (existing.modified(), metadata.modified()) {
+            (Ok(target_time), Ok(source_time)) => target_time >= source_time,
+            _ => false,
+        };
+        if options.update && newer {
+            return Ok(None);
+        }
+        if options.interactive && !confirm(&format!("{}: overwrite '{}'?", name, target.display()))
+        {
+            return Ok(None);
+        }
+        if existing.is_dir() && !metadata.is_dir() {
+            return Err(format!(
+                "{}: cannot overwrite directory '{}' with non-directory",
+                name,
+                target.display()
+            ));
+        }
+    }
+
+    let result = match verb {
+        Verb::Copy => copy_tree(source, target, options.preserve, follow, progress),
+        Verb::Move => move_path(source, target),
+    };
+    result.map_err(|e| {
+        format!(
+            "{}: cannot {} '{}' to '{}': {}",
+            name,
+            if verb == Verb::Copy { "copy" } else { "move" },
+            source.display(),
+            target.display(),
+            e
+        )
+    })?;
+
+    let done = if verb == Verb::Copy {
+        "Copied"
+    } else {
+        "Moved"
+    };
+    Ok(options.verbose.then(|| {
+        success(&format!(
+            "{}: {} -> {}",
+            done,
+            source.display(),
+            target.display()
+        ))
+    }))
+}
+
+/// Moves a file or directory, copying it when the destination is on another
+/// filesystem.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+            let mut progress = Progress::new(tree_size(from));
+            let copied = copy_tree(from, to, true, Follow::Never, &mut progress);
+            progress.finish();
+            copied?;
+            if from.symlink_metadata()?.is_dir() {
+                fs::remove_dir_all(from)
+            } else {
+                fs::remove_file(from)
+            }
+        }
+        result => result,
+    }
+}
+
+/// Copies a file, symlink or whole directory, merging into directories that
+/// already exist. Permissions are always kept, modification times only when
+/// `preserve` is set.
+pub fn copy_tree(
+    from: &Path,
+    to: &Path,
+    preserve: bool,
+    follow: Follow,
+    progress: &mut Progress,
+) -> io::Result<()> {
+    let metadata = if follow == Follow::Never {
+        from.symlink_metadata()?
+    } else {
+        from.metadata()?
+    };
+    let inner = if follow == Follow::All {
+        Follow::All
+    } else {
+        Follow::Never
+    };
+
+    if metadata.is_symlink() {
+        if to.symlink_metadata().is_ok() {
+            fs::remove_file(to)?;
+        }
+        return symlink(fs::read_link(from)?, to);
+    }
+    if metadata.is_dir() {
+        if !to.is_dir() {
+            fs::create_dir(to)?;
+        }
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_tree(
+                &entry.path(),
+                &to.join(entry.file_name()),
+                preserve,
+                inner,
+                progress,
+            )?;
+        }
+    } else if progress.visible {
+        copy_file_with_progress(from, to, progress)?;
+    } else {
+        fs::copy(from, to)?;
+    }
+
+    fs::set_permissions(to, metadata.permissions())?;
+    if preserve {
+        if let Ok(modified) = metadata.modified() {
+            // directories can't be opened for writing, so open read-only
+            File::open(to)?.set_modified(modified)?;
+        }
+    }
+    Ok(())
+}
+
+fn copy_file_with_progress(from: &Path, to: &Path, progress: &mut Progress) -> io::Result<()> {
+    let mut reader = File::open(from)?;
+    let mut writer = File::create(to)?;
+    let mut buffer = vec![0; 1 << 20];
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(());
+        }
+        writer.write_all(&buffer[..read])?;
+        progress.advance(read as u64);
+    }
+}
+
+// the total size of the regular files under a path
+fn tree_size(path: &Path) -> u64 {
+    match path.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::read_dir(path)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| tree_size(&entry.path()))
+                    .sum()
+            })
+            .unwrap_or(0),
+        Ok(metadata) if metadata.is_file() => metadata.len(),
+        _ => 0,
+    }
+}
+
+// copies smaller than this finish too quickly to need a progress bar
+const PROGRESS_THRESHOLD: u64 = 32 * 1024 * 1024;
+
+/// A progress bar for long copies, drawn on stderr when it is a terminal.
+pub struct Progress {
+    total: u64,
+    done: u64,
+    visible: bool,
+    last_draw: Option<Instant>,
+}
+
+impl Progress {
+    fn new(total: u64) -> Self {
+        Progress {
+            total,
+            done: 0,
+            visible: total >= PROGRESS_THRESHOLD && io::stderr().is_terminal(),
+            last_draw: None,
+        }
+    }
+
+    fn hidden() -> Self {
+        Progress {
+            total: 0,
+            done: 0,
+            visible: false,
+            last_draw: None,
+        }
+    }
+
+    fn advance(&mut self, bytes: u64) {
+        self.done += bytes;
+        let due = self
+            .last_draw
+            .is_none_or(|last| last.elapsed() >= Duration::from_millis(100));
+        if self.visible && due {
+            self.draw();
+            self.last_draw = Some(Instant::now());
+        }
+    }
+
+    fn draw(&self) {
+        const WIDTH: usize = 30;
+        let fraction = (self.done as f64 / self.total.max(1) as f64).min(1.0);
+        let filled = (fraction * WIDTH as f64) as usize;
+        eprint!(
+            "\r{} {}{} {:>3}% {}/{}",
+            "🦇".bright_purple(),
+            "█".repeat(filled).bright_purple(),
+            "░".repeat(WIDTH - filled).bright_black(),
+            (fraction * 100.0) as u32,
+            human_size(self.done).bright_yellow(),
+            human_size(self.total).bright_yellow()
+        );
+        let _ = io::stderr().flush();
+    }
+
+    // clears the bar once the copy is done
+    fn finish(&self) {
+        if self.visible && self.last_draw.is_some() {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+    }
+}
+
+/// Resolves a path against the current directory without following links.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| {
+        env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    })
+}
@@ -0,0 +1,244 @@
+// The frecency module keeps the directory database behind `z` and `zi`. The
+// directory changes of commands typed at the prompt (`cd`, `pushd`, `popd`
+// and `z` itself) are recorded in ~/.batcave_dirs as `path|rank|last visit`.
+// Changes made by the rc file, hooks and functions are not, so scripted cds
+// don't crowd out the places the user actually goes. Directories are ranked
+// by how often and how recently they were visited, the same way z.sh and
+// zoxide do it. Fragments given to `z` are matched against the stored paths
+// with the skim fuzzy matcher.
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use colored::*;
+use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
+use log::error;
+
+use crate::{
+    shell::Shell,
+    utils::{failure, success},
+};
+
+// once the ranks add up to this much they are all scaled down, so entries
+// that are no longer visited eventually fall out of the database
+const MAX_TOTAL_RANK: f64 = 9000.0;
+const AGING_FACTOR: f64 = 0.99;
+// the number of candidates offered by `zi`
+const PICKER_SIZE: usize = 10;
+
+struct Entry {
+    path: PathBuf,
+    rank: f64,
+    time: u64,
+}
+
+impl Entry {
+    // weights the visit count by how long ago the last visit was
+    fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.time);
+        let weight = match age {
+            0..3600 => 4.0,
+            3600..86400 => 2.0,
+            86400..604800 => 0.5,
+            _ => 0.25,
+        };
+        self.rank * weight
+    }
+}
+
+fn database_path(shell: &Shell) -> Option<PathBuf> {
+    shell
+        .get_env("HOME")
+        .map(|home| Path::new(home).join(".batcave_dirs"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+fn load(shell: &Shell) -> Vec<Entry> {
+    let Some(content) = database_path(shell).and_then(|path| fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+
+    content
+        .lines()
+        .filter_map(|line| {
+            // split from the right, the path itself may contain '|'
+            let mut fields = line.rsplitn(3, '|');
+            let time = fields.next()?.parse().ok()?;
+            let rank = fields.next()?.parse().ok()?;
+            let path = PathBuf::from(fields.next()?);
+            Some(Entry { path, rank, time })
+        })
+        .collect()
+}
+
+fn save(entries: &[Entry], shell: &Shell) -> io::Result<()> {
+    let Some(path) = database_path(shell) else {
+        return Ok(());
+    };
+    let content: String = entries
+        .iter()
+        .map(|entry| format!("{}|{}|{}\n", entry.path.display(), entry.rank, entry.time))
+        .collect();
+
+    // write a temporary file first so an interrupted save can't lose the
+    // whole database
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&temp, content)?;
+    fs::rename(&temp, &path)
+}
+
+/// Records a visit to `dir`. The home directory is not recorded since `cd`
+/// already gets there without arguments.
+pub fn record_visit(dir: &Path, shell: &Shell) {
+    if shell
+        .get_env("HOME")
+        .is_some_and(|home| Path::new(home) == dir)
+    {
+        return;
+    }
+
+    let mut entries = load(shell);
+    let time = now();
+    match entries.iter_mut().find(|entry| entry.path == dir) {
+        Some(entry) => {
+            entry.rank += 1.0;
+            entry.time = time;
+        }
+        None => entries.push(Entry {
+            path: dir.to_path_buf(),
+            rank: 1.0,
+            time,
+        }),
+    }
+
+    if entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+        for entry in &mut entries {
+            entry.rank *= AGING_FACTOR;
+        }
+        entries.retain(|entry| entry.rank >= 1.0);
+    }
+
+    if let Err(e) = save(&entries, shell) {
+        error!("Failed to save the directory database: {}", e);
+    }
+}
+
+// returns the existing directories matching every fragment, best first. A
+// match of the last fragment against the final path component counts double,
+// so `z src` prefers .../src over .../src/foo.
+fn matches(fragments: &[String], shell: &Shell) -> Vec<(f64, PathBuf)> {
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let current = shell.current_dir();
+    let time = now();
+
+    let mut found: Vec<(f64, PathBuf)> = load(shell)
+        .into_iter()
+        .filter(|entry| entry.path != current && entry.path.is_dir())
+        .filter_map(|entry| {
+            let path = entry.path.to_string_lossy();
+            let mut score = 0;
+            for fragment in fragments {
+                score += matcher.fuzzy_match(&path, fragment)?;
+            }
+            let base = entry
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let base_bonus = match fragments.last() {
+                Some(last) if matcher.fuzzy_match(&base, last).is_some() => 2.0,
+                _ => 1.0,
+            };
+            let fuzziness = 1.0 + score.max(0) as f64 / 100.0;
+            Some((entry.frecency(time) * fuzziness * base_bonus, entry.path))
+        })
+        .collect();
+    found.sort_by(|a, b| b.0.total_cmp(&a.0));
+    found
+}
+
+/// Finds the best directory for `z FRAGMENT...`.
+pub fn best_match(fragments: &[String], shell: &Shell) -> Result<PathBuf, String> {
+    matches(fragments, shell)
+        .into_iter()
+        .next()
+        .map(|(_, path)| path)
+        .ok_or_else(|| failure(&format!("z: no match for '{}'", fragments.join(" "))))
+}
+
+/// Lists matching directories with their scores, lowest first like z.sh
+/// does, so the best match ends up next to the prompt.
+pub fn list_matches(fragments: &[String], shell: &Shell) -> Result<String, String> {
+    let found = matches(fragments, shell);
+    if found.is_empty() {
+        return Err(failure("z: no matching directories"));
+    }
+    Ok(found
+        .iter()
+        .rev()
+        .map(|(score, path)| format!("{:>10.1}  {}", score, path.display().to_string().cyan()))
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+/// Shows a numbered list of the best matches for `zi` and asks which one to
+/// go to. Returns None if nothing was picked.
+pub fn pick(fragments: &[String], shell: &Shell) -> Result<Option<PathBuf>, String> {
+    let found: Vec<PathBuf> = matches(fragments, shell)
+        .into_iter()
+        .take(PICKER_SIZE)
+        .map(|(_, path)| path)
+        .collect();
+    match found.len() {
+        0 => return Err(failure("zi: no matching directories")),
+        1 => return Ok(found.into_iter().next()),
+        _ => {}
+    }
+
+    for (i, path) in found.iter().enumerate() {
+        eprintln!("{:>3}  {}", (i + 1).to_string().yellow(), path.display());
+    }
+    eprint!(
+        "{} ",
+        format!("Jump to [1-{}]:", found.len()).bright_purple()
+    );
+    let _ = io::stderr().flush();
+
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return Ok(None);
+    }
+    match answer.trim() {
+        "" => Ok(None),
+        choice => choice
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| n.checked_sub(1))
+            .and_then(|i| found.get(i).cloned())
+            .map(Some)
+            .ok_or_else(|| failure(&format!("zi: invalid choice '{}'", choice))),
+    }
+}
+
+/// Removes directories that no longer exist from the database.
+pub fn purge(shell: &Shell) -> Result<String, String> {
+    let mut entries = load(shell);
+    let before = entries.len();
+    entries.retain(|entry| entry.path.is_dir());
+    let removed = before - entries.len();
+    save(&entries, shell).map_err(|e| failure(&format!("z: {}", e)))?;
+    Ok(success(&format!(
+        "Purged {} missing director{}",
+        removed,
+        if removed == 1 { "y" } else { "ies" }
+    )))
+}
@@ -37,10 +37,24 @@ fn general_help() -> String {
         "  {}    - Push directory to stack\n",
         "pushd".green()
     ));
+    help.push_str(&format!(
+        "  {}     - Show the directory stack\n",
+        "dirs".green()
+    ));
     help.push_str(&format!(
         "  {}     - Pop directory from stack\n",
         "popd".green()
     ));
+    help.push_str(&format!(
+        "  {}        - Jump to a frequently used directory
+",
+        "z".green()
+    ));
+    help.push_str(&format!(
+        "  {}       - Pick a frequently used directory interactively
+",
+        "zi".green()
+    ));
 
     // Process Management
     help.push_str(&format!(
@@ -48,6 +62,10 @@ fn general_help() -> String {
         "Process Management:".bright_blue().bold()
     ));
     help.push_str(&format!("  {}     - List active jobs\n", "jobs".green()));
+    help.push_str(&format!(
+        "  {}     - Time a command or pipeline\n",
+        "time".green()
+    ));
 
     help.push_str(&format!(
         "\n{}\n",
@@ -73,7 +91,41 @@ fn general_help() -> String {
         "  {}      - Remove file/directory\n",
         "rm".green()
     ));
+    help.push_str(&format!(
+        "  {}      - Copy files/directories\n",
+        "cp".green()
+    ));
+    help.push_str(&format!(
+        "  {}      - Move or rename files/directories\n",
+        "mv".green()
+    ));
+    help.push_str(&format!(
+        "  {}    - Move files to the trash and restore them\n",
+        "trash".green()
+    ));
     help.push_str(&format!("  {}    - Create empty file\n", "touch".green()));
+    help.push_str(&format!(
+        "  {}      - Show files, highlighted on the terminal\n",
+        "cat".green()
+    ));
+    help.push_str(&format!(
+        "  {}/{} - Show the start or end of files\n",
+        "head".green(),
+        "tail".green()
+    ));
+    help.push_str(&format!(
+        "  {}       - Count lines, words and bytes\n",
+        "wc".green()
+    ));
+    help.push_str(&format!(
+        "  {}     - Show a directory tree\n",
+        "tree".green()
+    ));
+    help.push_str(&format!("  {}       - Show disk usage\n", "du".green()));
+    help.push_str(&format!(
+        "  {}     - Search for files by name, type, size or age\n",
+        "find".green()
+    ));
 
     help.push_str(&format!("\n{}\n", "Shell Management:".bright_blue().bold()));
     help.push_str(&format!(
@@ -98,14 +150,36 @@ fn general_help() -> String {
         "  {}    - Define/display aliases\n",
         "alias".green()
     ));
+    help.push_str(&format!("  {}  - Remove aliases\n", "unalias".green()));
+    help.push_str(&format!(
+        "  {}  - Run a command, skipping aliases and functions\n",
+        "command".green()
+    ));
     help.push_str(&format!(
-        "  {}   - Set environment variables\n",
+        "  {}  - Set variable attributes (also typeset)\n",
+        "declare".green()
+    ));
+    help.push_str(&format!(
+        "  {} - Make variables readonly\n",
+        "readonly".green()
+    ));
+    help.push_str(&format!(
+        "  {}    - Remove variables or functions\n",
+        "unset".green()
+    ));
+    help.push_str(&format!("  {}      - List all variables\n", "set".green()));
+    help.push_str(&format!(
+        "  {}   - Pass variables on to commands\n",
         "export".green()
     ));
     help.push_str(&format!(
-        "  {}      - Show environment variables\n",
+        "  {}      - Show or change the environment of a command\n",
         "env".green()
     ));
+    help.push_str(&format!(
+        "  {}   - Save aliases and variables in ~/.batcaverc\n",
+        "config".green()
+    ));
 
     help.push_str(&format!("\n{}\n", "System & Help:".bright_blue().bold()));
     help.push_str(&format!(
@@ -123,9 +197,17 @@ fn general_help() -> String {
 
     help.push_str(&format!("\n{}\n", "Shell Features:".bright_blue().bold()));
     help.push_str(" • Command history (↑/↓ arrows)\n");
+    help.push_str(" • History suggestions (→/Ctrl-F to accept, Alt-F for one word)\n");
     help.push_str(" • Tab completion for commands & files\n");
     help.push_str(" • Environment variable expansion ($VAR)\n");
+    help.push_str(" • Multi-line input for open quotes, pipes and blocks ($PS2)\n");
     help.push_str(" • Custom aliases and configurations\n");
+    help.push_str(" • Custom prompts with PS1, PS2 and RPROMPT\n");
+    help.push_str(" • Functions and precmd/preexec/chpwd/zshexit hooks\n");
+    help.push_str(" • Conditions with test, [ ] and [[ ]]\n");
+    help.push_str(" • Subshells ( ... ) and brace groups { ...; } with redirections\n");
+    help.push_str(" • Process substitution <(cmd) and >(cmd), statuses in $PROCSUB_STATUS\n");
+    help.push_str(" • Here-documents (<<EOF, <<-EOF, <<'EOF') and here-strings (<<<)\n");
 
     help.push_str(&format!("\n{}: ", "Usage".bright_yellow()));
     help.push_str("help <command> for specific command details\n");
@@ -136,17 +218,34 @@ fn general_help() -> String {
 fn command_specific_help(command: &str) -> String {
     let help_text = match command {
         "pushd" => format!(
-            "{}\n{}\n\n{}\n  pushd /path/to/dir",
-            "pushd <directory>".bright_yellow().bold(),
-            "Push current directory to stack and change to new directory".bright_blue(),
-            "Example:".bright_green()
+            "{}\n{}\n\n{}\n  pushd /path/to/dir   # save the current directory and go there\n  pushd                # swap the top two directories\n  pushd +2             # rotate entry 2 (see dirs -v) to the top\n  pushd -n /etc        # add to the stack without changing directory",
+            "pushd [-n] [directory | +N | -N]".bright_yellow().bold(),
+            "Push directories onto the directory stack".bright_blue(),
+            "Examples:".bright_green()
         ),
 
         "popd" => format!(
-            "{}\n{}\n\n{}\n  popd",
-            "popd".bright_yellow().bold(),
-            "Pop directory from stack and change to it".bright_blue(),
-            "Example:".bright_green()
+            "{}\n{}\n\n{}\n  popd      # go back to the previous directory\n  popd +1   # forget entry 1 of dirs -v\n  popd -n   # drop the top entry but stay here",
+            "popd [-n] [+N | -N]".bright_yellow().bold(),
+            "Pop directories from the directory stack".bright_blue(),
+            "Examples:".bright_green()
+        ),
+
+        "dirs" => format!(
+            "{}\n{}\n\n{}\n  -v  one entry per line with its index\n  -p  one entry per line\n  -l  full paths instead of ~\n  -c  clear the stack\n\n{}\n  dirs -v\n  cd -2                # go to entry 2 counting from the end\n  echo ${{DIRSTACK[1]}}  # the stack is also in $DIRSTACK",
+            "dirs [-clpv] [+N | -N]".bright_yellow().bold(),
+            "Show the directory stack, starting with the current directory".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "z" | "zi" => format!(
+            "{}\n{}\n\n{}\n  z proj        # best match for 'proj'\n  z src batcave # both fragments must match\n  zi doc        # choose from the matches\n  z -l rs       # list matches with their scores\n  z --purge     # forget directories that no longer exist\n\nEvery directory changed to from the prompt is remembered in\n~/.batcave_dirs, ranked by how often and how recently it was visited.\nChanges made by ~/.batcaverc, hooks and functions are not recorded.",
+            "z <fragment...> | zi [fragment...] | z -l [fragment...] | z --purge"
+                .bright_yellow()
+                .bold(),
+            "Jump to the highest ranked directory matching the fragments".bright_blue(),
+            "Examples:".bright_green()
         ),
 
         "jobs" => format!(
@@ -178,66 +277,220 @@ fn command_specific_help(command: &str) -> String {
         ),
 
         "pwd" => format!(
-            "{}\n{}\n\n{}\n  pwd",
-            "pwd".bright_yellow().bold(),
+            "{}\n{}\n\n{}\n  pwd\n  pwd -P   # with symlinks resolved",
+            "pwd [-P]".bright_yellow().bold(),
             "Print current working directory path".bright_blue(),
             "Example:".bright_green()
         ),
 
         "cd" => format!(
-            "{}\n{}\n\n{}\n  cd /home/user\n  cd ..\n  cd ~",
-            "cd [directory]".bright_yellow().bold(),
+            "{}\n{}\n\n{}\n  cd /home/user\n  cd ..\n  cd        # go home\n  cd -      # go back to $OLDPWD\n  cd -1     # go to an entry of the directory stack\n  cd -P link   # resolve symlinks\n\nRelative names are also looked up in the directories listed in $CDPATH.",
+            "cd [-L|-P] [directory|-|+N|-N]".bright_yellow().bold(),
             "Change current directory".bright_blue(),
             "Examples:".bright_green()
         ),
 
         "ls" => format!(
-            "{}\n{}\n\n{}\n  ls\n  ls /home\n  ls -la",
-            "ls [directory]".bright_yellow().bold(),
+            "{}\n{}\n\n{}\n  -l  long format       -a  include . and ..  -A  hidden files\n  -h  human sizes       -R  recursive         -d  directories themselves\n  -t  sort by time      -S  sort by size      -r  reverse order\n  -1  one per line\n\n{}\n  ls\n  ls /home\n  ls -la\n  ls -lhS ~/Downloads\n\nColors come from $LS_COLORS.",
+            "ls [options] [path...]".bright_yellow().bold(),
             "List directory contents".bright_blue(),
+            "Options:".bright_green(),
             "Examples:".bright_green()
         ),
 
         "mkdir" => format!(
-            "{}\n{}\n\n{}\n  mkdir new_folder\n  mkdir -p parent/child",
-            "mkdir <directory>".bright_yellow().bold(),
-            "Create new directory".bright_blue(),
+            "{}\n{}\n\n{}\n  -p       create parent directories as needed\n  -m MODE  set the mode, e.g. 700 or u=rwx,go=rx\n  -v       print each directory created\n\n{}\n  mkdir new_folder\n  mkdir -p parent/child\n  mkdir -m 700 private another",
+            "mkdir [options] <directory...>".bright_yellow().bold(),
+            "Create new directories".bright_blue(),
+            "Options:".bright_green(),
             "Examples:".bright_green()
         ),
 
         "rm" => format!(
-            "{}\n{}\n\n{}\n  rm file.txt\n  rm -r directory",
-            "rm <path>".bright_yellow().bold(),
-            "Remove file or directory".bright_blue(),
+            "{}\n{}\n\n{}\n  -r  remove directories and their contents\n  -f  ignore missing files, never prompt\n  -i  prompt before every removal\n  -v  explain what is being done\n  -d  remove empty directories\n  --trash  move to the trash instead (or export RM_TRASH=\"true\")\n  --no-preserve-root  allow removing / or $HOME\n\n{}\n  rm file.txt\n  rm -r directory\n  rm -iv *.log",
+            "rm [options] <path...>".bright_yellow().bold(),
+            "Remove files or directories".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "cp" => format!(
+            "{}\n{}\n\n{}\n  -r  copy directories recursively\n  -p  keep modification times (-a = -rp)\n  -L  follow symlinks, also inside directories\n  -P  copy symlinks as links (the default with -r)\n  -i  prompt before overwriting\n  -n  never overwrite\n  -u  only copy when the source is newer\n  -v  explain what is being done\n\n{}\n  cp notes.txt backup.txt\n  cp -rv src/ /tmp/src-copy\n  cp -u *.rs ~/backup",
+            "cp [options] <source...> <destination>".bright_yellow().bold(),
+            "Copy files and directories, with a progress bar for large copies".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "mv" => format!(
+            "{}\n{}\n\n{}\n  -i  prompt before overwriting\n  -n  never overwrite\n  -u  only move when the source is newer\n  -v  explain what is being done\n\n{}\n  mv old.txt new.txt\n  mv -iv *.log logs/",
+            "mv [options] <source...> <destination>".bright_yellow().bold(),
+            "Move or rename files and directories, across filesystems too".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "trash" => format!(
+            "{}\n{}\n\n{}\n  trash old.txt\n  trash list\n  trash restore old.txt\n  trash empty",
+            "trash <path...> | list | restore <name...> | empty".bright_yellow().bold(),
+            "Move files to the trash (~/.local/share/Trash), list, restore or empty it".bright_blue(),
             "Examples:".bright_green()
         ),
 
         "touch" => format!(
-            "{}\n{}\n\n{}\n  touch newfile.txt\n  touch file1.txt file2.txt",
-            "touch <filename>".bright_yellow().bold(),
-            "Create empty file or update timestamp".bright_blue(),
+            "{}\n{}\n\n{}\n  -a       change only the access time\n  -m       change only the modification time\n  -c       don't create missing files\n  -d DATE  use DATE (2024-05-01, \"2024-05-01 13:45\", @1714560000, yesterday)\n  -r FILE  use FILE's modification time\n\n{}\n  touch newfile.txt\n  touch file1.txt file2.txt\n  touch -d yesterday old.log",
+            "touch [options] <file...>".bright_yellow().bold(),
+            "Create empty files or update their timestamps".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "cat" => format!(
+            "{}\n{}\n\n{}\n  -n       number all lines\n  -b       number non-blank lines\n  --plain  no syntax highlighting\n\n{}\n  cat main.rs\n  cat -n notes.txt\n  cat a.txt b.txt > both.txt",
+            "cat [options] [file...]".bright_yellow().bold(),
+            "Print files, or standard input. Source code is highlighted on the terminal".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "head" => format!(
+            "{}\n{}\n\n{}\n  -n N  the first N lines (default 10), or all but the last N with -N\n  -c N  the first N bytes\n  -q    never print file headers\n\n{}\n  head -n 5 log.txt\n  history | head -3",
+            "head [options] [file...]".bright_yellow().bold(),
+            "Print the first lines of files".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "tail" => format!(
+            "{}\n{}\n\n{}\n  -n N  the last N lines (default 10), or from line N on with +N\n  -c N  the last N bytes\n  -f    keep printing appended data until Ctrl-C\n  -q    never print file headers\n\n{}\n  tail -n 20 app.log\n  tail -f app.log",
+            "tail [options] [file...]".bright_yellow().bold(),
+            "Print the last lines of files".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "wc" => format!(
+            "{}\n{}\n\n{}\n  -l  lines\n  -w  words\n  -m  characters\n  -c  bytes\n\n{}\n  wc -l *.rs\n  ls | wc -l",
+            "wc [options] [file...]".bright_yellow().bold(),
+            "Count lines, words and bytes".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "tree" => format!(
+            "{}\n{}\n\n{}\n  -a            include hidden files\n  -d            directories only\n  -L N          descend at most N levels\n  -I PATTERN    skip names matching PATTERN (a|b for several)\n  --gitignore   skip files ignored by .gitignore\n\n{}\n  tree\n  tree -L 2 src\n  tree --gitignore -I '*.lock'",
+            "tree [options] [directory...]".bright_yellow().bold(),
+            "Show the contents of directories as a tree".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "du" => format!(
+            "{}\n{}\n\n{}\n  -h               human readable sizes\n  -s               only a total for each argument\n  -d N             report directories at most N levels deep\n  -a               report files too\n  -c               add a grand total\n  --sort           biggest first\n  --apparent-size  file lengths instead of disk usage\n\n{}\n  du -sh *\n  du -h -d 1 --sort ~",
+            "du [options] [path...]".bright_yellow().bold(),
+            "Show disk usage in KiB, counting hard links once".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "find" => format!(
+            "{}\n{}\n\n{}\n  -name/-iname PATTERN   match the file name\n  -path PATTERN          match the whole path\n  -type f|d|l|p|s|b|c    file type\n  -size [+-]N[ckMG]      size, in 512-byte blocks without a suffix\n  -mtime [+-]N           modified N days ago (-mmin for minutes)\n  -newer FILE            modified after FILE\n  -empty                 empty files and directories\n  ! EXPR, EXPR -o EXPR   negation and alternatives\n  -maxdepth/-mindepth N  limit the depth\n  -print                 print the path\n  -exec CMD {{}} ;         run CMD for each match ({{}} + for all at once)\n\n{}\n  find . -name '*.rs'\n  find ~/Downloads -type f -size +100M -mtime +30\n  find . -name '*.log' -exec rm {{}} \\;",
+            "find [path...] [expression]".bright_yellow().bold(),
+            "Search for files in a directory hierarchy".bright_blue(),
+            "Tests:".bright_green(),
             "Examples:".bright_green()
         ),
 
         "alias" => format!(
-            "{}\n{}\n\n{}\n  alias ll='ls -la'\n  alias",
-            "alias [name=value]".bright_yellow().bold(),
-            "Create command aliases or show existing ones".bright_blue(),
+            "{}\n{}\n\n{}\n  alias ll='ls -la'\n  ll src            (runs ls -la src)\n  alias sudo='sudo '  (a trailing space expands the next word too)\n  \\ll               (skips the alias)\n  alias --save gs='git status'  (also writes it to ~/.batcaverc)\n  alias",
+            "alias [--save] [name=value]".bright_yellow().bold(),
+            "Create command aliases or show existing ones. Arguments are kept and aliases can use other aliases".bright_blue(),
+            "Examples:".bright_green()
+        ),
+
+        "command" => format!(
+            "{}\n{}\n\n{}\n  command ls\n  ls() {{ command ls -F \"$@\"; }}",
+            "command name [args...]".bright_yellow().bold(),
+            "Run a builtin or program, skipping aliases and functions with that name".bright_blue(),
+            "Examples:".bright_green()
+        ),
+
+        "config" => format!(
+            "{}\n{}\n\n{}\n  path             print the location of ~/.batcaverc\n  get KEY          print a saved value\n  set KEY VALUE    save a value and apply it now\n  unset KEY        remove a saved value and apply it now\n  edit             open the file in $VISUAL or $EDITOR, then reload it\n\n{}\n  config set EDITOR nvim\n  config get alias.ll\n  config unset alias.gco",
+            "config path | get KEY | set KEY VALUE | unset KEY | edit".bright_yellow().bold(),
+            "Edit ~/.batcaverc in place, keeping comments and order. KEY is a variable NAME or alias.NAME. The previous file is kept as ~/.batcaverc.bak".bright_blue(),
+            "Commands:".bright_green(),
             "Examples:".bright_green()
         ),
 
         "export" => format!(
-            "{}\n{}\n\n{}\n  export PATH=$PATH:/new/path\n  export EDITOR=vim",
-            "export NAME=value".bright_yellow().bold(),
-            "Set environment variables".bright_blue(),
+            "{}\n{}\n\n{}\n  -n  stop exporting the variables\n  -p  list exported variables (the default without names)\n  --save  also write the variables to ~/.batcaverc\n\n{}\n  export PATH=$PATH:/new/path\n  export --save EDITOR=vim\n  NAME=value; export NAME\n  DEBUG=1 cargo run    (only for this command)",
+            "export [-n] [-p] [--save] [NAME[=value]...]".bright_yellow().bold(),
+            "Pass variables on to the commands the shell runs. Plain NAME=value assignments stay local to the shell".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "unalias" => format!(
+            "{}\n{}\n\n{}\n  -a  remove every alias\n\n{}\n  unalias ll\n  unalias -a",
+            "unalias [-a] name...".bright_yellow().bold(),
+            "Remove aliases".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "unset" => format!(
+            "{}\n{}\n\n{}\n  -v  only remove variables\n  -f  only remove functions\n\n{}\n  unset EDITOR\n  unset -f greet",
+            "unset [-f|-v] name...".bright_yellow().bold(),
+            "Remove variables or functions. Readonly variables can't be removed".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "declare" | "typeset" => format!(
+            "{}\n{}\n\n{}\n  -a  indexed array\n  -A  associative array\n  -i  integer, assigned values are evaluated as arithmetic\n  -l  lowercase assigned values\n  -u  uppercase assigned values\n  -r  readonly\n  -x  export\n  -p  print variables as declare commands\n  +X  remove attribute X\n\n{}\n  declare -i count=0\n  declare -a files=(a b c)\n  declare -A ports=([http]=80 [https]=443)\n  echo ${{ports[https]}} ${{#files[@]}} \"${{files[@]}}\"\n  declare -rx API_URL=https://example.com\n  declare -p count",
+            "declare [-aAilrux] [-p] [name[=value]...]".bright_yellow().bold(),
+            "Set variable attributes, or list variables with them. typeset is the same command".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "readonly" => format!(
+            "{}\n{}\n\n{}\n  readonly VERSION=1.0\n  readonly",
+            "readonly [name[=value]...]".bright_yellow().bold(),
+            "Make variables readonly, or list the readonly ones".bright_blue(),
+            "Examples:".bright_green()
+        ),
+
+        "let" | "((" | "arithmetic" => format!(
+            "{}\n{}\n\n{}\n  + - * / % **       arithmetic\n  << >> & | ^ ~      bitwise\n  < <= > >= == !=    comparisons, 1 or 0\n  && || !            logic\n  = += -= *= /= ...  assignment\n  ++ --              increment and decrement\n  c ? a : b          conditional\n  0x1f 017 2#101     hex, octal and other bases\n\n{}\n  echo $((2 ** 10))\n  let count+=1\n  (( n > 3 )) && echo big\n  for ((i = 0; i < 5; i++)); do echo $i; done",
+            "let EXPR...   $(( EXPR ))   (( EXPR ))".bright_yellow().bold(),
+            "Evaluate integer arithmetic. Variables can be used without $; (( )) and let fail when the result is 0".bright_blue(),
+            "Operators:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "test" | "[" | "[[" => format!(
+            "{}\n{}\n\n{}\n  -e -f -d -L     exists, regular file, directory, symlink\n  -r -w -x -s     readable, writable, executable, not empty\n  a -nt b  a -ot b  newer or older than\n  -z -n           empty or non-empty string\n  = != < >        string comparisons\n  -eq -ne -lt -le -gt -ge  integer comparisons\n  ! ( )           negation and grouping\n  -a -o           and, or (test and [ ])\n  && ||           and, or ([[ ]])\n  == pattern      glob match ([[ ]])\n  =~ regex        regex match, groups in BASH_REMATCH ([[ ]])\n\n{}\n  [ -f Cargo.toml ] && echo rust\n  test \"$a\" -gt 3 -o -z \"$b\"\n  [[ $file == *.rs && -s $file ]]\n  [[ $v =~ ^([0-9]+)\\.([0-9]+)$ ]] && echo ${{BASH_REMATCH[1]}}",
+            "test EXPR   [ EXPR ]   [[ EXPR ]]".bright_yellow().bold(),
+            "Check files and compare strings or numbers. The status is 0 when true, 1 when false and 2 on errors".bright_blue(),
+            "Operators:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "set" => format!(
+            "{}\n{}\n\n{}\n  set\n  set | grep PATH\n  set -- one two three",
+            "set [-- arg...]".bright_yellow().bold(),
+            "List all variables, sorted, or replace the positional parameters".bright_blue(),
             "Examples:".bright_green()
         ),
 
         "env" => format!(
-            "{}\n{}\n\n{}\n  env",
-            "env".bright_yellow().bold(),
-            "Display all environment variables".bright_blue(),
-            "Example:".bright_green()
+            "{}\n{}\n\n{}\n  -i  start with an empty environment\n  -u  remove NAME from the environment\n\n{}\n  env\n  env -u PAGER git log\n  env -i PATH=$PATH sh",
+            "env [-i] [-u NAME] [NAME=value...] [command]".bright_yellow().bold(),
+            "Show the exported variables, or run a command with a changed environment".bright_blue(),
+            "Options:".bright_green(),
+            "Examples:".bright_green()
         ),
 
         "init" => format!(
@@ -268,6 +521,30 @@ fn command_specific_help(command: &str) -> String {
             "Example:".bright_green()
         ),
 
+        "prompt" => format!(
+            "{}\n{}\n\n{}\n  \\u user  \\h host  \\w cwd  \\W basename  \\t time  \\$ prompt char\n  \\? exit status  \\n newline  \\e[..m colors\n  \\{{git}} \\{{status}} \\{{duration}}  segments\n  \\{{red}} \\{{green}} \\{{purple}} \\{{bold}} \\{{reset}} ...  colors\n\n{}\n  export PS1=\"\\{{purple}}\\u@\\h \\W \\{{git}}\\{{reset}}\\$ \"\n  export RPROMPT=\"\\{{status}} \\{{duration}}\"\n  export REPORTTIME=5",
+            "PS1 / PS2 / RPROMPT".bright_yellow().bold(),
+            "Customize the prompt, continuation prompt and right-hand prompt".bright_blue(),
+            "Escapes:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
+        "time" => format!(
+            "{}\n{}\n\n{}\n  time cargo build\n  time ls -R | wc -l\n\n{}\n  $CMD_DURATION holds how long the last command took in milliseconds.\n  Set $REPORTTIME to only show \\{{duration}} in the prompt for slow commands.",
+            "time <pipeline>".bright_yellow().bold(),
+            "Run a pipeline and report real, user and system time".bright_blue(),
+            "Examples:".bright_green(),
+            "See also:".bright_green()
+        ),
+
+        "hooks" => format!(
+            "{}\n{}\n\n{}\n  precmd    before each prompt is drawn\n  preexec   before a command runs, with the command line as $1\n  chpwd     after the current directory changes\n  zshexit   when the shell exits\n\n{}\n  precmd() {{ echo \"last status: $?\"; }}\n  chpwd_commands=(\"ls\" \"git status\")",
+            "Hooks".bright_yellow().bold(),
+            "Define a function with the hook's name, or list commands in a NAME_commands array, in ~/.batcaverc".bright_blue(),
+            "Hooks:".bright_green(),
+            "Examples:".bright_green()
+        ),
+
         "help" => format!(
             "{}\n{}\n\n{}\n  help\n  help cd",
             "help [command]".bright_yellow().bold(),
@@ -276,10 +553,11 @@ fn command_specific_help(command: &str) -> String {
         ),
 
         "exit" => format!(
-            "{}\n{}\n\n{}\n  exit",
-            "exit".bright_yellow().bold(),
-            "Exit the Batcave shell".bright_blue(),
-            "Example:".bright_green()
+            "{}\n{}\n\n{}\n  exit\n  exit 1\n  (cd build && make || exit 2)",
+            "exit [n]".bright_yellow().bold(),
+            "Exit the shell, or the subshell it runs in, with status n (default: the last status)"
+                .bright_blue(),
+            "Examples:".bright_green()
         ),
 
         _ => format!(
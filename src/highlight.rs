@@ -0,0 +1,374 @@
+// The highlight module colors source code for `cat` previews. It is a small
+// lexer rather than a full grammar: comments, strings, numbers, keywords,
+// type names and function calls are told apart with a few rules per
+// language, which is enough to make a file easy to scan in the terminal.
+use std::path::Path;
+
+use colored::*;
+
+pub struct Language {
+    keywords: &'static [&'static str],
+    line_comments: &'static [&'static str],
+    block_comment: Option<(&'static str, &'static str)>,
+    quotes: &'static [char],
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"'],
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+        "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "self",
+        "True", "try", "while", "with", "yield",
+    ],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const JAVASCRIPT: Language = Language {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "else",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "undefined",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const C: Language = Language {
+    keywords: &[
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "char",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extern",
+        "false",
+        "float",
+        "for",
+        "goto",
+        "if",
+        "include",
+        "define",
+        "inline",
+        "int",
+        "long",
+        "namespace",
+        "new",
+        "nullptr",
+        "private",
+        "public",
+        "return",
+        "short",
+        "signed",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "template",
+        "this",
+        "true",
+        "typedef",
+        "union",
+        "unsigned",
+        "using",
+        "void",
+        "volatile",
+        "while",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const GO: Language = Language {
+    keywords: &[
+        "break",
+        "case",
+        "chan",
+        "const",
+        "continue",
+        "default",
+        "defer",
+        "else",
+        "false",
+        "fallthrough",
+        "for",
+        "func",
+        "go",
+        "goto",
+        "if",
+        "import",
+        "interface",
+        "map",
+        "nil",
+        "package",
+        "range",
+        "return",
+        "select",
+        "struct",
+        "switch",
+        "true",
+        "type",
+        "var",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\'', '`'],
+};
+
+const JAVA: Language = Language {
+    keywords: &[
+        "abstract",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "continue",
+        "default",
+        "do",
+        "else",
+        "enum",
+        "extends",
+        "false",
+        "final",
+        "finally",
+        "for",
+        "fun",
+        "if",
+        "implements",
+        "import",
+        "interface",
+        "new",
+        "null",
+        "override",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "static",
+        "super",
+        "switch",
+        "this",
+        "throw",
+        "throws",
+        "true",
+        "try",
+        "val",
+        "var",
+        "void",
+        "when",
+        "while",
+    ],
+    line_comments: &["//"],
+    block_comment: Some(("/*", "*/")),
+    quotes: &['"', '\''],
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+        "in", "local", "return", "then", "until", "while", "alias",
+    ],
+    line_comments: &["#"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const CONFIG: Language = Language {
+    keywords: &["true", "false", "null", "yes", "no", "on", "off"],
+    line_comments: &["#", ";"],
+    block_comment: None,
+    quotes: &['"', '\''],
+};
+
+const JSON: Language = Language {
+    keywords: &["true", "false", "null"],
+    line_comments: &[],
+    block_comment: None,
+    quotes: &['"'],
+};
+
+/// Picks the language of a file from its extension or name.
+pub fn language_for(path: &Path) -> Option<&'static Language> {
+    let name = path.file_name()?.to_string_lossy().to_lowercase();
+    if name.starts_with('.') && name.ends_with("rc") {
+        return Some(&SHELL);
+    }
+    let extension = path.extension()?.to_string_lossy().to_lowercase();
+    Some(match extension.as_str() {
+        "rs" => &RUST,
+        "py" | "pyw" => &PYTHON,
+        "js" | "jsx" | "mjs" | "cjs" | "ts" | "tsx" => &JAVASCRIPT,
+        "c" | "h" | "cc" | "cpp" | "cxx" | "hpp" => &C,
+        "go" => &GO,
+        "java" | "kt" | "kts" => &JAVA,
+        "sh" | "bash" | "zsh" => &SHELL,
+        "toml" | "ini" | "cfg" | "conf" | "yaml" | "yml" => &CONFIG,
+        "json" => &JSON,
+        _ => return None,
+    })
+}
+
+/// Colors `source` for the terminal. Every line is colored on its own, so
+/// the result can still be split into lines (for `cat -n`).
+pub fn highlight(source: &str, language: &Language) -> String {
+    let chars: Vec<char> = source.chars().collect();
+    let mut output = String::with_capacity(source.len() * 2);
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+        let at_word_start = i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_');
+
+        if let Some((open, close)) = language
+            .block_comment
+            .filter(|(open, _)| starts_with(rest, open))
+        {
+            let end = find_from(&chars, i + open.len(), close)
+                .map_or(chars.len(), |end| end + close.len());
+            paint(&mut output, &chars[i..end], |text| text.bright_black());
+            i = end;
+        } else if language.line_comments.iter().any(|&marker| {
+            // `#` only starts a comment after whitespace, so `$#` isn't one
+            starts_with(rest, marker) && (marker != "#" || i == 0 || chars[i - 1].is_whitespace())
+        }) {
+            let end = chars[i..]
+                .iter()
+                .position(|&c| c == '\n')
+                .map_or(chars.len(), |n| i + n);
+            paint(&mut output, &chars[i..end], |text| text.bright_black());
+            i = end;
+        } else if language.quotes.contains(&c) {
+            // only backtick strings may span lines, so a stray apostrophe
+            // doesn't color the rest of the file
+            let mut end = i + 1;
+            while end < chars.len() && chars[end] != c && (c == '`' || chars[end] != '\n') {
+                end += if chars[end] == '\\' { 2 } else { 1 };
+            }
+            let end = if chars.get(end) == Some(&c) {
+                end + 1
+            } else {
+                end.min(chars.len())
+            };
+            paint(&mut output, &chars[i..end], |text| text.green());
+            i = end;
+        } else if c.is_ascii_digit() && at_word_start {
+            let end = i + chars[i..]
+                .iter()
+                .position(|&c| !(c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+                .unwrap_or(chars.len() - i);
+            paint(&mut output, &chars[i..end], |text| text.yellow());
+            i = end;
+        } else if (c.is_alphabetic() || c == '_') && at_word_start {
+            let end = i + chars[i..]
+                .iter()
+                .position(|&c| !(c.is_alphanumeric() || c == '_'))
+                .unwrap_or(chars.len() - i);
+            let word: String = chars[i..end].iter().collect();
+            let colored = if language.keywords.contains(&word.as_str()) {
+                word.bright_purple()
+            } else if chars.get(end) == Some(&'(') || chars.get(end) == Some(&'!') {
+                word.bright_blue()
+            } else if c.is_uppercase() {
+                word.cyan()
+            } else {
+                word.normal()
+            };
+            output.push_str(&colored.to_string());
+            i = end;
+        } else {
+            output.push(c);
+            i += 1;
+        }
+    }
+    output
+}
+
+fn starts_with(chars: &[char], prefix: &str) -> bool {
+    let mut chars = chars.iter();
+    prefix.chars().all(|p| chars.next() == Some(&p))
+}
+
+fn find_from(chars: &[char], start: usize, needle: &str) -> Option<usize> {
+    (start..chars.len()).find(|&i| starts_with(&chars[i..], needle))
+}
+
+// colors each line of a token separately so no escape sequence spans a newline
+fn paint(output: &mut String, token: &[char], color: impl Fn(&str) -> ColoredString) {
+    let text: String = token.iter().collect();
+    let lines: Vec<String> = text
+        .split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                color(line).to_string()
+            }
+        })
+        .collect();
+    output.push_str(&lines.join("\n"));
+}
@@ -0,0 +1,580 @@
+// The ls module implements the `ls` builtin: long listings, hidden files,
+// sorting, recursion, column layout and LS_COLORS coloring.
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    ffi::CStr,
+    fs::{self, Metadata},
+    os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local};
+use colored::*;
+use log::error;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{exec::Io, shell::Shell, utils::human_size};
+
+// used when LS_COLORS is not set, roughly matching the dircolors defaults
+const DEFAULT_LS_COLORS: &str = "di=01;34:ln=01;36:so=01;35:pi=40;33:bd=40;33;01:cd=40;33;01:\
+or=40;31;01:ex=01;32:*.tar=01;31:*.gz=01;31:*.zip=01;31:*.xz=01;31:*.jpg=01;35:*.png=01;35:\
+*.gif=01;35:*.mp3=00;36:*.mp4=01;35";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Sort {
+    Name,
+    Time,
+    Size,
+}
+
+struct Options {
+    long: bool,
+    all: bool,
+    almost_all: bool,
+    human: bool,
+    recursive: bool,
+    directory: bool,
+    one_per_line: bool,
+    reverse: bool,
+    sort: Sort,
+}
+
+struct Entry {
+    name: String,
+    path: PathBuf,
+    metadata: Metadata,
+}
+
+/// Runs `ls` with its arguments. Names are laid out in columns when the
+/// output goes to the terminal, and listed one per line otherwise.
+pub fn list_directory(args: &[String], shell: &Shell, io: &Io) -> Result<String, String> {
+    let (options, mut paths) = parse_args(args)?;
+    if paths.is_empty() {
+        paths.push(String::from("."));
+    }
+
+    let mut lister = Lister {
+        options,
+        colors: LsColors::from_shell(shell),
+        width: io.terminal_width(),
+        users: HashMap::new(),
+        groups: HashMap::new(),
+        sections: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    let mut files = Vec::new();
+    let mut dirs = Vec::new();
+    for path in &paths {
+        match fs::symlink_metadata(path) {
+            Ok(metadata) => {
+                let is_dir = metadata.is_dir()
+                    || (metadata.is_symlink() && !lister.options.long && Path::new(path).is_dir());
+                let entry = Entry {
+                    name: path.clone(),
+                    path: PathBuf::from(path),
+                    metadata,
+                };
+                if is_dir && !lister.options.directory {
+                    dirs.push(entry);
+                } else {
+                    files.push(entry);
+                }
+            }
+            Err(e) => lister.error(&format!("cannot access '{}': {}", path, e)),
+        }
+    }
+
+    lister.sort(&mut files);
+    lister.sort(&mut dirs);
+    if !files.is_empty() {
+        let listing = lister.format(&files, false);
+        lister.sections.push(listing);
+    }
+    let show_headers = paths.len() > 1 || lister.options.recursive;
+    for dir in &dirs {
+        lister.list(&dir.path, show_headers);
+    }
+
+    let output = lister.sections.join("\n\n");
+    io.finish(output, lister.errors)
+}
+
+fn parse_args(args: &[String]) -> Result<(Options, Vec<String>), String> {
+    let mut options = Options {
+        long: false,
+        all: false,
+        almost_all: false,
+        human: false,
+        recursive: false,
+        directory: false,
+        one_per_line: false,
+        reverse: false,
+        sort: Sort::Name,
+    };
+    let mut paths = Vec::new();
+    let mut only_paths = false;
+
+    for arg in args {
+        if only_paths || arg == "-" || !arg.starts_with('-') {
+            paths.push(arg.clone());
+            continue;
+        }
+        match arg.as_str() {
+            "--" => only_paths = true,
+            "--all" => options.all = true,
+            "--almost-all" => options.almost_all = true,
+            "--human-readable" => options.human = true,
+            "--recursive" => options.recursive = true,
+            "--reverse" => options.reverse = true,
+            "--directory" => options.directory = true,
+            long if long.starts_with("--") => {
+                return Err(format!(
+                    "{}ls: unrecognized option '{}'{}",
+                    "[".red(),
+                    long,
+                    "]".red()
+                ));
+            }
+            flags => {
+                for flag in flags.chars().skip(1) {
+                    match flag {
+                        'l' => options.long = true,
+                        'a' => options.all = true,
+                        'A' => options.almost_all = true,
+                        'h' => options.human = true,
+                        'R' => options.recursive = true,
+                        'd' => options.directory = true,
+                        '1' => options.one_per_line = true,
+                        'r' => options.reverse = true,
+                        't' => options.sort = Sort::Time,
+                        'S' => options.sort = Sort::Size,
+                        _ => {
+                            return Err(format!(
+                                "{}ls: invalid option -- '{}'{}",
+                                "[".red(),
+                                flag,
+                                "]".red()
+                            ));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok((options, paths))
+}
+
+struct Lister {
+    options: Options,
+    colors: LsColors,
+    width: Option<usize>,
+    users: HashMap<u32, String>,
+    groups: HashMap<u32, String>,
+    sections: Vec<String>,
+    errors: Vec<String>,
+}
+
+impl Lister {
+    fn error(&mut self, message: &str) {
+        error!("ls: {}", message);
+        self.errors
+            .push(format!("{}ls: {}{}", "[".red(), message, "]".red()));
+    }
+
+    // lists one directory, then its subdirectories when recursing
+    fn list(&mut self, dir: &Path, show_header: bool) {
+        let entries = match self.read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                self.error(&format!("cannot open directory '{}': {}", dir.display(), e));
+                return;
+            }
+        };
+
+        let listing = self.format(&entries, true);
+        self.sections.push(match (show_header, listing.is_empty()) {
+            (false, _) => listing,
+            (true, true) => format!("{}:", dir.display()),
+            (true, false) => format!("{}:\n{}", dir.display(), listing),
+        });
+
+        if self.options.recursive {
+            for entry in &entries {
+                if entry.metadata.is_dir() && entry.name != "." && entry.name != ".." {
+                    self.list(&entry.path, true);
+                }
+            }
+        }
+    }
+
+    fn read_dir(&self, dir: &Path) -> std::io::Result<Vec<Entry>> {
+        let mut entries = Vec::new();
+        if self.options.all {
+            for name in [".", ".."] {
+                let path = dir.join(name);
+                if let Ok(metadata) = fs::symlink_metadata(&path) {
+                    entries.push(Entry {
+                        name: name.to_string(),
+                        path,
+                        metadata,
+                    });
+                }
+            }
+        }
+
+        for entry in fs::read_dir(dir)?.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') && !self.options.all && !self.options.almost_all {
+                continue;
+            }
+            if let Ok(metadata) = entry.path().symlink_metadata() {
+                entries.push(Entry {
+                    name,
+                    path: entry.path(),
+                    metadata,
+                });
+            }
+        }
+
+        self.sort(&mut entries);
+        Ok(entries)
+    }
+
+    fn sort(&self, entries: &mut [Entry]) {
+        let by_name = |a: &Entry, b: &Entry| {
+            let key = |name: &str| name.trim_start_matches('.').to_lowercase();
+            key(&a.name)
+                .cmp(&key(&b.name))
+                .then_with(|| a.name.cmp(&b.name))
+        };
+        let modified = |entry: &Entry| entry.metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+
+        entries.sort_by(|a, b| {
+            let order = match self.options.sort {
+                Sort::Name => Ordering::Equal,
+                Sort::Time => modified(b).cmp(&modified(a)),
+                Sort::Size => b.metadata.len().cmp(&a.metadata.len()),
+            };
+            order.then_with(|| by_name(a, b))
+        });
+        if self.options.reverse {
+            entries.reverse();
+        }
+    }
+
+    fn format(&mut self, entries: &[Entry], show_total: bool) -> String {
+        if self.options.long {
+            return self.format_long(entries, show_total);
+        }
+
+        let names: Vec<(String, usize)> = entries
+            .iter()
+            .map(|entry| (self.colors.paint(entry), entry.name.width()))
+            .collect();
+        match self.width {
+            Some(width) if !self.options.one_per_line => layout_columns(&names, width),
+            _ => names
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+
+    fn format_long(&mut self, entries: &[Entry], show_total: bool) -> String {
+        let rows: Vec<[String; 6]> = entries
+            .iter()
+            .map(|entry| {
+                let metadata = &entry.metadata;
+                let size = if self.options.human {
+                    human_size(metadata.len())
+                } else {
+                    metadata.len().to_string()
+                };
+                let mut name = self.colors.paint(entry);
+                if metadata.is_symlink() {
+                    if let Ok(target) = fs::read_link(&entry.path) {
+                        name = format!("{} -> {}", name, target.display());
+                    }
+                }
+                [
+                    mode_string(metadata),
+                    metadata.nlink().to_string(),
+                    self.user_name(metadata.uid()),
+                    self.group_name(metadata.gid()),
+                    size,
+                    format!("{} {}", format_mtime(metadata), name),
+                ]
+            })
+            .collect();
+
+        let mut widths = [0; 5];
+        for row in &rows {
+            for (width, field) in widths.iter_mut().zip(row) {
+                *width = (*width).max(field.width());
+            }
+        }
+
+        let mut lines = Vec::new();
+        if show_total {
+            // st_blocks counts 512-byte blocks; ls reports 1K blocks
+            let blocks: u64 = entries.iter().map(|entry| entry.metadata.blocks()).sum();
+            let total = if self.options.human {
+                human_size(blocks * 512)
+            } else {
+                (blocks / 2).to_string()
+            };
+            lines.push(format!("total {}", total));
+        }
+        for [mode, links, user, group, size, rest] in rows {
+            lines.push(format!(
+                "{:<w0$} {:>w1$} {:<w2$} {:<w3$} {:>w4$} {}",
+                mode,
+                links,
+                user,
+                group,
+                size,
+                rest,
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+            ));
+        }
+        lines.join("\n")
+    }
+
+    fn user_name(&mut self, uid: u32) -> String {
+        self.users
+            .entry(uid)
+            .or_insert_with(|| {
+                // SAFETY: getpwuid returns null or a pointer to a static
+                // record that stays valid until the next call
+                let passwd = unsafe { libc::getpwuid(uid) };
+                if passwd.is_null() {
+                    uid.to_string()
+                } else {
+                    unsafe { CStr::from_ptr((*passwd).pw_name) }
+                        .to_string_lossy()
+                        .into_owned()
+                }
+            })
+            .clone()
+    }
+
+    fn group_name(&mut self, gid: u32) -> String {
+        self.groups
+            .entry(gid)
+            .or_insert_with(|| {
+                // SAFETY: as for getpwuid above
+                let group = unsafe { libc::getgrgid(gid) };
+                if group.is_null() {
+                    gid.to_string()
+                } else {
+                    unsafe { CStr::from_ptr((*group).gr_name) }
+                        .to_string_lossy()
+                        .into_owned()
+                }
+            })
+            .clone()
+    }
+}
+
+// arranges names in as many columns as fit in the terminal, filling each
+// column top to bottom like GNU ls
+fn layout_columns(names: &[(String, usize)], width: usize) -> String {
+    if names.is_empty() {
+        return String::new();
+    }
+
+    let mut rows = 1;
+    let mut column_widths = Vec::new();
+    while rows < names.len() {
+        column_widths = names
+            .chunks(rows)
+            .map(|column| column.iter().map(|(_, width)| width + 2).max().unwrap_or(0))
+            .collect();
+        if column_widths.iter().sum::<usize>() - 2 <= width {
+            break;
+        }
+        rows += 1;
+    }
+    if rows >= names.len() {
+        return names
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    let mut lines = Vec::new();
+    for row in 0..rows {
+        let mut line = String::new();
+        for (column, column_width) in column_widths.iter().enumerate() {
+            let Some((name, name_width)) = names.get(column * rows + row) else {
+                break;
+            };
+            line.push_str(name);
+            if names.get((column + 1) * rows + row).is_some() {
+                line.push_str(&" ".repeat(column_width - name_width));
+            }
+        }
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+fn mode_string(metadata: &Metadata) -> String {
+    let file_type = metadata.file_type();
+    let kind = if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_block_device() {
+        'b'
+    } else if file_type.is_char_device() {
+        'c'
+    } else {
+        '-'
+    };
+
+    let mode = metadata.permissions().mode();
+    let bit = |mask: u32, c: char| if mode & mask != 0 { c } else { '-' };
+    // setuid, setgid and sticky bits replace the execute bit of their triple
+    let special = |exec: u32, special: u32, set: char| match (mode & exec != 0, mode & special != 0)
+    {
+        (true, true) => set,
+        (false, true) => set.to_ascii_uppercase(),
+        (true, false) => 'x',
+        (false, false) => '-',
+    };
+
+    [
+        kind,
+        bit(0o400, 'r'),
+        bit(0o200, 'w'),
+        special(0o100, 0o4000, 's'),
+        bit(0o040, 'r'),
+        bit(0o020, 'w'),
+        special(0o010, 0o2000, 's'),
+        bit(0o004, 'r'),
+        bit(0o002, 'w'),
+        special(0o001, 0o1000, 't'),
+    ]
+    .iter()
+    .collect()
+}
+
+// shows the time for recent files and the year for files older than six months
+fn format_mtime(metadata: &Metadata) -> String {
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    let six_months = Duration::from_secs(60 * 60 * 24 * 182);
+    let recent = SystemTime::now()
+        .duration_since(modified)
+        .map(|age| age < six_months)
+        .unwrap_or(false);
+
+    let time: DateTime<Local> = modified.into();
+    if recent {
+        time.format("%b %e %H:%M").to_string()
+    } else {
+        time.format("%b %e  %Y").to_string()
+    }
+}
+
+/// Colors parsed from an LS_COLORS string such as `di=01;34:*.rs=33`.
+pub struct LsColors {
+    types: HashMap<String, String>,
+    extensions: Vec<(String, String)>,
+}
+
+impl LsColors {
+    /// Uses $LS_COLORS, or the dircolors defaults when it isn't set.
+    pub fn from_shell(shell: &Shell) -> Self {
+        LsColors::parse(
+            shell
+                .get_env("LS_COLORS")
+                .map_or(DEFAULT_LS_COLORS, |colors| colors.as_str()),
+        )
+    }
+
+    fn parse(spec: &str) -> Self {
+        let mut colors = LsColors {
+            types: HashMap::new(),
+            extensions: Vec::new(),
+        };
+        for rule in spec.split(':') {
+            let Some((key, code)) = rule.split_once('=') else {
+                continue;
+            };
+            match key.strip_prefix('*') {
+                Some(suffix) => colors
+                    .extensions
+                    .push((suffix.to_lowercase(), code.to_string())),
+                None => {
+                    colors.types.insert(key.to_string(), code.to_string());
+                }
+            }
+        }
+        colors
+    }
+
+    fn code_for(&self, name: &str, path: &Path, metadata: &Metadata) -> Option<&str> {
+        let file_type = metadata.file_type();
+        let key = if file_type.is_symlink() {
+            if path.exists() {
+                "ln"
+            } else {
+                "or"
+            }
+        } else if file_type.is_dir() {
+            "di"
+        } else if file_type.is_fifo() {
+            "pi"
+        } else if file_type.is_socket() {
+            "so"
+        } else if file_type.is_block_device() {
+            "bd"
+        } else if file_type.is_char_device() {
+            "cd"
+        } else {
+            let name = name.to_lowercase();
+            if let Some((_, code)) = self
+                .extensions
+                .iter()
+                .find(|(suffix, _)| name.ends_with(suffix.as_str()))
+            {
+                return Some(code);
+            }
+            if metadata.permissions().mode() & 0o111 != 0 {
+                "ex"
+            } else {
+                "fi"
+            }
+        };
+        self.types.get(key).map(String::as_str)
+    }
+
+    fn paint(&self, entry: &Entry) -> String {
+        self.paint_name(&entry.name, &entry.path, &entry.metadata)
+    }
+
+    /// Colors `name` according to the type of the file at `path`.
+    pub fn paint_name(&self, name: &str, path: &Path, metadata: &Metadata) -> String {
+        match self.code_for(name, path, metadata) {
+            Some(code) if colored::control::SHOULD_COLORIZE.should_colorize() => {
+                format!("\x1b[{}m{}\x1b[0m", code, name)
+            }
+            _ => name.to_string(),
+        }
+    }
+}
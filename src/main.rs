@@ -2,22 +2,41 @@
 // It provides a set of built-in commands, supports external command execution,
 // and includes features like environment variable management, command aliases,
 // and auto-completion.
+mod arith;
 mod commands;
+mod conditions;
+mod config;
+mod dirstack;
+mod exec;
+mod expand;
+mod explore;
+mod fileops;
+mod frecency;
 mod help;
+mod highlight;
+mod ls;
+mod parser;
+mod prompt;
 mod shell;
 mod system;
+mod textops;
+mod trash;
 mod utils;
+mod variables;
 
 use crate::commands::execute_command;
-use crate::shell::{Shell, ShellHelper};
+use crate::exec::{run_hook, Flow};
+use crate::parser::{check_input, InputStatus};
+use crate::prompt::{render_prompt, DEFAULT_PS1, DEFAULT_PS2};
+use crate::shell::{AcceptHint, Shell, ShellHelper};
 use system::system_info;
 use utils::{print_banner, setup_logging};
 
-use std::{env, io};
+use std::io;
 
 use colored::*;
 use log::info;
-use rustyline::{error::ReadlineError, Editor};
+use rustyline::{error::ReadlineError, Editor, EventHandler, KeyEvent};
 
 fn main() -> io::Result<()> {
     setup_logging()?;
@@ -25,8 +44,16 @@ fn main() -> io::Result<()> {
 
     let mut shell = Shell::new();
     let helper = ShellHelper::new(&shell);
-    let mut rl = Editor::new().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let mut rl = Editor::new().map_err(io::Error::other)?;
     rl.set_helper(Some(helper));
+    rl.bind_sequence(
+        KeyEvent::ctrl('F'),
+        EventHandler::Conditional(Box::new(AcceptHint::Full)),
+    );
+    rl.bind_sequence(
+        KeyEvent::alt('f'),
+        EventHandler::Conditional(Box::new(AcceptHint::Word)),
+    );
 
     shell.add_alias("ll".to_string(), "ls -la".to_string());
     shell.add_alias("cls".to_string(), "clear".to_string());
@@ -48,30 +75,43 @@ fn main() -> io::Result<()> {
     println!();
 
     loop {
-        let current_dir = env::current_dir()?;
-        let prompt = format!("🦇 {}> ", current_dir.display())
-            .bright_purple()
-            .to_string();
+        run_hook("precmd", &[], &mut shell);
+        if let Some(helper) = rl.helper_mut() {
+            helper.sync(&shell);
+        }
+
+        let ps1 = shell.get_env("PS1").map_or(DEFAULT_PS1, |ps1| ps1.as_str());
+        let prompt = render_prompt(ps1, &shell);
+        let rprompt = shell
+            .get_env("RPROMPT")
+            .map(|rprompt| render_prompt(rprompt, &shell))
+            .unwrap_or_default();
+        let columns = rl.dimensions().map_or(80, |(columns, _)| columns);
+        if let Some(helper) = rl.helper_mut() {
+            helper.set_prompt(&prompt, rprompt, columns);
+        }
 
         let readline = rl.readline(&prompt);
 
         match readline {
             Ok(line) => {
+                let Some(line) = read_continuation(&mut rl, &shell, line) else {
+                    continue;
+                };
                 let input = line.trim().to_string();
                 if input.is_empty() {
                     continue;
                 }
-                if input == "exit" {
-                    println!("{}", "Exiting the Batcave...".bright_blue());
-                    break;
-                }
 
                 rl.add_history_entry(input.as_str());
                 shell.add_to_history(input.clone());
 
-                let output = execute_command(&input, &mut shell);
-                println!("{}", output.bright_white());
+                execute_command(&input, &mut shell);
                 info!("Executed command: {}", input);
+                if shell.flow == Flow::Exit {
+                    println!("{}", "Exiting the Batcave...".bright_blue());
+                    break;
+                }
             }
             Err(ReadlineError::Interrupted) => {
                 println!("CTRL-C");
@@ -87,5 +127,42 @@ fn main() -> io::Result<()> {
             }
         }
     }
+    let exit_status = (shell.flow == Flow::Exit).then_some(shell.last_status);
+    run_hook("zshexit", &[], &mut shell);
+    if let Some(status) = exit_status {
+        std::process::exit(status);
+    }
     Ok(())
 }
+
+// Keeps reading lines with the PS2 prompt while the input is incomplete
+// (open quotes, a trailing `|` or `\`, an unclosed block or heredoc).
+// Returns None if the user abandons the command with Ctrl-C or Ctrl-D.
+fn read_continuation(
+    rl: &mut Editor<ShellHelper>,
+    shell: &Shell,
+    mut input: String,
+) -> Option<String> {
+    while check_input(&input) == InputStatus::Incomplete {
+        if let Some(helper) = rl.helper_mut() {
+            helper.set_pending(&input);
+        }
+        let ps2 = shell.get_env("PS2").map_or(DEFAULT_PS2, |ps2| ps2.as_str());
+        let ps2 = render_prompt(ps2, shell);
+        if let Some(helper) = rl.helper_mut() {
+            helper.set_prompt(&ps2, String::new(), 0);
+        }
+        let next = rl.readline(&ps2);
+        if let Some(helper) = rl.helper_mut() {
+            helper.set_pending("");
+        }
+        match next {
+            Ok(line) => {
+                input.push('\n');
+                input.push_str(&line);
+            }
+            Err(_) => return None,
+        }
+    }
+    Some(input)
+}
@@ -0,0 +1,1122 @@
+// The parser module turns raw input into tokens and then into a command tree
+// that the executor walks. The line editor also uses it to decide whether to
+// execute the input or to keep reading with the continuation prompt (PS2).
+use std::rc::Rc;
+
+const OPERATORS: [&str; 19] = [
+    "<<<", "<<-", "&&", "||", ";;", "<<", ">>", ">&", "<&", "&>", ">|", "|", "&", ";", "(", ")",
+    "<", ">", "\n",
+];
+
+const REDIRECTIONS: [&str; 10] = ["<", ">", ">>", "<<", "<<-", "<<<", ">&", "<&", "&>", ">|"];
+
+/// A single lexical token. Words keep their quotes so that expansion can
+/// tell quoted and unquoted text apart later on.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    Operator(&'static str),
+    // a file descriptor number directly in front of a redirection, as in `2>`
+    IoNumber(i32),
+    // the body of a here-document, which follows its delimiter word
+    HereDoc(String),
+}
+
+/// The result of checking a command line before it is executed.
+#[derive(Debug, PartialEq)]
+pub enum InputStatus {
+    Complete,
+    Incomplete,
+    Invalid(String),
+}
+
+/// Raised by the lexer when the input ends in the middle of a construct.
+#[derive(Debug, PartialEq)]
+pub struct Unterminated;
+
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    // the delimiter, whether `<<-` strips leading tabs, and the index of
+    // the token that receives the body
+    pending_heredocs: Vec<(String, bool, usize)>,
+}
+
+impl Lexer {
+    fn new(input: &str) -> Self {
+        Lexer {
+            chars: input.chars().collect(),
+            pos: 0,
+            pending_heredocs: Vec::new(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    // `<(` and `>(` start a word rather than a redirection
+    fn at_process_substitution(&self) -> bool {
+        self.starts_with("<(") || self.starts_with(">(")
+    }
+
+    fn starts_with(&self, s: &str) -> bool {
+        s.chars()
+            .enumerate()
+            .all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+    }
+
+    fn tokens(mut self) -> Result<Vec<Token>, Unterminated> {
+        let mut tokens = Vec::new();
+
+        loop {
+            // skip blanks and line continuations
+            while let Some(c) = self.peek() {
+                if c == ' ' || c == '\t' {
+                    self.pos += 1;
+                } else if self.starts_with("\\\n") {
+                    self.pos += 2;
+                } else {
+                    break;
+                }
+            }
+
+            let Some(c) = self.peek() else { break };
+
+            if c == '#' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                continue;
+            }
+
+            // the right side of `=~` may hold `(`, `|` and other operator
+            // characters
+            if in_conditional(&tokens) && tokens.last() == Some(&Token::Word(String::from("=~"))) {
+                let regex = self.read_regex()?;
+                tokens.push(Token::Word(regex));
+                continue;
+            }
+
+            if self.starts_with("((") {
+                if let Some(word) = self.read_arithmetic()? {
+                    tokens.push(Token::Word(word));
+                    continue;
+                }
+            }
+
+            let operator = OPERATORS.iter().find(|op| self.starts_with(op));
+            if let Some(op) = operator.filter(|_| !self.at_process_substitution()) {
+                self.pos += op.chars().count();
+                if *op == "\n" {
+                    self.read_heredoc_bodies(&mut tokens)?;
+                }
+                let is_heredoc = *op == "<<" || *op == "<<-";
+                tokens.push(Token::Operator(op));
+                if is_heredoc {
+                    if let Some(delimiter) = self.word_after_heredoc()? {
+                        let strip_tabs = *op == "<<-";
+                        self.pending_heredocs.push((
+                            unquote(&delimiter),
+                            strip_tabs,
+                            tokens.len() + 1,
+                        ));
+                        tokens.push(Token::Word(delimiter));
+                        tokens.push(Token::HereDoc(String::new()));
+                    }
+                }
+                continue;
+            }
+
+            let word = self.read_word()?;
+            match word.parse() {
+                Ok(fd) if self.starts_with("<") || self.starts_with(">") => {
+                    tokens.push(Token::IoNumber(fd))
+                }
+                _ => tokens.push(Token::Word(word)),
+            }
+        }
+
+        if self.pending_heredocs.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(Unterminated)
+        }
+    }
+
+    // reads `(( ... ))` as a single word, since the expression may contain
+    // characters like `<` and `&`. Returns None if the parentheses close
+    // separately, as in nested subshells `((cmd) )`.
+    fn read_arithmetic(&mut self) -> Result<Option<String>, Unterminated> {
+        let mut depth = 0;
+        let mut i = self.pos + 2;
+        loop {
+            match self.chars.get(i) {
+                None => return Err(Unterminated),
+                Some('(') => depth += 1,
+                Some(')') if depth > 0 => depth -= 1,
+                Some(')') if self.chars.get(i + 1) == Some(&')') => break,
+                Some(')') => return Ok(None),
+                Some(_) => {}
+            }
+            i += 1;
+        }
+        let word = self.chars[self.pos..i + 2].iter().collect();
+        self.pos = i + 2;
+        Ok(Some(word))
+    }
+
+    // reads the delimiter word following `<<`, if there is one
+    fn word_after_heredoc(&mut self) -> Result<Option<String>, Unterminated> {
+        while self.peek().is_some_and(|c| c == ' ' || c == '\t') {
+            self.pos += 1;
+        }
+        match self.peek() {
+            None => Ok(None),
+            Some(_) if OPERATORS.iter().any(|op| self.starts_with(op)) => Ok(None),
+            Some(_) => self.read_word().map(Some),
+        }
+    }
+
+    // reads the bodies of heredocs started on the line that just ended into
+    // their tokens
+    fn read_heredoc_bodies(&mut self, tokens: &mut [Token]) -> Result<(), Unterminated> {
+        for (delimiter, strip_tabs, index) in std::mem::take(&mut self.pending_heredocs) {
+            let mut body = String::new();
+            loop {
+                if self.pos >= self.chars.len() {
+                    return Err(Unterminated);
+                }
+                let start = self.pos;
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+                let line: String = self.chars[start..self.pos].iter().collect();
+                let finished = self.peek().is_some();
+                if finished {
+                    self.pos += 1;
+                }
+                let line = if strip_tabs {
+                    line.trim_start_matches('\t')
+                } else {
+                    line.as_str()
+                };
+                if line == delimiter {
+                    break;
+                }
+                if !finished {
+                    return Err(Unterminated);
+                }
+                body.push_str(line);
+                body.push('\n');
+            }
+            tokens[index] = Token::HereDoc(body);
+        }
+        Ok(())
+    }
+
+    fn read_word(&mut self) -> Result<String, Unterminated> {
+        let mut word = String::new();
+
+        while let Some(c) = self.peek() {
+            if c == '(' && is_assignment_prefix(&word) {
+                self.read_array_literal(&mut word)?;
+                continue;
+            }
+            if self.at_process_substitution() {
+                self.read_group(&mut word)?;
+                continue;
+            }
+            if c == ' ' || c == '\t' || OPERATORS.iter().any(|op| self.starts_with(op)) {
+                break;
+            }
+            match c {
+                '\\' => {
+                    if self.starts_with("\\\n") {
+                        self.pos += 2;
+                        continue;
+                    }
+                    word.push(c);
+                    self.pos += 1;
+                    word.push(self.peek().ok_or(Unterminated)?);
+                    self.pos += 1;
+                }
+                '\'' => self.read_single_quoted(&mut word)?,
+                '"' => self.read_double_quoted(&mut word)?,
+                '`' => self.read_until_backtick(&mut word)?,
+                '$' if self.starts_with("$(") || self.starts_with("${") => {
+                    self.read_group(&mut word)?
+                }
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Ok(word)
+    }
+
+    // reads the regex after `=~` in `[[ ]]`, where parentheses and `|` are
+    // part of the word rather than operators. An unbalanced `(` ends at the
+    // line or at a `]]` outside a bracket expression, and is then reported as
+    // an invalid regex instead of waiting for more input.
+    fn read_regex(&mut self) -> Result<String, Unterminated> {
+        let mut word = String::new();
+        let mut depth = 0;
+        let mut in_brackets = false;
+
+        while let Some(c) = self.peek() {
+            match c {
+                '\n' => break,
+                ' ' | '\t' if depth == 0 => break,
+                ')' if depth == 0 && !in_brackets => break,
+                ']' if depth > 0 && !in_brackets && self.starts_with("]]") => break,
+                // a `]` right after `[` or `[^` is part of the bracket expression
+                ']' if in_brackets && !word.ends_with('[') && !word.ends_with("[^") => {
+                    in_brackets = false
+                }
+                '[' if !in_brackets => in_brackets = true,
+                '(' if !in_brackets => depth += 1,
+                ')' if !in_brackets => depth -= 1,
+                _ => {}
+            }
+            match c {
+                '\\' => {
+                    word.push(c);
+                    self.pos += 1;
+                    word.push(self.peek().ok_or(Unterminated)?);
+                    self.pos += 1;
+                }
+                '\'' => self.read_single_quoted(&mut word)?,
+                '"' => self.read_double_quoted(&mut word)?,
+                '$' if self.starts_with("$(") || self.starts_with("${") => {
+                    self.read_group(&mut word)?
+                }
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Ok(word)
+    }
+
+    // reads the `( ... )` of an array assignment such as `list=(a "b c")`
+    fn read_array_literal(&mut self, word: &mut String) -> Result<(), Unterminated> {
+        word.push('(');
+        self.pos += 1;
+        loop {
+            let c = self.peek().ok_or(Unterminated)?;
+            match c {
+                ')' => {
+                    word.push(c);
+                    self.pos += 1;
+                    return Ok(());
+                }
+                '\'' => self.read_single_quoted(word)?,
+                '"' => self.read_double_quoted(word)?,
+                '\\' => {
+                    word.push(c);
+                    self.pos += 1;
+                    word.push(self.peek().ok_or(Unterminated)?);
+                    self.pos += 1;
+                }
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn read_single_quoted(&mut self, word: &mut String) -> Result<(), Unterminated> {
+        word.push('\'');
+        self.pos += 1;
+        loop {
+            let c = self.peek().ok_or(Unterminated)?;
+            word.push(c);
+            self.pos += 1;
+            if c == '\'' {
+                return Ok(());
+            }
+        }
+    }
+
+    fn read_double_quoted(&mut self, word: &mut String) -> Result<(), Unterminated> {
+        word.push('"');
+        self.pos += 1;
+        loop {
+            let c = self.peek().ok_or(Unterminated)?;
+            match c {
+                '"' => {
+                    word.push(c);
+                    self.pos += 1;
+                    return Ok(());
+                }
+                '\\' => {
+                    word.push(c);
+                    self.pos += 1;
+                    word.push(self.peek().ok_or(Unterminated)?);
+                    self.pos += 1;
+                }
+                '`' => self.read_until_backtick(word)?,
+                '$' if self.starts_with("$(") || self.starts_with("${") => self.read_group(word)?,
+                _ => {
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn read_until_backtick(&mut self, word: &mut String) -> Result<(), Unterminated> {
+        word.push('`');
+        self.pos += 1;
+        loop {
+            let c = self.peek().ok_or(Unterminated)?;
+            word.push(c);
+            self.pos += 1;
+            match c {
+                '\\' => {
+                    word.push(self.peek().ok_or(Unterminated)?);
+                    self.pos += 1;
+                }
+                '`' => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+
+    // reads `$( ... )`, `$(( ... ))`, `${ ... }` and the process substitutions
+    // `<( ... )` and `>( ... )`, honoring nesting and quotes
+    fn read_group(&mut self, word: &mut String) -> Result<(), Unterminated> {
+        let (open, close) = if self.starts_with("${") {
+            ('{', '}')
+        } else {
+            ('(', ')')
+        };
+        word.extend(self.peek());
+        word.push(open);
+        self.pos += 2;
+        let mut depth = 1;
+
+        while depth > 0 {
+            let c = self.peek().ok_or(Unterminated)?;
+            match c {
+                '\'' => self.read_single_quoted(word)?,
+                '"' => self.read_double_quoted(word)?,
+                '`' => self.read_until_backtick(word)?,
+                '\\' => {
+                    word.push(c);
+                    self.pos += 1;
+                    word.push(self.peek().ok_or(Unterminated)?);
+                    self.pos += 1;
+                }
+                _ => {
+                    if c == open {
+                        depth += 1;
+                    } else if c == close {
+                        depth -= 1;
+                    }
+                    word.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits input into words and operators, failing if the input stops inside
+/// quotes, a `$( )` group, a trailing `\` or a heredoc body.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, Unterminated> {
+    Lexer::new(input).tokens()
+}
+
+/// Removes quoting from a word, e.g. `'EOF'` or `"EOF"` becomes `EOF`.
+pub fn unquote(word: &str) -> String {
+    let mut result = String::new();
+    let mut chars = word.chars();
+    let mut quote: Option<char> = None;
+
+    while let Some(c) = chars.next() {
+        match (c, quote) {
+            ('\\', None) | ('\\', Some('"')) => {
+                if let Some(next) = chars.next() {
+                    result.push(next);
+                }
+            }
+            ('\'', None) | ('"', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            (c, _) => result.push(c),
+        }
+    }
+
+    result
+}
+
+/// Returns true for words like `NAME=` or `NAME+=` that start an assignment.
+pub fn is_assignment_prefix(word: &str) -> bool {
+    let name = word
+        .strip_suffix("+=")
+        .or_else(|| word.strip_suffix('='))
+        .unwrap_or_default();
+    is_name(name)
+}
+
+/// Returns true if `name` is a valid variable or function name.
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Splits `name[subscript]` into the name and the raw subscript.
+pub fn split_subscript(word: &str) -> Option<(&str, &str)> {
+    let (name, subscript) = word.strip_suffix(']')?.split_once('[')?;
+    is_name(name).then_some((name, subscript))
+}
+
+/// Splits an assignment word into its name and raw value. The name may have
+/// a subscript, as in `arr[1]=x` or `map[$key]+=x`.
+pub fn split_assignment(word: &str) -> Option<(&str, &str)> {
+    // the subscript may contain `=` itself, so look for the `=` after it
+    let after_subscript = match word.find('[') {
+        Some(open) if is_name(&word[..open]) => open + word[open..].find(']')?,
+        _ => 0,
+    };
+    let equals = after_subscript + word[after_subscript..].find('=')?;
+    let (name, value) = (&word[..equals], &word[equals + 1..]);
+    let target = name.strip_suffix('+').unwrap_or(name);
+    (is_name(target) || split_subscript(target).is_some()).then_some((name, value))
+}
+
+/// A redirection such as `2>> errors.log`. The target is still unexpanded.
+/// Here-documents keep their delimiter as the target and carry their body.
+#[derive(Debug, Clone)]
+pub struct Redirect {
+    pub fd: Option<i32>,
+    pub op: &'static str,
+    pub target: String,
+    pub body: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimpleCommand {
+    pub assignments: Vec<String>,
+    pub words: Vec<String>,
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CaseArm {
+    pub patterns: Vec<String>,
+    pub body: Command,
+}
+
+/// The command tree produced by the parser.
+#[derive(Debug, Clone)]
+pub enum Command {
+    Simple(SimpleCommand),
+    Pipeline(Vec<Command>),
+    Not(Box<Command>),
+    And(Box<Command>, Box<Command>),
+    Or(Box<Command>, Box<Command>),
+    Sequence(Vec<Command>),
+    Brace(Box<Command>),
+    // `( ... )`, run on a copy of the shell
+    Subshell(Box<Command>),
+    // a compound command followed by redirections, as in `{ a; b; } > log`
+    Redirected {
+        command: Box<Command>,
+        redirects: Vec<Redirect>,
+    },
+    Time(Box<Command>),
+    If {
+        branches: Vec<(Command, Command)>,
+        otherwise: Option<Box<Command>>,
+    },
+    Loop {
+        condition: Box<Command>,
+        body: Box<Command>,
+        until: bool,
+    },
+    For {
+        variable: String,
+        words: Option<Vec<String>>,
+        body: Box<Command>,
+    },
+    // `for ((init; condition; step))`, with empty expressions left empty
+    ArithFor {
+        init: String,
+        condition: String,
+        step: String,
+        body: Box<Command>,
+    },
+    // `(( expression ))`, true when the expression is not zero
+    Arith(String),
+    // the words between `[[` and `]]`, unexpanded, with operators such as
+    // `&&` and `(` turned into plain words
+    Conditional(Vec<String>),
+    Case {
+        word: String,
+        arms: Vec<CaseArm>,
+    },
+    Function {
+        name: String,
+        body: Rc<Command>,
+    },
+}
+
+/// Why a command line could not be parsed.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    // the input ended early and more lines could complete it
+    Incomplete,
+    Invalid(String),
+}
+
+// whether the tokens so far leave a `[[` open
+fn in_conditional(tokens: &[Token]) -> bool {
+    let position = |word: &str| {
+        tokens
+            .iter()
+            .rposition(|token| matches!(token, Token::Word(w) if w == word))
+    };
+    match (position("[["), position("]]")) {
+        (Some(open), Some(close)) => open > close,
+        (open, _) => open.is_some(),
+    }
+}
+
+const RESERVED: [&str; 14] = [
+    "if", "then", "elif", "else", "fi", "while", "until", "for", "do", "done", "case", "esac", "{",
+    "}",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    fn is_operator(&self, op: &str) -> bool {
+        matches!(self.peek(), Some(Token::Operator(o)) if *o == op)
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.is_operator("\n") {
+            self.pos += 1;
+        }
+    }
+
+    // builds the error for the token at the current position
+    fn unexpected(&self) -> ParseError {
+        match self.peek() {
+            None => ParseError::Incomplete,
+            Some(Token::Word(word)) => unexpected(word),
+            Some(Token::Operator(op)) => unexpected(op),
+            Some(Token::IoNumber(fd)) => unexpected(&fd.to_string()),
+            Some(Token::HereDoc(_)) => unexpected("\n"),
+        }
+    }
+
+    fn expect_word(&mut self, word: &str) -> Result<(), ParseError> {
+        if self.peek_word() == Some(word) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn expect_operator(&mut self, op: &str) -> Result<(), ParseError> {
+        if self.is_operator(op) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    // parses commands separated by `;`, `&` or newlines until one of the
+    // terminating reserved words (or the end of input) is reached
+    fn parse_list(&mut self, terminators: &[&str]) -> Result<Command, ParseError> {
+        let mut commands = Vec::new();
+
+        loop {
+            self.skip_newlines();
+            match self.peek() {
+                None => break,
+                Some(Token::Word(word)) if terminators.contains(&word.as_str()) => break,
+                Some(Token::Operator(op)) if terminators.contains(op) => break,
+                _ => {}
+            }
+
+            commands.push(self.parse_and_or()?);
+
+            // background jobs aren't supported, so `&` runs in the foreground
+            if self.is_operator(";") || self.is_operator("&") || self.is_operator("\n") {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        if commands.is_empty() && !terminators.is_empty() {
+            return Err(self.unexpected());
+        }
+
+        Ok(if commands.len() == 1 {
+            commands.remove(0)
+        } else {
+            Command::Sequence(commands)
+        })
+    }
+
+    fn parse_and_or(&mut self) -> Result<Command, ParseError> {
+        let mut command = self.parse_pipeline()?;
+
+        loop {
+            let and = self.is_operator("&&");
+            if !and && !self.is_operator("||") {
+                return Ok(command);
+            }
+            self.pos += 1;
+            self.skip_newlines();
+            let next = self.parse_pipeline()?;
+            command = if and {
+                Command::And(Box::new(command), Box::new(next))
+            } else {
+                Command::Or(Box::new(command), Box::new(next))
+            };
+        }
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Command, ParseError> {
+        if self.peek_word() == Some("time") {
+            self.pos += 1;
+            // a bare `time` reports the (zero) times of an empty command
+            let bare = match self.peek() {
+                None => true,
+                Some(Token::Operator(op)) => !REDIRECTIONS.contains(op) && *op != "(",
+                _ => false,
+            };
+            let pipeline = if bare {
+                Command::Sequence(Vec::new())
+            } else {
+                self.parse_pipeline()?
+            };
+            return Ok(Command::Time(Box::new(pipeline)));
+        }
+
+        let negate = self.peek_word() == Some("!");
+        if negate {
+            self.pos += 1;
+        }
+
+        let mut commands = vec![self.parse_command()?];
+        while self.is_operator("|") {
+            self.pos += 1;
+            self.skip_newlines();
+            commands.push(self.parse_command()?);
+        }
+
+        let pipeline = if commands.len() == 1 {
+            commands.remove(0)
+        } else {
+            Command::Pipeline(commands)
+        };
+        Ok(if negate {
+            Command::Not(Box::new(pipeline))
+        } else {
+            pipeline
+        })
+    }
+
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        let command = match self.peek() {
+            None => Err(ParseError::Incomplete),
+            Some(Token::Operator("(")) => {
+                self.pos += 1;
+                let body = self.parse_list(&[")"])?;
+                self.expect_operator(")")?;
+                Ok(Command::Subshell(Box::new(body)))
+            }
+            Some(Token::Operator(op)) if !REDIRECTIONS.contains(op) => Err(self.unexpected()),
+            Some(Token::Word(word)) => match word.as_str() {
+                "{" => {
+                    self.pos += 1;
+                    let body = self.parse_list(&["}"])?;
+                    self.expect_word("}")?;
+                    Ok(Command::Brace(Box::new(body)))
+                }
+                "if" => self.parse_if(),
+                "while" | "until" => self.parse_loop(),
+                "for" => self.parse_for(),
+                "case" => self.parse_case(),
+                "[[" => self.parse_conditional(),
+                "function" => {
+                    self.pos += 1;
+                    let name = self
+                        .peek_word()
+                        .ok_or_else(|| self.unexpected())?
+                        .to_string();
+                    self.pos += 1;
+                    if self.is_operator("(") {
+                        self.pos += 1;
+                        self.expect_operator(")")?;
+                    }
+                    self.parse_function_body(name)
+                }
+                word if RESERVED.contains(&word) => Err(self.unexpected()),
+                word if arithmetic(word).is_some() => {
+                    let expression = arithmetic(word).unwrap_or_default().to_string();
+                    self.pos += 1;
+                    Ok(Command::Arith(expression))
+                }
+                word if is_name(word)
+                    && self.tokens.get(self.pos + 1) == Some(&Token::Operator("(")) =>
+                {
+                    let name = word.to_string();
+                    self.pos += 2;
+                    self.expect_operator(")")?;
+                    self.parse_function_body(name)
+                }
+                _ => self.parse_simple(),
+            },
+            _ => self.parse_simple(),
+        }?;
+
+        // redirections after a compound command apply to everything it runs
+        if matches!(command, Command::Simple(_) | Command::Function { .. }) {
+            return Ok(command);
+        }
+        let mut redirects = Vec::new();
+        while let Some(redirect) = self.parse_redirect()? {
+            redirects.push(redirect);
+        }
+        Ok(if redirects.is_empty() {
+            command
+        } else {
+            Command::Redirected {
+                command: Box::new(command),
+                redirects,
+            }
+        })
+    }
+
+    fn parse_conditional(&mut self) -> Result<Command, ParseError> {
+        self.pos += 1;
+        let mut words = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(ParseError::Incomplete),
+                Some(Token::Word(word)) if word == "]]" => break,
+                Some(Token::Word(word)) => words.push(word.clone()),
+                Some(Token::Operator("\n")) => {}
+                Some(Token::Operator(op)) if ["&&", "||", "(", ")", "<", ">"].contains(op) => {
+                    words.push(op.to_string())
+                }
+                Some(_) => return Err(self.unexpected()),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Ok(Command::Conditional(words))
+    }
+
+    fn parse_function_body(&mut self, name: String) -> Result<Command, ParseError> {
+        self.skip_newlines();
+        let body = self.parse_command()?;
+        if matches!(body, Command::Simple(_)) {
+            return Err(ParseError::Invalid(format!(
+                "function body for '{}' must be a compound command such as {{ ...; }}",
+                name
+            )));
+        }
+        Ok(Command::Function {
+            name,
+            body: Rc::new(body),
+        })
+    }
+
+    fn parse_simple(&mut self) -> Result<Command, ParseError> {
+        let mut command = SimpleCommand {
+            assignments: Vec::new(),
+            words: Vec::new(),
+            redirects: Vec::new(),
+        };
+
+        loop {
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    if command.words.is_empty() && split_assignment(word).is_some() {
+                        command.assignments.push(word.clone());
+                    } else {
+                        command.words.push(word.clone());
+                    }
+                    self.pos += 1;
+                }
+                Some(Token::IoNumber(_)) | Some(Token::Operator(_)) => {
+                    match self.parse_redirect()? {
+                        Some(redirect) => command.redirects.push(redirect),
+                        None => break,
+                    }
+                }
+                Some(Token::HereDoc(_)) | None => break,
+            }
+        }
+
+        Ok(Command::Simple(command))
+    }
+
+    // parses a redirection such as `2> file` or `<<EOF`, or returns None if
+    // the next token doesn't start one
+    fn parse_redirect(&mut self) -> Result<Option<Redirect>, ParseError> {
+        let fd = match self.peek() {
+            Some(&Token::IoNumber(fd)) => {
+                self.pos += 1;
+                Some(fd)
+            }
+            _ => None,
+        };
+        let op = match self.peek() {
+            Some(Token::Operator(op)) if REDIRECTIONS.contains(op) => *op,
+            _ if fd.is_some() => return Err(self.unexpected()),
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        let target = match self.peek() {
+            Some(Token::Word(target)) => target.clone(),
+            None => return Err(unexpected("\n")),
+            Some(_) => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        let body = match self.peek() {
+            Some(Token::HereDoc(body)) => {
+                let body = body.clone();
+                self.pos += 1;
+                Some(body)
+            }
+            _ => None,
+        };
+        Ok(Some(Redirect {
+            fd,
+            op,
+            target,
+            body,
+        }))
+    }
+
+    fn parse_if(&mut self) -> Result<Command, ParseError> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+
+        self.expect_word("if")?;
+        loop {
+            let condition = self.parse_list(&["then"])?;
+            self.expect_word("then")?;
+            let body = self.parse_list(&["elif", "else", "fi"])?;
+            branches.push((condition, body));
+
+            match self.peek_word() {
+                Some("elif") => self.pos += 1,
+                Some("else") => {
+                    self.pos += 1;
+                    otherwise = Some(Box::new(self.parse_list(&["fi"])?));
+                    self.expect_word("fi")?;
+                    break;
+                }
+                _ => {
+                    self.expect_word("fi")?;
+                    break;
+                }
+            }
+        }
+
+        Ok(Command::If {
+            branches,
+            otherwise,
+        })
+    }
+
+    fn parse_loop(&mut self) -> Result<Command, ParseError> {
+        let until = self.peek_word() == Some("until");
+        self.pos += 1;
+        let condition = self.parse_list(&["do"])?;
+        let body = self.parse_do_group()?;
+        Ok(Command::Loop {
+            condition: Box::new(condition),
+            body: Box::new(body),
+            until,
+        })
+    }
+
+    fn parse_do_group(&mut self) -> Result<Command, ParseError> {
+        self.expect_word("do")?;
+        let body = self.parse_list(&["done"])?;
+        self.expect_word("done")?;
+        Ok(body)
+    }
+
+    fn parse_for(&mut self) -> Result<Command, ParseError> {
+        self.expect_word("for")?;
+        if let Some(expressions) = self.peek_word().and_then(arithmetic) {
+            let parts: Vec<&str> = expressions.split(';').collect();
+            let [init, condition, step] = parts[..] else {
+                return Err(ParseError::Invalid(String::from(
+                    "syntax error: `for ((init; condition; step))' needs three expressions",
+                )));
+            };
+            let (init, condition, step) =
+                (init.to_string(), condition.to_string(), step.to_string());
+            self.pos += 1;
+            if self.is_operator(";") {
+                self.pos += 1;
+            }
+            self.skip_newlines();
+            let body = self.parse_do_group()?;
+            return Ok(Command::ArithFor {
+                init,
+                condition,
+                step,
+                body: Box::new(body),
+            });
+        }
+
+        let variable = match self.peek_word() {
+            Some(name) if is_name(name) => name.to_string(),
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+
+        self.skip_newlines();
+        let mut words = None;
+        if self.peek_word() == Some("in") {
+            self.pos += 1;
+            let mut list = Vec::new();
+            while let Some(word) = self.peek_word() {
+                list.push(word.to_string());
+                self.pos += 1;
+            }
+            words = Some(list);
+        }
+        if self.is_operator(";") {
+            self.pos += 1;
+        } else if words.is_some() && !self.is_operator("\n") {
+            return Err(self.unexpected());
+        }
+        self.skip_newlines();
+
+        let body = self.parse_do_group()?;
+        Ok(Command::For {
+            variable,
+            words,
+            body: Box::new(body),
+        })
+    }
+
+    fn parse_case(&mut self) -> Result<Command, ParseError> {
+        self.expect_word("case")?;
+        let word = self
+            .peek_word()
+            .ok_or_else(|| self.unexpected())?
+            .to_string();
+        self.pos += 1;
+        self.skip_newlines();
+        self.expect_word("in")?;
+
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek_word() == Some("esac") {
+                self.pos += 1;
+                break;
+            }
+
+            if self.is_operator("(") {
+                self.pos += 1;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                let pattern = self.peek_word().ok_or_else(|| self.unexpected())?;
+                patterns.push(pattern.to_string());
+                self.pos += 1;
+                if !self.is_operator("|") {
+                    break;
+                }
+                self.pos += 1;
+            }
+            self.expect_operator(")")?;
+
+            self.skip_newlines();
+            let body = if self.is_operator(";;") || self.peek_word() == Some("esac") {
+                Command::Sequence(Vec::new())
+            } else {
+                self.parse_list(&[";;", "esac"])?
+            };
+            arms.push(CaseArm { patterns, body });
+
+            if self.is_operator(";;") {
+                self.pos += 1;
+            } else {
+                self.skip_newlines();
+                self.expect_word("esac")?;
+                break;
+            }
+        }
+
+        Ok(Command::Case { word, arms })
+    }
+}
+
+// the expression of a `(( ... ))` word
+fn arithmetic(word: &str) -> Option<&str> {
+    word.strip_prefix("((")?.strip_suffix("))")
+}
+
+fn unexpected(token: &str) -> ParseError {
+    let token = if token == "\n" { "newline" } else { token };
+    ParseError::Invalid(format!("syntax error near unexpected token `{}'", token))
+}
+
+/// Parses a complete command line (or script) into a command tree.
+pub fn parse(input: &str) -> Result<Command, ParseError> {
+    let tokens = tokenize(input).map_err(|_| ParseError::Incomplete)?;
+    let mut parser = Parser { tokens, pos: 0 };
+
+    let command = parser.parse_list(&[])?;
+    if parser.peek().is_some() {
+        return Err(parser.unexpected());
+    }
+    Ok(command)
+}
+
+/// Checks a (possibly multi-line) command line. Incomplete input such as an
+/// unterminated quote, a trailing `|`, `&&` or `\`, an open `if`/`for`/
+/// `while`/`case`/`{` block or a heredoc without its delimiter should be
+/// continued on the next line rather than executed.
+pub fn check_input(input: &str) -> InputStatus {
+    match parse(input) {
+        Ok(_) => InputStatus::Complete,
+        Err(ParseError::Incomplete) => InputStatus::Incomplete,
+        Err(ParseError::Invalid(message)) => InputStatus::Invalid(message),
+    }
+}
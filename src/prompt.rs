@@ -0,0 +1,216 @@
+// The prompt module renders PS1, PS2 and RPROMPT. It understands the common
+// bash escapes plus a few Batcave segments written as `\{name}`:
+//
+//   \u user        \h host (short)   \H host (full)   \w cwd (~ for $HOME)
+//   \W basename    \t time 24h       \d date          \$ '#' for root, else '$'
+//   \? exit status \n newline        \\ backslash
+//   \e, \033       escape character for color sequences
+//   \[ \]          markers around non-printing text (ignored)
+//
+//   \{git}         branch name with '*' when the work tree is dirty
+//   \{status}      last exit status, shown only when it is non-zero
+//   \{duration}    how long the last command took, if at least $REPORTTIME secs
+//   \{red} \{green} \{yellow} \{blue} \{purple} \{cyan} \{white} \{grey}
+//   \{bold} \{reset}
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use chrono::Local;
+use colored::*;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{shell::Shell, utils::strip_ansi};
+
+pub const DEFAULT_PS1: &str = "🦇 \\w> ";
+pub const DEFAULT_PS2: &str = "> ";
+
+/// Renders a prompt template. Prompts without any color escapes are drawn in
+/// the Batcave purple so a plain PS1 keeps the familiar look.
+pub fn render_prompt(template: &str, shell: &Shell) -> String {
+    let mut prompt = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('u') => prompt.push_str(&user_name(shell)),
+            Some('h') => prompt.push_str(host_name().split('.').next().unwrap_or_default()),
+            Some('H') => prompt.push_str(&host_name()),
+            Some('w') => prompt.push_str(&current_dir(shell, false)),
+            Some('W') => prompt.push_str(&current_dir(shell, true)),
+            Some('t') => prompt.push_str(&Local::now().format("%H:%M:%S").to_string()),
+            Some('d') => prompt.push_str(&Local::now().format("%a %b %d").to_string()),
+            Some('$') => prompt.push(if is_root() { '#' } else { '$' }),
+            Some('?') => prompt.push_str(&shell.last_status.to_string()),
+            Some('n') => prompt.push('\n'),
+            Some('e') => prompt.push('\x1b'),
+            Some('0') if chars.peek() == Some(&'3') => {
+                chars.next();
+                if chars.next_if_eq(&'3').is_some() {
+                    prompt.push('\x1b');
+                } else {
+                    prompt.push_str("\\03");
+                }
+            }
+            Some('[') | Some(']') => {}
+            Some('{') => {
+                let name: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                prompt.push_str(&segment(&name, shell));
+            }
+            Some('\\') => prompt.push('\\'),
+            Some(other) => {
+                prompt.push('\\');
+                prompt.push(other);
+            }
+            None => prompt.push('\\'),
+        }
+    }
+
+    if prompt.contains('\x1b') {
+        // make sure colors from the prompt don't leak into the input
+        prompt.push_str("\x1b[0m");
+        prompt
+    } else {
+        prompt.bright_purple().to_string()
+    }
+}
+
+/// Returns the printable width of the last line of a rendered prompt,
+/// ignoring color escape sequences.
+pub fn display_width(text: &str) -> usize {
+    let last_line = text.rsplit('\n').next().unwrap_or_default();
+    strip_ansi(last_line).width()
+}
+
+fn segment(name: &str, shell: &Shell) -> String {
+    match name {
+        "git" => git_segment().unwrap_or_default(),
+        "status" if shell.last_status != 0 => format!("✘ {}", shell.last_status).red().to_string(),
+        "status" => String::new(),
+        "duration" => shell
+            .last_duration
+            .filter(|&duration| duration >= report_time(shell))
+            .map(|duration| format_duration(duration).yellow().to_string())
+            .unwrap_or_default(),
+        "red" => String::from("\x1b[31m"),
+        "green" => String::from("\x1b[32m"),
+        "yellow" => String::from("\x1b[33m"),
+        "blue" => String::from("\x1b[34m"),
+        "purple" | "magenta" => String::from("\x1b[95m"),
+        "cyan" => String::from("\x1b[36m"),
+        "white" => String::from("\x1b[97m"),
+        "grey" | "gray" => String::from("\x1b[90m"),
+        "bold" => String::from("\x1b[1m"),
+        "reset" => String::from("\x1b[0m"),
+        _ => format!("\\{{{}}}", name),
+    }
+}
+
+// the threshold in seconds from $REPORTTIME below which \{duration} stays
+// hidden, so quick commands don't clutter the prompt
+fn report_time(shell: &Shell) -> Duration {
+    shell
+        .get_env("REPORTTIME")
+        .and_then(|secs| secs.trim().parse::<f64>().ok())
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .unwrap_or(Duration::ZERO)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs_f64();
+    if secs < 1.0 {
+        format!("{}ms", duration.as_millis())
+    } else if secs < 60.0 {
+        format!("{:.2}s", secs)
+    } else {
+        format!("{}m{}s", duration.as_secs() / 60, duration.as_secs() % 60)
+    }
+}
+
+fn user_name(shell: &Shell) -> String {
+    shell
+        .get_env("USER")
+        .or_else(|| shell.get_env("LOGNAME"))
+        .cloned()
+        .unwrap_or_else(|| String::from("batman"))
+}
+
+fn host_name() -> String {
+    sysinfo::System::host_name().unwrap_or_else(|| String::from("batcave"))
+}
+
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+fn current_dir(shell: &Shell, basename_only: bool) -> String {
+    let cwd = shell.current_dir();
+    let home = shell.get_env("HOME").map(PathBuf::from);
+
+    if home.as_deref() == Some(cwd.as_path()) {
+        return String::from("~");
+    }
+    if basename_only {
+        return cwd
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| cwd.display().to_string());
+    }
+    match home.and_then(|home| cwd.strip_prefix(home).ok().map(Path::to_path_buf)) {
+        Some(relative) => format!("~/{}", relative.display()),
+        None => cwd.display().to_string(),
+    }
+}
+
+// finds the enclosing git directory by walking up from the current directory
+fn find_git_dir() -> Option<PathBuf> {
+    let mut dir = env::current_dir().ok()?;
+    loop {
+        let candidate = dir.join(".git");
+        if candidate.is_dir() {
+            return Some(candidate);
+        }
+        if candidate.is_file() {
+            // worktrees and submodules point at the real git dir
+            let content = fs::read_to_string(&candidate).ok()?;
+            let target = content.trim().strip_prefix("gitdir:")?.trim();
+            return Some(dir.join(target));
+        }
+        if !dir.pop() {
+            return None;
+        }
+    }
+}
+
+fn git_segment() -> Option<String> {
+    let git_dir = find_git_dir()?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let head = head.trim();
+    let branch = match head.strip_prefix("ref: refs/heads/") {
+        Some(branch) => branch.to_string(),
+        None => head.chars().take(7).collect(),
+    };
+
+    let dirty = Command::new("git")
+        .args(["status", "--porcelain", "--untracked-files=no"])
+        .stderr(Stdio::null())
+        .output()
+        .map(|output| !output.stdout.is_empty())
+        .unwrap_or(false);
+
+    let segment = if dirty {
+        format!("{}*", branch)
+    } else {
+        branch
+    };
+    Some(segment.bright_cyan().to_string())
+}
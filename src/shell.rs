@@ -1,29 +1,114 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     env, fs,
-    path::Path,
-    time::SystemTime,
+    os::fd::RawFd,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, SystemTime},
 };
 
 use colored::*;
+use log::error;
 
 use rustyline::{
     completion::{Completer, Pair},
     error::ReadlineError,
     highlight::Highlighter,
-    hint::Hinter,
-    validate::Validator,
-    Context, Helper,
+    hint::{Hint, Hinter},
+    validate::{ValidationContext, ValidationResult, Validator},
+    Cmd, ConditionalEventHandler, Context, Event, EventContext, Helper, RepeatCount,
 };
 
-use crate::utils::autocomplete;
+use crate::{
+    arith::evaluate,
+    config::{save, Kind},
+    exec::{run_script, Flow, Input, Io, Output, Substitution},
+    parser::{check_input, Command, InputStatus},
+    prompt::display_width,
+    utils::autocomplete,
+};
+
+/// A single history entry, remembering the directory the command was run in
+/// so suggestions can prefer commands used in the current directory.
+#[derive(Clone)]
+pub struct HistoryEntry {
+    pub command: String,
+    pub cwd: String,
+}
+
+/// The attributes `declare` and `readonly` can give a variable.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub struct Attributes {
+    pub readonly: bool,
+    pub integer: bool,
+    pub lowercase: bool,
+    pub uppercase: bool,
+}
+
+/// The value of a shell variable: a plain string, an indexed array or an
+/// associative array. Indexed arrays may have gaps, like in bash.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Scalar(String),
+    Indexed(BTreeMap<usize, String>),
+    Assoc(BTreeMap<String, String>),
+}
+
+impl Value {
+    /// The elements of the value in order; a scalar is a one element list.
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(value) => vec![value.clone()],
+            Value::Indexed(items) => items.values().cloned().collect(),
+            Value::Assoc(items) => items.values().cloned().collect(),
+        }
+    }
+
+    /// The indices or keys of the elements, for `${!name[@]}`.
+    pub fn keys(&self) -> Vec<String> {
+        match self {
+            Value::Scalar(_) => vec![String::from("0")],
+            Value::Indexed(items) => items.keys().map(usize::to_string).collect(),
+            Value::Assoc(items) => items.keys().cloned().collect(),
+        }
+    }
+}
+
+// where `name[subscript]` refers to: a key of an associative array, or an
+// index of an indexed array or scalar
+enum Slot {
+    Key(String),
+    Index(usize),
+}
 
 /// This Shell struct is the core data structure that maintains the state of the shell session.
 #[derive(Clone)]
 pub struct Shell {
-    pub env_vars: HashMap<String, String>,
+    pub vars: HashMap<String, Value>,
+    // the variables passed on to child processes
+    pub exported: HashSet<String>,
+    pub attributes: HashMap<String, Attributes>,
     pub aliases: HashMap<String, String>,
-    pub history: VecDeque<String>,
+    pub history: VecDeque<HistoryEntry>,
+    pub last_status: i32,
+    pub last_duration: Option<Duration>,
+    pub functions: HashMap<String, Rc<Command>>,
+    pub positional: Vec<String>,
+    pub flow: Flow,
+    pub hooks_running: Vec<String>,
+    // an error raised while expanding words, such as a division by zero in
+    // `$(( ))`. The command being expanded is not run.
+    pub expansion_error: Option<String>,
+    // the `<(...)` and `>(...)` commands of the command being run
+    pub substitutions: Vec<Substitution>,
+    // the descriptors of the process substitutions in the arguments of the
+    // commands being run, which the programs they start inherit
+    pub substitution_fds: Vec<RawFd>,
+    // the aliases whose text is being run, which are not expanded again
+    pub expanding_aliases: Vec<String>,
+    // set while a command typed at the prompt runs, outside of functions,
+    // hooks and subshells. Only those `cd`s count as visits for `z`.
+    pub interactive: bool,
     command_start_time: Option<SystemTime>,
     dir_stack: Vec<String>,
 }
@@ -32,16 +117,33 @@ impl Shell {
     // creates a new shell instance with the current environment variables
     pub fn new() -> Self {
         let mut shell = Shell {
-            env_vars: HashMap::new(),
+            vars: HashMap::new(),
+            exported: HashSet::new(),
+            attributes: HashMap::new(),
             aliases: HashMap::new(),
             history: VecDeque::with_capacity(1000),
+            last_status: 0,
+            last_duration: None,
+            functions: HashMap::new(),
+            positional: Vec::new(),
+            flow: Flow::Normal,
+            hooks_running: Vec::new(),
+            expansion_error: None,
+            substitutions: Vec::new(),
+            substitution_fds: Vec::new(),
+            expanding_aliases: Vec::new(),
+            interactive: false,
             command_start_time: None,
             dir_stack: Vec::new(),
         };
 
         for (key, value) in env::vars() {
-            shell.env_vars.insert(key, value);
+            shell.exported.insert(key.clone());
+            shell.vars.insert(key, Value::Scalar(value));
         }
+        let cwd = shell.current_dir();
+        shell.set_pwd(&cwd, &cwd);
+        shell.vars.remove("OLDPWD");
 
         shell.ensure_config_exists();
 
@@ -54,9 +156,16 @@ impl Shell {
         self.command_start_time = Some(SystemTime::now());
     }
 
-    pub fn end_command_timer(&mut self) -> Option<f64> {
-        self.command_start_time
-            .and_then(|start| start.elapsed().ok().map(|duration| duration.as_secs_f64()))
+    // records how long the last command took, in `last_duration` for the
+    // prompt and in milliseconds as $CMD_DURATION
+    pub fn end_command_timer(&mut self) {
+        self.last_duration = self
+            .command_start_time
+            .take()
+            .and_then(|start| start.elapsed().ok());
+        if let Some(duration) = self.last_duration {
+            self.set_env("CMD_DURATION".to_string(), duration.as_millis().to_string());
+        }
     }
 
     pub fn get_show_system_info(&self) -> bool {
@@ -65,16 +174,24 @@ impl Shell {
             .unwrap_or(true)
     }
 
-    // Pushes current directory to stack
-    // Used for directory navigation
-    pub fn push_dir(&mut self, dir: String) {
-        self.dir_stack.push(dir)
+    /// The saved directories of `pushd`, most recent first. The current
+    /// directory is not part of it.
+    pub fn dir_stack(&self) -> &[String] {
+        &self.dir_stack
     }
 
-    // Retrieves previous directory
-    // Used for directory navigation
-    pub fn pop_dir(&mut self) -> Option<String> {
-        self.dir_stack.pop()
+    pub fn set_dir_stack(&mut self, stack: Vec<String>) {
+        self.dir_stack = stack;
+        self.sync_dirstack();
+    }
+
+    // mirrors the stack into $DIRSTACK, which like in bash starts with the
+    // current directory
+    fn sync_dirstack(&mut self) {
+        let mut dirs = vec![self.current_dir().to_string_lossy().into_owned()];
+        dirs.extend(self.dir_stack.iter().cloned());
+        let dirs = Value::Indexed(dirs.into_iter().enumerate().collect());
+        self.vars.insert(String::from("DIRSTACK"), dirs);
     }
 
     // Add command suggestions
@@ -95,59 +212,26 @@ impl Shell {
 
     pub fn set_show_system_info(&mut self, show: bool) {
         self.set_env("SHOW_SYSTEM_INFO".to_string(), show.to_string());
-        if let Ok(home) = env::var("HOME") {
-            let config_path = format!("{}/.batcaverc", home);
-            if let Ok(content) = fs::read_to_string(&config_path) {
-                let mut new_lines = Vec::new();
-                let mut found = false;
-
-                for line in content.lines() {
-                    if line.trim().starts_with("export SHOW_SYSTEM_INFO=") {
-                        new_lines.push(format!("export SHOW_SYSTEM_INFO=\"{}\"", show));
-                        found = true;
-                    } else {
-                        new_lines.push(line.to_string());
-                    }
-                }
-
-                if !found {
-                    new_lines.push(format!("export SHOW_SYSTEM_INFO=\"{}\"", show));
-                }
-
-                let new_content = new_lines.join("\n");
-                let _ = fs::write(&config_path, new_content);
-            }
+        if let Err(e) = save(Kind::Export, "SHOW_SYSTEM_INFO", Some(&show.to_string())) {
+            error!("Failed to save SHOW_SYSTEM_INFO: {}", e);
         }
     }
 
-    fn load_config(&mut self) {
+    /// Runs ~/.batcaverc as a script, so it can define functions and hooks
+    /// as well as aliases and variables. Normal output is discarded.
+    pub fn load_config(&mut self) {
         if let Ok(home) = env::var("HOME") {
             let config_path = format!("{}/.batcaverc", home);
             if let Ok(content) = fs::read_to_string(&config_path) {
-                for line in content.lines() {
-                    let line = line.trim();
-                    if line.starts_with('#') || line.is_empty() {
-                        continue;
-                    }
-
-                    if line.starts_with("alias ") {
-                        if let Some(alias_def) = line.strip_prefix("alias ") {
-                            if let Some((name, command)) = alias_def.split_once('=') {
-                                let name = name.trim().to_string();
-                                let command = command.trim().trim_matches('"').to_string();
-                                self.add_alias(name, command);
-                            }
-                        }
-                    } else if line.starts_with("export ") {
-                        if let Some(export_def) = line.strip_prefix("export ") {
-                            if let Some((name, value)) = export_def.split_once('=') {
-                                let name = name.trim().to_string();
-                                let value = value.trim().trim_matches('"').to_string();
-                                self.set_env(name, value);
-                            }
-                        }
-                    }
-                }
+                let io = Io {
+                    stdin: Input::Inherit,
+                    stdout: Output::Null,
+                    stderr: Output::Terminal,
+                };
+                run_script(&content, self, &io);
+                // `exit` ends the rc file, not the shell
+                self.flow = Flow::Normal;
+                self.last_status = 0;
             }
         }
     }
@@ -170,13 +254,19 @@ export PATH="$HOME/.cargo/bin:$PATH"
 export EDITOR="vim"
 export TERM="xterm-256color"
 
-# Custom prompt settings
+# Custom prompt settings (see 'help prompt')
 export PS1="🦇 \w> "
+# export RPROMPT="\{status} \{git} \{duration}"
+# export REPORTTIME=5   # only show \{duration} for commands slower than 5s
+
+# Hooks (see 'help hooks'), for example:
+# precmd() { echo "ready"; }
+# chpwd_commands=("ls")
 
 # Add your custom configurations below
 "#;
 
-            if let Ok(_) = fs::write(&config_path, config_content) {
+            if fs::write(&config_path, config_content).is_ok() {
                 println!(
                     "{}",
                     format!("Initialized configuration at {}", config_path).green()
@@ -190,14 +280,306 @@ export PS1="🦇 \w> "
         }
     }
 
-    // function to retrieve an environment variable
+    // retrieves a variable as a string. Like in bash, an array used as a
+    // scalar means its element 0.
     pub fn get_env(&self, key: &str) -> Option<&String> {
-        self.env_vars.get(key)
+        match self.vars.get(key)? {
+            Value::Scalar(value) => Some(value),
+            Value::Indexed(items) => items.get(&0),
+            Value::Assoc(items) => items.get("0"),
+        }
+    }
+
+    pub fn var(&self, key: &str) -> Option<&Value> {
+        self.vars.get(key)
     }
 
-    // function to set an environment variabl
+    /// Looks up `name[subscript]`. Indexed arrays take an arithmetic
+    /// expression, which counts from the end when negative; a scalar is an
+    /// array with one element.
+    pub fn get_element(&mut self, key: &str, subscript: &str) -> Result<Option<String>, String> {
+        let slot = self.slot(key, subscript)?;
+        Ok(slot.and_then(|slot| self.element(key, &slot).cloned()))
+    }
+
+    // resolves the subscript of `name[subscript]`. Subscripts of anything but
+    // an associative array are evaluated, so `${a[i+1]}` works. None for an
+    // index before the start of the array.
+    fn slot(&mut self, key: &str, subscript: &str) -> Result<Option<Slot>, String> {
+        if let Some(Value::Assoc(_)) = self.vars.get(key) {
+            return Ok(Some(Slot::Key(subscript.to_string())));
+        }
+        let n = evaluate(subscript, self)?;
+        let end = match self.vars.get(key) {
+            Some(Value::Indexed(items)) => items.keys().next_back().map_or(0, |last| last + 1),
+            Some(_) => 1,
+            None => 0,
+        };
+        let index = if n < 0 { end as i64 + n } else { n };
+        Ok(usize::try_from(index).ok().map(Slot::Index))
+    }
+
+    fn element(&self, key: &str, slot: &Slot) -> Option<&String> {
+        match (self.vars.get(key)?, slot) {
+            (Value::Scalar(value), Slot::Index(0)) => Some(value),
+            (Value::Indexed(items), Slot::Index(index)) => items.get(index),
+            (Value::Assoc(items), Slot::Key(subscript)) => items.get(subscript),
+            _ => None,
+        }
+    }
+
+    // looks up a parameter for expansion: special parameters like `$?` and
+    // `$1`, then shell variables
+    pub fn get_param(&self, name: &str) -> Option<String> {
+        match name {
+            "?" => Some(self.last_status.to_string()),
+            "#" => Some(self.positional.len().to_string()),
+            "$" => Some(std::process::id().to_string()),
+            "0" => Some(String::from("batcave")),
+            "*" | "@" => Some(self.positional.join(" ")),
+            _ if name.chars().all(|c| c.is_ascii_digit()) => name
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| self.positional.get(n.checked_sub(1)?))
+                .cloned(),
+            _ => self.get_env(name).cloned(),
+        }
+    }
+
+    // the logical current directory: $PWD when it still names the working
+    // directory, so paths through symlinks are kept
+    pub fn current_dir(&self) -> PathBuf {
+        let physical = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        match self.get_env("PWD").map(PathBuf::from) {
+            Some(pwd)
+                if pwd.is_absolute() && pwd.canonicalize().ok() == physical.canonicalize().ok() =>
+            {
+                pwd
+            }
+            _ => physical,
+        }
+    }
+
+    // records a directory change in $OLDPWD and $PWD, for the shell and for
+    // the programs it starts
+    pub fn set_pwd(&mut self, old: &Path, new: &Path) {
+        for (name, dir) in [("OLDPWD", old), ("PWD", new)] {
+            self.set_env(name.to_string(), dir.to_string_lossy().into_owned());
+            self.export(name);
+        }
+        self.sync_dirstack();
+    }
+
+    // sets a shell variable. It is only passed on to child processes if it
+    // was exported before.
     pub fn set_env(&mut self, key: String, value: String) {
-        self.env_vars.insert(key, value);
+        match self.vars.get_mut(&key) {
+            Some(Value::Indexed(items)) => {
+                items.insert(0, value);
+            }
+            Some(Value::Assoc(items)) => {
+                items.insert(String::from("0"), value);
+            }
+            _ => {
+                self.vars.insert(key, Value::Scalar(value));
+            }
+        }
+    }
+
+    // removes a variable, along with its export
+    pub fn unset_env(&mut self, key: &str) {
+        self.vars.remove(key);
+        self.exported.remove(key);
+    }
+
+    pub fn attributes(&self, key: &str) -> Attributes {
+        self.attributes.get(key).copied().unwrap_or_default()
+    }
+
+    pub fn is_readonly(&self, key: &str) -> bool {
+        self.attributes(key).readonly
+    }
+
+    /// Assigns a variable the way a user assignment does: readonly variables
+    /// are refused, integer variables store a number and `-l`/`-u` variables
+    /// change case. With `append` the value is added to the old one, as a
+    /// number for integer variables.
+    pub fn assign_var(&mut self, key: &str, value: String, append: bool) -> Result<(), String> {
+        if self.is_readonly(key) {
+            return Err(format!("{}: readonly variable", key));
+        }
+        let old = self.get_env(key).cloned().unwrap_or_default();
+        let value = self.apply_attributes(key, old, value, append)?;
+        self.set_env(key.to_string(), value);
+        Ok(())
+    }
+
+    /// Assigns `name[subscript]=value`. A variable that doesn't exist yet
+    /// becomes an indexed array, and a scalar becomes its element 0.
+    pub fn set_element(
+        &mut self,
+        key: &str,
+        subscript: &str,
+        value: String,
+        append: bool,
+    ) -> Result<(), String> {
+        if self.is_readonly(key) {
+            return Err(format!("{}: readonly variable", key));
+        }
+        let slot = self
+            .slot(key, subscript)?
+            .ok_or_else(|| format!("{}[{}]: bad array subscript", key, subscript))?;
+        let old = self.element(key, &slot).cloned().unwrap_or_default();
+        let value = self.apply_attributes(key, old, value, append)?;
+
+        let entry = self
+            .vars
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Indexed(BTreeMap::new()));
+        if let Value::Scalar(scalar) = entry {
+            *entry = Value::Indexed(BTreeMap::from([(0, std::mem::take(scalar))]));
+        }
+        match (entry, slot) {
+            (Value::Indexed(items), Slot::Index(index)) => {
+                items.insert(index, value);
+            }
+            (Value::Assoc(items), Slot::Key(subscript)) => {
+                items.insert(subscript, value);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    /// Removes `name[subscript]`. The other elements keep their indices.
+    pub fn unset_element(&mut self, key: &str, subscript: &str) -> Result<(), String> {
+        if self.is_readonly(key) {
+            return Err(format!("{}: cannot unset: readonly variable", key));
+        }
+        let Some(slot) = self.slot(key, subscript)? else {
+            return Ok(());
+        };
+        match (self.vars.get_mut(key), slot) {
+            (Some(Value::Indexed(items)), Slot::Index(index)) => {
+                items.remove(&index);
+            }
+            (Some(Value::Assoc(items)), Slot::Key(subscript)) => {
+                items.remove(&subscript);
+            }
+            (Some(Value::Scalar(_)), Slot::Index(0)) => self.unset_env(key),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Assigns the items of `name=(...)`. An item given as `[key]=value`
+    /// goes to that index or key, the others follow the previous index.
+    /// Associative arrays stay associative and need a key for every item.
+    pub fn assign_array(
+        &mut self,
+        key: &str,
+        items: Vec<(Option<String>, String)>,
+        append: bool,
+    ) -> Result<(), String> {
+        if self.is_readonly(key) {
+            return Err(format!("{}: readonly variable", key));
+        }
+        let mut value = match (self.vars.get(key), append) {
+            (Some(Value::Assoc(old)), true) => Value::Assoc(old.clone()),
+            (Some(Value::Assoc(_)), false) => Value::Assoc(BTreeMap::new()),
+            (Some(Value::Indexed(old)), true) => Value::Indexed(old.clone()),
+            (Some(Value::Scalar(old)), true) => Value::Indexed(BTreeMap::from([(0, old.clone())])),
+            _ => Value::Indexed(BTreeMap::new()),
+        };
+
+        match &mut value {
+            Value::Indexed(array) => {
+                let mut next = array.keys().next_back().map_or(0, |last| last + 1);
+                for (subscript, item) in items {
+                    if let Some(subscript) = subscript {
+                        next = subscript
+                            .trim()
+                            .parse()
+                            .map_err(|_| format!("{}[{}]: bad array subscript", key, subscript))?;
+                    }
+                    let item = self.apply_attributes(key, String::new(), item, false)?;
+                    array.insert(next, item);
+                    next += 1;
+                }
+            }
+            Value::Assoc(map) => {
+                for (subscript, item) in items {
+                    let Some(subscript) = subscript else {
+                        return Err(format!(
+                            "{}: {}: must use subscript when assigning associative array",
+                            key, item
+                        ));
+                    };
+                    let item = self.apply_attributes(key, String::new(), item, false)?;
+                    map.insert(subscript, item);
+                }
+            }
+            Value::Scalar(_) => unreachable!(),
+        }
+        self.vars.insert(key.to_string(), value);
+        Ok(())
+    }
+
+    // applies the integer and case attributes of `key` to an assigned value.
+    // Values of integer variables are arithmetic expressions.
+    fn apply_attributes(
+        &mut self,
+        key: &str,
+        old: String,
+        value: String,
+        append: bool,
+    ) -> Result<String, String> {
+        let attributes = self.attributes(key);
+        let value = if attributes.integer {
+            let value = evaluate(&value, self)?;
+            if append {
+                evaluate(&old, self)?.wrapping_add(value).to_string()
+            } else {
+                value.to_string()
+            }
+        } else if append {
+            old + &value
+        } else {
+            value
+        };
+        Ok(if attributes.lowercase {
+            value.to_lowercase()
+        } else if attributes.uppercase {
+            value.to_uppercase()
+        } else {
+            value
+        })
+    }
+
+    /// Marks a variable to be passed on to child processes. Like in bash it
+    /// may be exported before it has a value.
+    pub fn export(&mut self, key: &str) {
+        self.exported.insert(key.to_string());
+    }
+
+    pub fn is_exported(&self, key: &str) -> bool {
+        self.exported.contains(key)
+    }
+
+    /// The environment for child processes: every exported variable that
+    /// has a value.
+    pub fn exported_vars(&self) -> Vec<(String, String)> {
+        let mut vars: Vec<(String, String)> = self
+            .exported
+            .iter()
+            .filter_map(|key| match self.vars.get(key)? {
+                // arrays can't be passed through the environment
+                Value::Scalar(value) => Some((key.clone(), value.clone())),
+                _ => None,
+            })
+            .collect();
+        vars.sort();
+        vars
     }
 
     // function to add a command alias
@@ -215,11 +597,14 @@ export PS1="🦇 \w> "
         if self.history.len() >= 1000 {
             self.history.pop_front();
         }
-        self.history.push_back(command);
+        let cwd = env::current_dir()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        self.history.push_back(HistoryEntry { command, cwd });
     }
 
     // retrieves a command from history
-    fn _get_history(&self) -> &VecDeque<String> {
+    fn _get_history(&self) -> &VecDeque<HistoryEntry> {
         &self.history
     }
 }
@@ -227,14 +612,70 @@ export PS1="🦇 \w> "
 // The ShellHelper struct is used for rustyline integration,
 // providing auto-completion and other line editing features.
 pub struct ShellHelper {
-    shell: Shell,
+    history: VecDeque<HistoryEntry>,
+    aliases: Vec<String>,
+    variables: Vec<String>,
+    pending: String,
+    rprompt: String,
+    prompt_width: usize,
+    columns: usize,
 }
 
 impl ShellHelper {
     pub fn new(shell: &Shell) -> Self {
-        ShellHelper {
-            shell: shell.clone(),
+        let mut helper = ShellHelper {
+            history: VecDeque::new(),
+            aliases: Vec::new(),
+            variables: Vec::new(),
+            pending: String::new(),
+            rprompt: String::new(),
+            prompt_width: 0,
+            columns: 80,
+        };
+        helper.sync(shell);
+        helper
+    }
+
+    // sets the rendered prompt the next line is read with, so RPROMPT can be
+    // right-aligned on the same row
+    pub fn set_prompt(&mut self, prompt: &str, rprompt: String, columns: usize) {
+        self.prompt_width = display_width(prompt);
+        self.rprompt = rprompt;
+        self.columns = columns;
+    }
+
+    // sets the lines already entered for a command that is being continued,
+    // so each continuation line is validated in context
+    pub fn set_pending(&mut self, pending: &str) {
+        self.pending = pending.to_string();
+    }
+
+    // refreshes the history and the alias and variable names the helper
+    // suggests and completes from, after a command has run
+    pub fn sync(&mut self, shell: &Shell) {
+        self.history.clone_from(&shell.history);
+        self.aliases = shell.aliases.keys().cloned().collect();
+        self.variables = shell.vars.keys().cloned().collect();
+    }
+
+    // finds the most recent history entry starting with the given prefix,
+    // preferring entries that were run in the current directory
+    fn suggest_from_history(&self, prefix: &str) -> Option<&str> {
+        let cwd = env::current_dir()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let candidates = self.history.iter().rev().filter(|entry| {
+            entry.command.len() > prefix.len() && entry.command.starts_with(prefix)
+        });
+
+        let mut fallback = None;
+        for entry in candidates {
+            if entry.cwd == cwd {
+                return Some(&entry.command);
+            }
+            fallback.get_or_insert(entry.command.as_str());
         }
+        fallback
     }
 }
 
@@ -249,7 +690,7 @@ impl Completer for ShellHelper {
         _ctx: &Context<'_>,
     ) -> Result<(usize, Vec<Pair>), ReadlineError> {
         let (start, word) = extract_word(line, pos);
-        let completions = autocomplete(word, &self.shell);
+        let completions = autocomplete(word, &self.aliases, &self.variables);
         Ok((
             start,
             completions
@@ -263,12 +704,110 @@ impl Completer for ShellHelper {
     }
 }
 
-impl Validator for ShellHelper {}
+// Validator rejects input that can never become valid (such as a stray `fi`).
+// Input that is merely incomplete is accepted so the REPL can keep reading
+// with the continuation prompt.
+impl Validator for ShellHelper {
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
+        let input = if self.pending.is_empty() {
+            ctx.input().to_string()
+        } else {
+            format!("{}\n{}", self.pending, ctx.input())
+        };
+
+        match check_input(&input) {
+            InputStatus::Invalid(message) => Ok(ValidationResult::Invalid(Some(format!(
+                "  {}",
+                message.red()
+            )))),
+            InputStatus::Complete | InputStatus::Incomplete => Ok(ValidationResult::Valid(None)),
+        }
+    }
+}
 
 impl Helper for ShellHelper {}
+
 impl Highlighter for ShellHelper {}
+
+/// Text shown after the cursor: either a greyed-out suggestion from history,
+/// which can be accepted, or the right-aligned RPROMPT, which cannot.
+pub struct ShellHint {
+    display: String,
+    completion: Option<String>,
+}
+
+impl Hint for ShellHint {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn completion(&self) -> Option<&str> {
+        self.completion.as_deref()
+    }
+}
+
+// Hinter shows a greyed-out suggestion taken from history, fish-style
 impl Hinter for ShellHelper {
-    type Hint = String;
+    type Hint = ShellHint;
+
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<ShellHint> {
+        if pos < line.len() {
+            return None;
+        }
+
+        if !line.trim().is_empty() {
+            if let Some(command) = self.suggest_from_history(line) {
+                let rest = &command[line.len()..];
+                return Some(ShellHint {
+                    display: rest.bright_black().to_string(),
+                    completion: Some(rest.to_string()),
+                });
+            }
+        }
+
+        if self.rprompt.is_empty() || line.contains('\n') {
+            return None;
+        }
+        let used = self.prompt_width + display_width(line) + display_width(&self.rprompt);
+        // leave the last column free so the terminal doesn't wrap
+        let padding = self.columns.checked_sub(used + 1).filter(|&n| n > 0)?;
+        Some(ShellHint {
+            display: format!("{}{}", " ".repeat(padding), self.rprompt),
+            completion: None,
+        })
+    }
+}
+
+/// Key handler for accepting the current suggestion, either entirely
+/// (Right-arrow / Ctrl-F) or one word at a time (Alt-F).
+pub enum AcceptHint {
+    Full,
+    Word,
+}
+
+impl ConditionalEventHandler for AcceptHint {
+    fn handle(
+        &self,
+        _evt: &Event,
+        _n: RepeatCount,
+        _positive: bool,
+        ctx: &EventContext,
+    ) -> Option<Cmd> {
+        if ctx.pos() < ctx.line().len() {
+            return None;
+        }
+        let hint = ctx.hint_text()?;
+        match self {
+            AcceptHint::Full => Some(Cmd::CompleteHint),
+            AcceptHint::Word => {
+                let start = hint.len() - hint.trim_start().len();
+                let end = hint[start..]
+                    .find(char::is_whitespace)
+                    .map_or(hint.len(), |i| start + i);
+                Some(Cmd::Insert(1, hint[..end].to_string()))
+            }
+        }
+    }
 }
 
 fn extract_word(line: &str, pos: usize) -> (usize, &str) {
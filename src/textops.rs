@@ -0,0 +1,564 @@
+// The textops module holds the builtins that read files: `cat`, `head`,
+// `tail` and `wc`. They read their standard input when no file is given, so
+// they work at either end of a pipeline, and write through the command's
+// output so redirections apply.
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, IsTerminal, Read, Seek, SeekFrom},
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::Duration,
+};
+
+use colored::*;
+
+use crate::{
+    exec::{Io, Output},
+    highlight::{highlight, language_for},
+    utils::failure,
+};
+
+// how often `tail -f` checks the files for new data
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+// opens a file operand, where `-` means standard input
+fn open(name: &str, io: &Io) -> io::Result<Box<dyn Read>> {
+    if name == "-" {
+        io.stdin.reader()
+    } else if Path::new(name).is_dir() {
+        Err(io::Error::other("Is a directory"))
+    } else {
+        Ok(Box::new(File::open(name)?))
+    }
+}
+
+fn write(bytes: &[u8], command: &str, io: &Io) -> Result<(), String> {
+    io.stdout
+        .write_bytes(bytes)
+        .map_err(|e| failure(&format!("{}: write error: {}", command, e)))
+}
+
+/// Runs `cat`: `-n` numbers all lines and `-b` only the non-blank ones.
+/// Source files are syntax highlighted when shown on a terminal, unless
+/// `--plain` is given.
+pub fn cat(args: &[String], io: &Io) -> Result<String, String> {
+    let mut number = false;
+    let mut nonblank = false;
+    let mut plain = false;
+    let mut files = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--number" => number = true,
+            "--number-nonblank" => nonblank = true,
+            "--plain" => plain = true,
+            _ if arg.starts_with('-') && arg.len() > 1 && !arg.starts_with("--") => {
+                for flag in arg[1..].chars() {
+                    match flag {
+                        'n' => number = true,
+                        'b' => nonblank = true,
+                        'p' => plain = true,
+                        'u' => {}
+                        _ => return Err(failure(&format!("cat: invalid option -- '{}'", flag))),
+                    }
+                }
+            }
+            _ if arg.starts_with("--") => {
+                return Err(failure(&format!("cat: unrecognized option '{}'", arg)))
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+    if files.is_empty() {
+        files.push(String::from("-"));
+    }
+
+    let colorize = !plain
+        && matches!(io.stdout, Output::Terminal)
+        && io::stdout().is_terminal()
+        && colored::control::SHOULD_COLORIZE.should_colorize();
+    let mut line_number = 0;
+    let mut errors = Vec::new();
+
+    for name in &files {
+        let mut reader = match open(name, io) {
+            Ok(reader) => reader,
+            Err(e) => {
+                errors.push(failure(&format!("cat: {}: {}", name, e)));
+                continue;
+            }
+        };
+        let language = if colorize {
+            language_for(Path::new(name))
+        } else {
+            None
+        };
+        let Some(language) = language else {
+            let numbering = (number || nonblank).then_some((nonblank, &mut line_number));
+            if let Err(e) = copy(reader, numbering, io)? {
+                errors.push(failure(&format!("cat: {}: {}", name, e)));
+            }
+            continue;
+        };
+
+        // highlighting needs the whole file
+        let mut bytes = Vec::new();
+        if let Err(e) = reader.read_to_end(&mut bytes) {
+            errors.push(failure(&format!("cat: {}: {}", name, e)));
+            continue;
+        }
+        if let Ok(text) = std::str::from_utf8(&bytes) {
+            bytes = highlight(text, language).into_bytes();
+        }
+        if number || nonblank {
+            bytes = number_lines(&bytes, nonblank, &mut line_number);
+        }
+        write(&bytes, "cat", io)?;
+    }
+
+    io.finish(String::new(), errors)
+}
+
+// copies the input to stdout as it arrives, so `cat` keeps up with a pipe
+// that never ends. `numbering` holds the `-b` flag and the line count when
+// lines are numbered. A write error stops cat; a read error is returned for
+// the file being copied.
+fn copy(
+    reader: Box<dyn Read>,
+    mut numbering: Option<(bool, &mut usize)>,
+    io: &Io,
+) -> Result<io::Result<()>, String> {
+    let mut reader = BufReader::new(reader);
+    loop {
+        let mut chunk = Vec::new();
+        let read = match numbering {
+            Some(_) => reader.read_until(b'\n', &mut chunk),
+            None => reader.fill_buf().map(|buffer| {
+                chunk.extend_from_slice(buffer);
+                buffer.len()
+            }),
+        };
+        match read {
+            Ok(0) => return Ok(Ok(())),
+            Ok(n) if numbering.is_none() => reader.consume(n),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Ok(Err(e)),
+        }
+        if let Some((nonblank, line_number)) = &mut numbering {
+            chunk = number_lines(&chunk, *nonblank, line_number);
+        }
+        write(&chunk, "cat", io)?;
+    }
+}
+
+// prefixes lines with their number like `cat -n`; the count carries on
+// across files
+fn number_lines(bytes: &[u8], nonblank: bool, line_number: &mut usize) -> Vec<u8> {
+    let mut numbered = Vec::with_capacity(bytes.len() + bytes.len() / 8);
+    for line in bytes.split_inclusive(|&b| b == b'\n') {
+        if nonblank && line == b"\n" {
+            numbered.extend_from_slice(line);
+            continue;
+        }
+        *line_number += 1;
+        numbered.extend_from_slice(format!("{:>6}\t", line_number).as_bytes());
+        numbered.extend_from_slice(line);
+    }
+    numbered
+}
+
+#[derive(Clone, Copy)]
+enum Count {
+    // the first or last N lines or bytes
+    Lines(usize),
+    Bytes(usize),
+    // head: everything but the last N; tail: everything from line/byte N on
+    LinesExcept(usize),
+    BytesExcept(usize),
+}
+
+struct SliceOptions {
+    count: Count,
+    headers: Option<bool>,
+    follow: bool,
+    files: Vec<String>,
+}
+
+// parses the options shared by head and tail. A leading `-` on the count
+// means "all but" for head, and a `+` means "starting at" for tail.
+fn parse_slice_args(command: &str, args: &[String]) -> Result<SliceOptions, String> {
+    let mut options = SliceOptions {
+        count: Count::Lines(10),
+        headers: None,
+        follow: false,
+        files: Vec::new(),
+    };
+    let except_prefix = if command == "head" { '-' } else { '+' };
+    let parse_count = |value: Option<&str>, bytes: bool| {
+        let value = value.unwrap_or_default();
+        let (except, digits) = match value.strip_prefix(except_prefix) {
+            Some(digits) => (true, digits),
+            None => (false, value.strip_prefix(['-', '+']).unwrap_or(value)),
+        };
+        let n: usize = digits.parse().map_err(|_| {
+            failure(&format!(
+                "{}: invalid number of {}: '{}'",
+                command,
+                if bytes { "bytes" } else { "lines" },
+                value
+            ))
+        })?;
+        Ok::<Count, String>(match (bytes, except) {
+            (false, false) => Count::Lines(n),
+            (true, false) => Count::Bytes(n),
+            (false, true) => Count::LinesExcept(n),
+            (true, true) => Count::BytesExcept(n),
+        })
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" | "--lines" => {
+                options.count = parse_count(args.next().map(String::as_str), false)?
+            }
+            "-c" | "--bytes" => options.count = parse_count(args.next().map(String::as_str), true)?,
+            "-q" | "--quiet" | "--silent" => options.headers = Some(false),
+            "-v" | "--verbose" => options.headers = Some(true),
+            "-f" | "--follow" if command == "tail" => options.follow = true,
+            "-" => options.files.push(arg.clone()),
+            _ if arg.starts_with("--lines=") => {
+                options.count = parse_count(Some(&arg["--lines=".len()..]), false)?
+            }
+            _ if arg.starts_with("--bytes=") => {
+                options.count = parse_count(Some(&arg["--bytes=".len()..]), true)?
+            }
+            _ if arg.starts_with("-n") => options.count = parse_count(Some(&arg[2..]), false)?,
+            _ if arg.starts_with("-c") => options.count = parse_count(Some(&arg[2..]), true)?,
+            // the old `head -5` form
+            _ if arg.len() > 1 && arg[1..].bytes().all(|b| b.is_ascii_digit()) => {
+                options.count = parse_count(Some(&arg[1..]), false)?
+            }
+            _ if arg.starts_with('-') => {
+                return Err(failure(&format!("{}: invalid option '{}'", command, arg)))
+            }
+            _ => options.files.push(arg.clone()),
+        }
+    }
+    if options.files.is_empty() {
+        options.files.push(String::from("-"));
+    }
+    Ok(options)
+}
+
+fn header(name: &str, first: bool) -> String {
+    let name = if name == "-" { "standard input" } else { name };
+    format!("{}==> {} <==\n", if first { "" } else { "\n" }, name)
+}
+
+/// Runs `head`: the first 10 lines of each file, or `-n N` lines / `-c N`
+/// bytes. A negative count prints everything except the last N.
+pub fn head(args: &[String], io: &Io) -> Result<String, String> {
+    let options = parse_slice_args("head", args)?;
+    let headers = options.headers.unwrap_or(options.files.len() > 1);
+    let mut errors = Vec::new();
+
+    for (i, name) in options.files.iter().enumerate() {
+        let reader = match open(name, io) {
+            Ok(reader) => reader,
+            Err(e) => {
+                errors.push(failure(&format!("head: cannot open '{}': {}", name, e)));
+                continue;
+            }
+        };
+        if headers {
+            write(header(name, i == 0).as_bytes(), "head", io)?;
+        }
+        match read_head(reader, options.count) {
+            Ok(bytes) => write(&bytes, "head", io)?,
+            Err(e) => errors.push(failure(&format!("head: {}: {}", name, e))),
+        }
+    }
+
+    io.finish(String::new(), errors)
+}
+
+// reads only as much as needed, so `yes | head` finishes
+fn read_head(reader: Box<dyn Read>, count: Count) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    match count {
+        Count::Lines(n) => {
+            let mut reader = BufReader::new(reader);
+            for _ in 0..n {
+                if reader.read_until(b'\n', &mut bytes)? == 0 {
+                    break;
+                }
+            }
+        }
+        Count::Bytes(n) => {
+            reader.take(n as u64).read_to_end(&mut bytes)?;
+        }
+        Count::LinesExcept(n) => {
+            let mut all = Vec::new();
+            BufReader::new(reader).read_to_end(&mut all)?;
+            let lines: Vec<&[u8]> = all.split_inclusive(|&b| b == b'\n').collect();
+            for line in &lines[..lines.len().saturating_sub(n)] {
+                bytes.extend_from_slice(line);
+            }
+        }
+        Count::BytesExcept(n) => {
+            let mut reader = reader;
+            reader.read_to_end(&mut bytes)?;
+            bytes.truncate(bytes.len().saturating_sub(n));
+        }
+    }
+    Ok(bytes)
+}
+
+/// Runs `tail`: the last 10 lines of each file, or `-n N` lines / `-c N`
+/// bytes; `+N` starts at line or byte N instead. With `-f` it keeps printing
+/// what is appended to the files until interrupted with Ctrl-C.
+pub fn tail(args: &[String], io: &Io) -> Result<String, String> {
+    let options = parse_slice_args("tail", args)?;
+    let headers = options.headers.unwrap_or(options.files.len() > 1);
+    let mut errors = Vec::new();
+    let mut followed = Vec::new();
+
+    for (i, name) in options.files.iter().enumerate() {
+        let mut bytes = Vec::new();
+        if let Err(e) = open(name, io).and_then(|mut reader| reader.read_to_end(&mut bytes)) {
+            errors.push(failure(&format!("tail: cannot open '{}': {}", name, e)));
+            continue;
+        }
+        if headers {
+            write(header(name, i == 0).as_bytes(), "tail", io)?;
+        }
+        write(slice_tail(&bytes, options.count), "tail", io)?;
+        // like GNU tail, following standard input is ignored
+        if options.follow && name != "-" {
+            followed.push((name.clone(), bytes.len() as u64));
+        }
+    }
+
+    if !followed.is_empty() {
+        errors.extend(follow(&mut followed, headers, io));
+    }
+    io.finish(String::new(), errors)
+}
+
+fn slice_tail(bytes: &[u8], count: Count) -> &[u8] {
+    match count {
+        Count::Bytes(n) => &bytes[bytes.len().saturating_sub(n)..],
+        Count::BytesExcept(n) => &bytes[n.saturating_sub(1).min(bytes.len())..],
+        Count::Lines(0) => &[],
+        Count::Lines(n) => {
+            // skip a final newline so it doesn't count as an empty last line
+            let body = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+            let start = body
+                .iter()
+                .enumerate()
+                .rev()
+                .filter(|(_, &b)| b == b'\n')
+                .nth(n - 1)
+                .map_or(0, |(i, _)| i + 1);
+            &bytes[start..]
+        }
+        Count::LinesExcept(n) => {
+            let mut start = 0;
+            for _ in 1..n {
+                match bytes[start..].iter().position(|&b| b == b'\n') {
+                    Some(newline) => start += newline + 1,
+                    None => return &[],
+                }
+            }
+            &bytes[start..]
+        }
+    }
+}
+
+// polls the files for appended data until Ctrl-C, printing a header whenever
+// the output switches to another file
+fn follow(files: &mut [(String, u64)], headers: bool, io: &Io) -> Vec<String> {
+    let mut errors = Vec::new();
+    let mut current = files.len() - 1;
+
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    let handler = on_interrupt as extern "C" fn(libc::c_int);
+    // SAFETY: the handler only stores to an atomic, which is signal safe
+    let previous = unsafe { libc::signal(libc::SIGINT, handler as libc::sighandler_t) };
+
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        for (i, (name, position)) in files.iter_mut().enumerate() {
+            let Ok(len) = fs::metadata(&*name).map(|metadata| metadata.len()) else {
+                continue;
+            };
+            if len < *position {
+                io.stderr.write(
+                    &format!("tail: {}: file truncated", name)
+                        .yellow()
+                        .to_string(),
+                    true,
+                );
+                *position = 0;
+            }
+            if len == *position {
+                continue;
+            }
+
+            let mut bytes = Vec::new();
+            let read = File::open(&*name).and_then(|mut file| {
+                file.seek(SeekFrom::Start(*position))?;
+                file.read_to_end(&mut bytes)
+            });
+            match read {
+                Ok(count) => *position += count as u64,
+                Err(e) => {
+                    errors.push(failure(&format!("tail: {}: {}", name, e)));
+                    continue;
+                }
+            }
+            if headers && current != i {
+                let _ = io.stdout.write_bytes(header(name, false).as_bytes());
+                current = i;
+            }
+            if let Err(e) = io.stdout.write_bytes(&bytes) {
+                errors.push(failure(&format!("tail: write error: {}", e)));
+                INTERRUPTED.store(true, Ordering::SeqCst);
+            }
+        }
+        thread::sleep(FOLLOW_INTERVAL);
+    }
+
+    // SAFETY: puts back the handler that was installed before
+    unsafe { libc::signal(libc::SIGINT, previous) };
+    errors
+}
+
+#[derive(Default, Clone, Copy)]
+struct Counts {
+    lines: usize,
+    words: usize,
+    chars: usize,
+    bytes: usize,
+}
+
+/// Runs `wc`: counts lines (`-l`), words (`-w`), characters (`-m`) and
+/// bytes (`-c`) of each file, with a total when there are several.
+pub fn word_count(args: &[String], io: &Io) -> Result<String, String> {
+    let (mut lines, mut words, mut chars, mut bytes) = (false, false, false, false);
+    let mut files = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "--lines" => lines = true,
+            "--words" => words = true,
+            "--chars" => chars = true,
+            "--bytes" => bytes = true,
+            _ if arg.starts_with('-') && arg.len() > 1 && !arg.starts_with("--") => {
+                for flag in arg[1..].chars() {
+                    match flag {
+                        'l' => lines = true,
+                        'w' => words = true,
+                        'm' => chars = true,
+                        'c' => bytes = true,
+                        _ => return Err(failure(&format!("wc: invalid option -- '{}'", flag))),
+                    }
+                }
+            }
+            _ if arg.starts_with("--") => {
+                return Err(failure(&format!("wc: unrecognized option '{}'", arg)))
+            }
+            _ => files.push(arg.clone()),
+        }
+    }
+    if !(lines || words || chars || bytes) {
+        (lines, words, bytes) = (true, true, true);
+    }
+    let named = !files.is_empty();
+    if !named {
+        files.push(String::from("-"));
+    }
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    let mut total = Counts::default();
+    for name in &files {
+        let mut content = Vec::new();
+        if let Err(e) = open(name, io).and_then(|mut reader| reader.read_to_end(&mut content)) {
+            errors.push(failure(&format!("wc: {}: {}", name, e)));
+            continue;
+        }
+        let text = String::from_utf8_lossy(&content);
+        let counts = Counts {
+            lines: content.iter().filter(|&&b| b == b'\n').count(),
+            words: text.split_whitespace().count(),
+            chars: text.chars().count(),
+            bytes: content.len(),
+        };
+        total.lines += counts.lines;
+        total.words += counts.words;
+        total.chars += counts.chars;
+        total.bytes += counts.bytes;
+        rows.push((counts, named.then(|| name.clone())));
+    }
+    if rows.len() > 1 {
+        rows.push((total, Some(String::from("total"))));
+    }
+
+    let columns = |counts: &Counts| {
+        [
+            (lines, counts.lines),
+            (words, counts.words),
+            (chars, counts.chars),
+            (bytes, counts.bytes),
+        ]
+        .into_iter()
+        .filter(|(shown, _)| *shown)
+        .map(|(_, value)| value)
+        .collect::<Vec<_>>()
+    };
+    // like GNU wc, a single number isn't padded, and input of unknown size
+    // gets a generous width
+    let width = if rows.len() == 1 && columns(&rows[0].0).len() == 1 {
+        1
+    } else {
+        let widest = rows
+            .iter()
+            .flat_map(|(counts, _)| columns(counts))
+            .max()
+            .unwrap_or(0)
+            .to_string()
+            .len();
+        if files.iter().any(|name| name == "-") {
+            widest.max(7)
+        } else {
+            widest
+        }
+    };
+
+    let output = rows
+        .iter()
+        .map(|(counts, name)| {
+            let mut row = columns(counts)
+                .iter()
+                .map(|value| format!("{:>width$}", value, width = width))
+                .collect::<Vec<_>>()
+                .join(" ");
+            if let Some(name) = name {
+                row.push(' ');
+                row.push_str(name);
+            }
+            row
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    io.finish(output, errors)
+}
@@ -0,0 +1,265 @@
+// The trash module implements the freedesktop.org trash used by `rm --trash`
+// and the `trash` builtin. Trashed files live in $XDG_DATA_HOME/Trash/files
+// (usually ~/.local/share/Trash) next to a `.trashinfo` file in Trash/info
+// recording where they came from, so file managers can restore them too.
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use chrono::{Local, NaiveDateTime};
+use colored::*;
+
+use crate::{
+    exec::Io,
+    fileops::{absolute_path, is_protected, move_path},
+    shell::Shell,
+    utils::{failure, success},
+};
+
+struct TrashItem {
+    name: String,
+    original: PathBuf,
+    deleted: Option<NaiveDateTime>,
+}
+
+fn trash_dir(shell: &Shell) -> PathBuf {
+    match shell.get_env("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(data) => Path::new(data).join("Trash"),
+        None => Path::new(shell.get_env("HOME").map_or("/", |home| home.as_str()))
+            .join(".local/share/Trash"),
+    }
+}
+
+/// Moves a file or directory to the trash.
+pub fn move_to_trash(path: &Path, shell: &Shell) -> io::Result<()> {
+    let trash = trash_dir(shell);
+    fs::create_dir_all(trash.join("files"))?;
+    fs::create_dir_all(trash.join("info"))?;
+
+    let original = absolute_path(path);
+    let base = original
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| String::from("unnamed"));
+
+    // reserve a unique name by creating its info file first
+    let mut counter = 1;
+    let (name, info_path, mut info) = loop {
+        let name = if counter == 1 {
+            base.clone()
+        } else {
+            format!("{}.{}", base, counter)
+        };
+        let info_path = trash.join("info").join(format!("{}.trashinfo", name));
+        if !trash.join("files").join(&name).exists() {
+            match OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&info_path)
+            {
+                Ok(file) => break (name, info_path, file),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+        }
+        counter += 1;
+    };
+
+    let written = write!(
+        info,
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(&original.to_string_lossy()),
+        Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    let moved = written.and_then(|_| move_path(path, &trash.join("files").join(&name)));
+    if moved.is_err() {
+        let _ = fs::remove_file(info_path);
+    }
+    moved
+}
+
+/// Runs the `trash` builtin: `trash FILE...`, `trash list`,
+/// `trash restore NAME...` and `trash empty`.
+pub fn handle_trash(args: &[String], shell: &Shell, io: &Io) -> Result<String, String> {
+    match args.first().map(String::as_str) {
+        None => Err(failure(
+            "Usage: trash FILE... | trash list | trash restore NAME... | trash empty",
+        )),
+        Some("list") => Ok(list(shell)),
+        Some("restore") if args.len() > 1 => restore(&args[1..], shell, io),
+        Some("restore") => Err(failure("trash restore: missing name")),
+        Some("empty") => empty(shell),
+        Some(_) => {
+            let mut messages = Vec::new();
+            let mut errors = Vec::new();
+            for path in args {
+                let moved = if is_protected(Path::new(path), shell) {
+                    Err(io::Error::other(
+                        "refusing to trash the root or home directory",
+                    ))
+                } else {
+                    move_to_trash(Path::new(path), shell)
+                };
+                match moved {
+                    Ok(()) => messages.push(success(&format!("Moved to trash: {}", path))),
+                    Err(e) => errors.push(failure(&format!("trash: {}: {}", path, e))),
+                }
+            }
+            io.finish(messages.join("\n"), errors)
+        }
+    }
+}
+
+fn read_items(shell: &Shell) -> Vec<TrashItem> {
+    let Ok(entries) = fs::read_dir(trash_dir(shell).join("info")) else {
+        return Vec::new();
+    };
+
+    let mut items: Vec<TrashItem> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let name = file_name.strip_suffix(".trashinfo")?.to_string();
+            let content = fs::read_to_string(entry.path()).ok()?;
+            let mut original = None;
+            let mut deleted = None;
+            for line in content.lines() {
+                if let Some(path) = line.strip_prefix("Path=") {
+                    original = Some(PathBuf::from(decode_path(path)));
+                } else if let Some(date) = line.strip_prefix("DeletionDate=") {
+                    deleted = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S").ok();
+                }
+            }
+            Some(TrashItem {
+                name,
+                original: original?,
+                deleted,
+            })
+        })
+        .collect();
+    items.sort_by_key(|item| item.deleted);
+    items
+}
+
+fn list(shell: &Shell) -> String {
+    let items = read_items(shell);
+    if items.is_empty() {
+        return format!("{}Trash is empty{}", "[".bright_blue(), "]".bright_blue());
+    }
+
+    items
+        .iter()
+        .map(|item| {
+            let date = item
+                .deleted
+                .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+                .unwrap_or_else(|| String::from("????-??-?? ??:??"));
+            format!(
+                "{}  {}  {}",
+                date.bright_blue(),
+                item.name.green(),
+                item.original.display()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// restores items by their name in the trash or their original path; when a
+// path was trashed several times the most recent copy comes back
+fn restore(names: &[String], shell: &Shell, io: &Io) -> Result<String, String> {
+    let trash = trash_dir(shell);
+    let items = read_items(shell);
+    let mut messages = Vec::new();
+    let mut errors = Vec::new();
+
+    for name in names {
+        let wanted = absolute_path(Path::new(name));
+        let item = items
+            .iter()
+            .find(|item| &item.name == name)
+            .or_else(|| items.iter().rev().find(|item| item.original == wanted));
+
+        let result = match item {
+            None => Err(format!("trash restore: '{}' is not in the trash", name)),
+            Some(item) if item.original.symlink_metadata().is_ok() => Err(format!(
+                "trash restore: '{}' already exists",
+                item.original.display()
+            )),
+            Some(item) => {
+                let parent_created = item.original.parent().map_or(Ok(()), fs::create_dir_all);
+                parent_created
+                    .and_then(|_| move_path(&trash.join("files").join(&item.name), &item.original))
+                    .and_then(|_| {
+                        fs::remove_file(trash.join("info").join(format!("{}.trashinfo", item.name)))
+                    })
+                    .map(|_| format!("Restored: {}", item.original.display()))
+                    .map_err(|e| format!("trash restore: {}: {}", name, e))
+            }
+        };
+
+        match result {
+            Ok(message) => messages.push(success(&message)),
+            Err(message) => errors.push(failure(&message)),
+        }
+    }
+
+    io.finish(messages.join("\n"), errors)
+}
+
+fn empty(shell: &Shell) -> Result<String, String> {
+    let trash = trash_dir(shell);
+    for dir in ["files", "info"] {
+        let Ok(entries) = fs::read_dir(trash.join(dir)) else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let removed = if path.symlink_metadata().is_ok_and(|meta| meta.is_dir()) {
+                fs::remove_dir_all(&path)
+            } else {
+                fs::remove_file(&path)
+            };
+            removed.map_err(|e| failure(&format!("trash empty: {}: {}", path.display(), e)))?;
+        }
+    }
+    Ok(success("Trash emptied"))
+}
+
+// paths in .trashinfo files are URL-encoded
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn decode_path(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
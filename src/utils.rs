@@ -1,6 +1,6 @@
 use std::{
     env, fs,
-    io::{self, Error as IoError},
+    io::{self, Error as IoError, IsTerminal, Write},
     path::Path,
 };
 
@@ -8,8 +8,6 @@ use colored::*;
 use log::LevelFilter;
 use simplelog::{CombinedLogger, Config, WriteLogger};
 
-use crate::shell::Shell;
-
 pub fn print_banner() {
     println!(
         "{}",
@@ -53,75 +51,97 @@ pub fn setup_logging() -> io::Result<()> {
         Config::default(),
         file,
     )])
-    .map_err(|e| IoError::new(io::ErrorKind::Other, e))?;
+    .map_err(IoError::other)?;
 
     Ok(())
 }
 
-// splits command string into tokens
-pub fn tokenize_command(command: &str) -> Vec<String> {
-    let mut tokens = Vec::new();
-    let mut current_token = String::new();
-    let mut in_quotes = false;
-    let mut escape_next = false;
-
-    for c in command.chars() {
-        match (c, in_quotes, escape_next) {
-            ('\\', _, false) => escape_next = true,
-            ('"', _, true) => {
-                current_token.push('"');
-                escape_next = false;
-            }
-            (c, _, true) => {
-                current_token.push('\\');
-                current_token.push(c);
-                escape_next = false;
-            }
-            ('"', _, false) => in_quotes = !in_quotes,
-            (' ', false, false) => {
-                if !current_token.is_empty() {
-                    tokens.push(current_token.clone());
-                    current_token.clear();
+// removes ANSI color sequences, for output that isn't going to a terminal
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // skip a CSI sequence up to and including its final letter
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
                 }
             }
-            (c, _, false) => current_token.push(c),
+        } else {
+            plain.push(c);
         }
     }
+    plain
+}
 
-    if !current_token.is_empty() {
-        tokens.push(current_token);
+// returns the width of the terminal attached to stdout, if there is one
+pub fn terminal_width() -> Option<usize> {
+    if !io::stdout().is_terminal() {
+        return None;
+    }
+    // SAFETY: TIOCGWINSZ only fills in the winsize struct it is given
+    let mut size: libc::winsize = unsafe { std::mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+    if result == 0 && size.ws_col > 0 {
+        Some(size.ws_col as usize)
+    } else {
+        env::var("COLUMNS")
+            .ok()
+            .and_then(|columns| columns.parse().ok())
+            .or(Some(80))
     }
+}
 
-    tokens
+// formats a builtin's confirmation message in green brackets
+pub fn success(message: &str) -> String {
+    format!("{}{}{}", "[".green(), message, "]".green())
 }
 
-// expand env variables in a string
-pub fn expand_env_vars(command: &str, shell: &Shell) -> String {
-    let mut result = command.to_string();
-    while let Some(start) = result.find('$') {
-        if let Some(end) = result[start + 1..].find(|c: char| !c.is_alphanumeric() && c != '_') {
-            let var_name = &result[start + 1..start + 1 + end];
-            if let Some(value) = shell.get_env(var_name) {
-                result.replace_range(start..start + 1 + end, value);
-            }
-        } else {
-            let var_name = &result[start + 1..];
-            if let Some(value) = shell.get_env(var_name) {
-                result.replace_range(start.., value);
-            }
+// formats a builtin's error message in red brackets
+pub fn failure(message: &str) -> String {
+    format!("{}{}{}", "[".red(), message, "]".red())
+}
+
+// formats a byte count like `ls -h`: 1023, 1.0K, 15M, ...
+pub fn human_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut size = bytes as f64;
+    let mut unit = ' ';
+    for next in ['K', 'M', 'G', 'T', 'P', 'E'] {
+        if size < 1024.0 {
             break;
         }
+        size /= 1024.0;
+        unit = next;
+    }
+    if size < 10.0 {
+        format!("{:.1}{}", (size * 10.0).ceil() / 10.0, unit)
+    } else {
+        format!("{}{}", size.ceil() as u64, unit)
     }
-    result
+}
+
+// asks a yes/no question on stderr and reads the answer from stdin
+pub fn confirm(question: &str) -> bool {
+    eprint!("{} ", question);
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).is_ok()
+        && matches!(answer.trim_start().chars().next(), Some('y') | Some('Y'))
 }
 
 // provides autocomplete candidates for auto-completion
-pub fn autocomplete(input: &str, shell: &Shell) -> Vec<String> {
+pub fn autocomplete(input: &str, aliases: &[String], variables: &[String]) -> Vec<String> {
     let mut completions = Vec::new();
 
     // Complete commands
     let commands = vec![
-        "echo", "pwd", "cd", "ls", "mkdir", "rm", "touch", "alias", "export", "env",
+        "echo", "pwd", "cd", "z", "zi", "ls", "mkdir", "rm", "cp", "mv", "trash", "touch", "tree",
+        "du", "find", "alias", "unalias", "export", "env", "unset", "declare", "typeset",
+        "readonly", "set", "let", "test", "command", "config",
     ];
     for cmd in commands {
         if cmd.starts_with(input) {
@@ -130,14 +150,14 @@ pub fn autocomplete(input: &str, shell: &Shell) -> Vec<String> {
     }
 
     // Complete aliases
-    for alias in shell.aliases.keys() {
+    for alias in aliases {
         if alias.starts_with(input) {
             completions.push(alias.to_string());
         }
     }
 
     // Complete environment variables
-    for var in shell.env_vars.keys() {
+    for var in variables {
         if var.starts_with(input) {
             completions.push(format!("${}", var));
         }
@@ -0,0 +1,388 @@
+// The variables module holds the builtins that manage shell variables and
+// aliases beyond plain assignment: `declare`/`typeset` with attributes,
+// `readonly`, `unset`, `unalias` and `set`. The values and attributes live in
+// the Shell, which enforces them on every assignment.
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    exec::{array_items, Io},
+    parser::{is_name, split_subscript},
+    shell::{Shell, Value},
+    utils::failure,
+};
+
+/// Quotes a value the way `declare -p` prints it, so the output can be read
+/// back by the shell.
+pub fn double_quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Quotes a value for `set`, leaving plain words alone.
+pub fn single_quote(value: &str) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-./:,+=@%".contains(c))
+    {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+// every variable name the shell knows: scalars, arrays and names that were
+// only given attributes
+fn all_names(shell: &Shell) -> BTreeSet<&String> {
+    shell.vars.keys().chain(shell.attributes.keys()).collect()
+}
+
+// prints a value the way it would be assigned: arrays as `([key]="value")`
+fn literal(value: &Value, quote: fn(&str) -> String) -> String {
+    match value {
+        Value::Scalar(value) => quote(value),
+        _ => {
+            let items: Vec<String> = value
+                .keys()
+                .into_iter()
+                .zip(value.values())
+                .map(|(key, item)| format!("[{}]={}", key, double_quote(&item)))
+                .collect();
+            format!("({})", items.join(" "))
+        }
+    }
+}
+
+// prints one variable as a `declare` command
+fn describe(name: &str, shell: &Shell) -> String {
+    let attributes = shell.attributes(name);
+    let value = shell.var(name);
+    let mut flags = String::new();
+    for (set, flag) in [
+        (matches!(value, Some(Value::Indexed(_))), 'a'),
+        (matches!(value, Some(Value::Assoc(_))), 'A'),
+        (attributes.integer, 'i'),
+        (attributes.lowercase, 'l'),
+        (attributes.readonly, 'r'),
+        (attributes.uppercase, 'u'),
+        (shell.is_exported(name), 'x'),
+    ] {
+        if set {
+            flags.push(flag);
+        }
+    }
+    if flags.is_empty() {
+        flags.push('-');
+    }
+
+    match value {
+        Some(value) => format!(
+            "declare -{} {}={}",
+            flags,
+            name,
+            literal(value, double_quote)
+        ),
+        None => format!("declare -{} {}", flags, name),
+    }
+}
+
+/// Runs `set`. Without arguments it lists every variable, sorted, in a form
+/// that can be read back; `set -- ARGS` replaces the positional parameters.
+pub fn set(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    match args.first().map(String::as_str) {
+        None => Ok(all_names(shell)
+            .into_iter()
+            .filter_map(|name| {
+                let value = shell.var(name)?;
+                Some(format!("{}={}", name, literal(value, single_quote)))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")),
+        Some("--") => {
+            shell.positional = args[1..].to_vec();
+            Ok(String::new())
+        }
+        Some(arg) if arg.starts_with('-') || arg.starts_with('+') => Err(failure(&format!(
+            "set: {}: shell options are not supported",
+            arg
+        ))),
+        Some(_) => {
+            shell.positional = args.to_vec();
+            Ok(String::new())
+        }
+    }
+}
+
+/// Runs `declare` (also known as `typeset`). `-x` exports, `-r` makes
+/// variables readonly, `-i` keeps them integers, `-l`/`-u` change the case
+/// of assigned values, `-a` makes an indexed array and `-A` an associative
+/// one. `+` instead of `-` takes an attribute away. Without names the
+/// matching variables are listed.
+pub fn declare(args: &[String], shell: &mut Shell, io: &Io) -> Result<String, String> {
+    let mut add = String::new();
+    let mut remove = String::new();
+    let mut print = false;
+    let mut names = Vec::new();
+
+    for arg in args {
+        let (flags, target) = match arg.split_at_checked(1) {
+            Some(("-", flags)) if names.is_empty() && !flags.is_empty() => (flags, &mut add),
+            Some(("+", flags)) if names.is_empty() && !flags.is_empty() => (flags, &mut remove),
+            _ => {
+                names.push(arg);
+                continue;
+            }
+        };
+        for flag in flags.chars() {
+            match flag {
+                'p' => print = true,
+                // there are no function scopes, every variable is global
+                'g' => {}
+                'a' | 'A' | 'i' | 'l' | 'r' | 'u' | 'x' => target.push(flag),
+                _ => {
+                    return Err(failure(&format!(
+                        "declare: -{}: invalid option\nUsage: declare [-aAilrux] [-p] [name[=value] ...]",
+                        flag
+                    )))
+                }
+            }
+        }
+    }
+
+    if add.contains('a') && add.contains('A') {
+        return Err(failure("declare: -a and -A can't be used together"));
+    }
+    if remove.contains('r') {
+        return Err(failure("declare: +r: readonly variables stay readonly"));
+    }
+
+    if names.is_empty() {
+        return Ok(list_declared(&add, shell));
+    }
+    if print {
+        let mut errors = Vec::new();
+        let mut found = Vec::new();
+        for name in names {
+            if all_names(shell).contains(name) {
+                found.push(describe(name, shell));
+            } else {
+                errors.push(failure(&format!("declare: {}: not found", name)));
+            }
+        }
+        return io.finish(found.join("\n"), errors);
+    }
+
+    let errors: Vec<String> = names
+        .into_iter()
+        .filter_map(|arg| declare_one(arg, &add, &remove, shell).err())
+        .map(|message| failure(&format!("declare: {}", message)))
+        .collect();
+    if errors.is_empty() {
+        Ok(String::new())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+// lists the variables that have every attribute in `flags`, or all of them
+fn list_declared(flags: &str, shell: &Shell) -> String {
+    all_names(shell)
+        .into_iter()
+        .filter(|name| {
+            let attributes = shell.attributes(name);
+            flags.chars().all(|flag| match flag {
+                'a' => matches!(shell.var(name), Some(Value::Indexed(_))),
+                'A' => matches!(shell.var(name), Some(Value::Assoc(_))),
+                'i' => attributes.integer,
+                'l' => attributes.lowercase,
+                'r' => attributes.readonly,
+                'u' => attributes.uppercase,
+                'x' => shell.is_exported(name),
+                _ => true,
+            })
+        })
+        .map(|name| describe(name, shell))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// applies the attributes and value of a single `name[=value]` argument
+fn declare_one(arg: &str, add: &str, remove: &str, shell: &mut Shell) -> Result<(), String> {
+    let (name, value) = match arg.split_once('=') {
+        Some((name, value)) => (name, Some(value)),
+        None => (arg, None),
+    };
+    if !is_name(name) {
+        return Err(format!("'{}': not a valid identifier", arg));
+    }
+    if shell.is_readonly(name) && (value.is_some() || !add.is_empty() || !remove.is_empty()) {
+        return Err(format!("{}: readonly variable", name));
+    }
+
+    let mut attributes = shell.attributes(name);
+    for flag in add.chars() {
+        match flag {
+            'i' => attributes.integer = true,
+            'l' => {
+                attributes.lowercase = true;
+                attributes.uppercase = false;
+            }
+            'u' => {
+                attributes.uppercase = true;
+                attributes.lowercase = false;
+            }
+            _ => {}
+        }
+    }
+    for flag in remove.chars() {
+        match flag {
+            'i' => attributes.integer = false,
+            'l' => attributes.lowercase = false,
+            'u' => attributes.uppercase = false,
+            _ => {}
+        }
+    }
+    shell.attributes.insert(name.to_string(), attributes);
+
+    match (shell.var(name), add.contains('a'), add.contains('A')) {
+        (Some(Value::Indexed(_)), _, true) => {
+            return Err(format!(
+                "{}: cannot convert indexed to associative array",
+                name
+            ))
+        }
+        (Some(Value::Assoc(_)), true, _) => {
+            return Err(format!(
+                "{}: cannot convert associative to indexed array",
+                name
+            ))
+        }
+        (None | Some(Value::Scalar(_)), true, _) => {
+            // an existing scalar becomes element 0, like in bash
+            let items = shell.get_env(name).cloned().map(|value| (0, value));
+            let items = Value::Indexed(items.into_iter().collect());
+            shell.vars.insert(name.to_string(), items);
+        }
+        (None | Some(Value::Scalar(_)), _, true) => {
+            let items = shell
+                .get_env(name)
+                .cloned()
+                .map(|value| (String::from("0"), value));
+            let items = Value::Assoc(items.into_iter().collect::<BTreeMap<_, _>>());
+            shell.vars.insert(name.to_string(), items);
+        }
+        _ => {}
+    }
+
+    // `name=(...)` reaches declare unexpanded, so its items are expanded
+    // here the same way as for an assignment
+    match value.and_then(|v| v.strip_prefix('(')?.strip_suffix(')')) {
+        Some(list) => {
+            let items = array_items(list, shell)?;
+            shell.assign_array(name, items, false)?;
+        }
+        None => {
+            if let Some(value) = value {
+                shell.assign_var(name, value.to_string(), false)?;
+            }
+        }
+    }
+
+    if add.contains('x') {
+        shell.export(name);
+    }
+    if remove.contains('x') {
+        shell.exported.remove(name);
+    }
+    if add.contains('r') {
+        attributes.readonly = true;
+        shell.attributes.insert(name.to_string(), attributes);
+    }
+    Ok(())
+}
+
+/// Runs `readonly NAME[=value]...`, which marks variables so they can no
+/// longer be assigned or unset. Without names it lists them.
+pub fn readonly(args: &[String], shell: &mut Shell, io: &Io) -> Result<String, String> {
+    let names: Vec<String> = args.iter().filter(|arg| *arg != "-p").cloned().collect();
+    let mut declare_args = vec![String::from("-r")];
+    declare_args.extend(names);
+    declare(&declare_args, shell, io).map_err(|e| e.replace("declare:", "readonly:"))
+}
+
+/// Runs `unset [-f|-v] NAME...`. Without a flag a name that isn't a variable
+/// is looked up as a function instead. `unset NAME[KEY]` removes a single
+/// array element.
+pub fn unset(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    let (functions, variables, names) = match args.first().map(String::as_str) {
+        Some("-f") => (true, false, &args[1..]),
+        Some("-v") => (false, true, &args[1..]),
+        _ => (true, true, args),
+    };
+
+    let mut errors = Vec::new();
+    for name in names {
+        if let Some((name, subscript)) = split_subscript(name).filter(|_| variables) {
+            if let Err(message) = shell.unset_element(name, subscript) {
+                errors.push(failure(&format!("unset: {}", message)));
+            }
+            continue;
+        }
+        if !is_name(name) {
+            errors.push(failure(&format!(
+                "unset: '{}': not a valid identifier",
+                name
+            )));
+            continue;
+        }
+        let is_variable = all_names(shell).contains(name);
+        if variables && (is_variable || !functions) {
+            if shell.is_readonly(name) {
+                errors.push(failure(&format!(
+                    "unset: {}: cannot unset: readonly variable",
+                    name
+                )));
+                continue;
+            }
+            shell.unset_env(name);
+            shell.attributes.remove(name);
+        } else if functions {
+            shell.functions.remove(name);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(String::new())
+    } else {
+        Err(errors.join("\n"))
+    }
+}
+
+/// Runs `unalias NAME...`, or `unalias -a` to remove every alias.
+pub fn unalias(args: &[String], shell: &mut Shell) -> Result<String, String> {
+    if args.is_empty() {
+        return Err(failure("unalias: usage: unalias [-a] name [name ...]"));
+    }
+    if args.iter().any(|arg| arg == "-a") {
+        shell.aliases.clear();
+        return Ok(String::new());
+    }
+
+    let errors: Vec<String> = args
+        .iter()
+        .filter(|name| shell.aliases.remove(*name).is_none())
+        .map(|name| failure(&format!("unalias: {}: not found", name)))
+        .collect();
+    if errors.is_empty() {
+        Ok(String::new())
+    } else {
+        Err(errors.join("\n"))
+    }
+}